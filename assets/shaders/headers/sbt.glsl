@@ -24,8 +24,22 @@ layout(buffer_reference) buffer PaletteInfo {
     u8vec4 palette[];
 };
 
+struct PaletteMaterialProperties
+{
+    // Emitted radiance relative to the albedo
+    float emission;
+    float roughness;
+    float metalness;
+    float transmission;
+    float ior;
+};
+layout(buffer_reference, buffer_reference_align = 4, scalar) buffer PaletteMaterialInfo {
+    PaletteMaterialProperties materials[];
+};
+
 layout(shaderRecordEXT) buffer Sbt {
     GeometryInfo geometryInfo;
     MaterialInfo materialInfo;
     PaletteInfo paletteInfo;
+    PaletteMaterialInfo paletteMaterialInfo;
 } sbt;
@@ -19,7 +19,7 @@ use rhyolite_bevy::{Allocator, RenderSystems};
 use crate::{
     accel_struct::blas::{build_blas_system, BLAS},
     sbt::SbtIndex,
-    Renderable,
+    Renderable, Visibility,
 };
 use bevy_transform::components::GlobalTransform;
 use rhyolite::accel_struct::build::TLASBuildInfo;
@@ -85,12 +85,16 @@ pub(super) fn tlas_system<M: Component>(
             &BLAS,
             &SbtIndex<M>,
             &GlobalTransform,
+            Option<&Visibility>,
             Option<&mut TLASIndex<M>>,
         ),
-        (Or<(Changed<BLAS>, Changed<GlobalTransform>)>, With<M>),
+        (
+            Or<(Changed<BLAS>, Changed<GlobalTransform>, Changed<Visibility>)>,
+            With<M>,
+        ),
     >,
 ) {
-    for (entity, blas, sbt_index, global_transform, index) in query.iter_mut() {
+    for (entity, blas, sbt_index, global_transform, visibility, index) in query.iter_mut() {
         let Some(blas) = blas.blas.as_ref() else {
             // BLAS isn't ready yet
             continue;
@@ -104,9 +108,14 @@ pub(super) fn tlas_system<M: Component>(
                 .to_cols_array()[0..12],
         );
 
+        // Hidden instances are masked out of every ray.
+        let mask = match visibility {
+            Some(Visibility::Hidden) => 0,
+            _ => u8::MAX,
+        };
         let instance = rhyolite::ash::vk::AccelerationStructureInstanceKHR {
             transform,
-            instance_custom_index_and_mask: vk::Packed24_8::new(0, u8::MAX),
+            instance_custom_index_and_mask: vk::Packed24_8::new(0, mask),
             instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(
                 sbt_index.get_index(),
                 vk::GeometryInstanceFlagsKHR::empty().as_raw() as u8,
@@ -110,6 +110,7 @@ impl Plugin for RenderPlugin {
         })
         .add_plugins(PipelineCachePlugin::default())
         .register_type::<Renderable>()
+        .register_type::<Visibility>()
         .add_systems(PostUpdate, build_blas_system.in_set(RenderSystems::SetUp))
         .init_resource::<BlasStore>()
         .init_asset::<ShaderModule>()
@@ -146,3 +147,14 @@ impl Default for Renderable {
         }
     }
 }
+
+/// Whether rays may hit a [`Renderable`]. Hidden entities keep their BLAS and their
+/// SBT record, so showing them again only updates their TLAS instance.
+/// Entities without this component are visible.
+#[derive(Component, Reflect, Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[reflect(Component)]
+pub enum Visibility {
+    #[default]
+    Visible,
+    Hidden,
+}
@@ -0,0 +1,61 @@
+use glam::{IVec3, UVec3};
+
+/// Axis-aligned box of voxels. `min` is inclusive and `max` is exclusive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Aabb {
+    pub min: IVec3,
+    pub max: IVec3,
+}
+
+impl Aabb {
+    pub const fn new(min: IVec3, max: IVec3) -> Self {
+        Self { min, max }
+    }
+    /// Returns the box containing the `extent` voxels starting at `min`.
+    /// The box is clipped at `i32::MAX`, which can never be contained in a box.
+    pub fn from_extent(min: IVec3, extent: UVec3) -> Self {
+        let max = |min: i32, extent: u32| (min as i64 + extent as i64).min(i32::MAX as i64) as i32;
+        Self {
+            min,
+            max: IVec3::new(
+                max(min.x, extent.x),
+                max(min.y, extent.y),
+                max(min.z, extent.z),
+            ),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpge(self.max).any()
+    }
+    pub fn extent(&self) -> UVec3 {
+        if self.is_empty() {
+            return UVec3::ZERO;
+        }
+        // The extent may not fit into an IVec3.
+        self.max.as_uvec3().wrapping_sub(self.min.as_uvec3())
+    }
+    pub fn contains(&self, coords: IVec3) -> bool {
+        coords.cmpge(self.min).all() && coords.cmplt(self.max).all()
+    }
+    pub fn contains_aabb(&self, other: &Aabb) -> bool {
+        other.min.cmpge(self.min).all() && other.max.cmple(self.max).all()
+    }
+    /// Returns the coordinates of all voxels within the box, with x varying fastest.
+    pub fn iter(&self) -> impl Iterator<Item = IVec3> {
+        let Aabb { min, max } = *self;
+        let (min, max) = if self.is_empty() {
+            (IVec3::ZERO, IVec3::ZERO)
+        } else {
+            (min, max)
+        };
+        (min.z..max.z).flat_map(move |z| {
+            (min.y..max.y).flat_map(move |y| (min.x..max.x).map(move |x| IVec3::new(x, y, z)))
+        })
+    }
+    pub fn intersect(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.max(other.min),
+            max: self.max.min(other.max),
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use glam::UVec3;
+use std::sync::Arc;
+
+use glam::{IVec3, UVec3};
 
 use crate::{tree::TreeMeta, Node, NodeConst, Tree};
 
@@ -11,6 +13,8 @@ where
     last_coords: UVec3,
 }
 
+/// Coordinates are compared as two's complement bit patterns, so that coordinates on
+/// different sides of zero only share the root node.
 #[inline]
 fn lowest_common_ancestor_level(a: UVec3, b: UVec3, mask: UVec3, root_level: u32) -> u32 {
     let diff = a ^ b;
@@ -33,17 +37,32 @@ impl<'a, ROOT: Node> Accessor<'a, ROOT>
 where
     [(); ROOT::LEVEL as usize + 1]: Sized,
 {
+    /// Nodes on the cached path may have been freed or may have never existed.
+    /// Returns the lowest level at or above `level` with a valid cached pointer.
+    #[inline]
+    fn valid_cached_level(&self, mut level: u32) -> u32 {
+        while level < ROOT::LEVEL as u32 && self.ptrs[level as usize] == u32::MAX {
+            level += 1;
+        }
+        level
+    }
+    /// Returns `None` for coordinates outside of [`Tree::extent`].
     #[inline]
-    pub fn get(&mut self, coords: UVec3) -> Option<ROOT::Voxel>
+    pub fn get(&mut self, coords: IVec3) -> Option<ROOT::Voxel>
     where
         ROOT: ~const NodeConst,
     {
+        if !Tree::<ROOT>::in_extent(coords) {
+            return None;
+        }
+        let coords = coords.as_uvec3();
         let lca_level = lowest_common_ancestor_level(
             self.last_coords,
             coords,
             <Tree<ROOT> as TreeMeta<ROOT>>::META_MASK,
             ROOT::LEVEL as u32,
         );
+        let lca_level = self.valid_cached_level(lca_level);
         self.last_coords = coords;
         let result = if lca_level >= ROOT::LEVEL as u32 {
             self.tree.root.get(&self.tree.pool, coords, &mut self.ptrs)
@@ -64,23 +83,53 @@ where
     tree: &'a mut Tree<ROOT>,
     ptrs: [u32; ROOT::LEVEL],
     last_coords: UVec3,
+    /// Origin of the leaf node last marked dirty in the journal of the tree.
+    marked_leaf: Option<IVec3>,
 }
 
 impl<'a, ROOT: Node> AccessorMut<'a, ROOT>
 where
     [(); ROOT::LEVEL as usize + 1]: Sized,
 {
+    /// Nodes on the cached path may have been freed or may have never existed.
+    /// Returns the lowest level at or above `level` with a valid cached pointer.
+    #[inline]
+    fn valid_cached_level(&self, mut level: u32) -> u32 {
+        while level < ROOT::LEVEL as u32 && self.ptrs[level as usize] == u32::MAX {
+            level += 1;
+        }
+        level
+    }
+    /// Nodes shared with a snapshot are only written through their parent, which
+    /// re-points to a copy. Returns the lowest level at or above `level` with a valid
+    /// cached pointer to a node that may be written.
+    #[inline]
+    fn writable_cached_level(&self, mut level: u32) -> u32 {
+        while level < ROOT::LEVEL as u32
+            && (self.ptrs[level as usize] == u32::MAX
+                || self.tree.pool[level as usize].is_shared(self.ptrs[level as usize]))
+        {
+            level += 1;
+        }
+        level
+    }
+    /// Returns `None` for coordinates outside of [`Tree::extent`].
     #[inline]
-    pub fn get(&mut self, coords: UVec3) -> Option<ROOT::Voxel>
+    pub fn get(&mut self, coords: IVec3) -> Option<ROOT::Voxel>
     where
         ROOT: ~const NodeConst,
     {
+        if !Tree::<ROOT>::in_extent(coords) {
+            return None;
+        }
+        let coords = coords.as_uvec3();
         let lca_level = lowest_common_ancestor_level(
             self.last_coords,
             coords,
             <Tree<ROOT> as TreeMeta<ROOT>>::META_MASK,
             ROOT::LEVEL as u32,
         );
+        let lca_level = self.valid_cached_level(lca_level);
         self.last_coords = coords;
         let result = if lca_level >= ROOT::LEVEL as u32 {
             self.tree.root.get(&self.tree.pool, coords, &mut self.ptrs)
@@ -93,27 +142,75 @@ where
         return result;
     }
 
+    /// Mark the leaf node containing `coords` dirty before it is edited, looking it up
+    /// through the cached path.
     #[inline]
-    pub fn set(&mut self, coords: UVec3, value: Option<ROOT::Voxel>)
+    fn mark_leaf(&mut self, coords: IVec3)
     where
         ROOT: ~const NodeConst,
     {
+        let Some(journal) = self.tree.journal.as_ref() else {
+            return;
+        };
+        let origin = coords & !<ROOT::LeafType as Node>::EXTENT_MASK.as_ivec3();
+        if self.marked_leaf == Some(origin) {
+            return;
+        }
+        self.marked_leaf = Some(origin);
+        if journal.contains_leaf(origin) {
+            return;
+        }
+        // Leaves the cached path pointing at the leaf node, if there is one.
+        self.get(coords);
+        let existed = self.ptrs.first().map_or(true, |&ptr| ptr != u32::MAX);
+        self.tree
+            .journal
+            .as_mut()
+            .unwrap()
+            .mark_leaf(origin, existed);
+    }
+
+    /// Coordinates outside of [`Tree::extent`] are ignored.
+    #[inline]
+    pub fn set(&mut self, coords: IVec3, value: Option<ROOT::Voxel>)
+    where
+        ROOT: ~const NodeConst,
+    {
+        if !Tree::<ROOT>::in_extent(coords) {
+            return;
+        }
+        self.mark_leaf(coords);
+        let coords = coords.as_uvec3();
         let lca_level = lowest_common_ancestor_level(
             self.last_coords,
             coords,
             <Tree<ROOT> as TreeMeta<ROOT>>::META_MASK,
             ROOT::LEVEL as u32,
         );
+        let lca_level = self.writable_cached_level(lca_level);
         self.last_coords = coords;
         if lca_level >= ROOT::LEVEL as u32 {
-            self.tree
-                .root
-                .set(&mut self.tree.pool, coords, value, &mut self.ptrs);
+            Arc::make_mut(&mut self.tree.root).set(
+                &mut self.tree.pool,
+                coords,
+                value,
+                &mut self.ptrs,
+            );
         } else {
             let meta = &<Tree<ROOT> as TreeMeta<ROOT>>::METAS[lca_level as usize];
             let new_coords = coords & meta.extent_mask;
             let ptr = self.ptrs[lca_level as usize];
             (meta.setter)(&mut self.tree.pool, new_coords, ptr, value, &mut self.ptrs);
+            if value.is_none() && (meta.is_empty)(&self.tree.pool, ptr) {
+                // The cached node became empty. Clear again from the root so that
+                // the parent nodes get a chance to free it.
+                Arc::make_mut(&mut self.tree.root).set(
+                    &mut self.tree.pool,
+                    coords,
+                    value,
+                    &mut self.ptrs,
+                );
+            }
         }
     }
 }
@@ -125,22 +222,23 @@ where
     pub fn accessor(&self) -> Accessor<ROOT> {
         Accessor {
             tree: self,
-            ptrs: [0; ROOT::LEVEL],
+            ptrs: [u32::MAX; ROOT::LEVEL],
             last_coords: UVec3::new(u32::MAX, u32::MAX, u32::MAX),
         }
     }
     pub fn accessor_mut(&mut self) -> AccessorMut<ROOT> {
         AccessorMut {
             tree: self,
-            ptrs: [0; ROOT::LEVEL],
+            ptrs: [u32::MAX; ROOT::LEVEL],
             last_coords: UVec3::new(u32::MAX, u32::MAX, u32::MAX),
+            marked_leaf: None,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use glam::UVec3;
+    use glam::{IVec3, UVec3};
 
     use super::lowest_common_ancestor_level;
     use crate::{hierarchy, tree::TreeMeta, Node, Tree};
@@ -169,6 +267,91 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_anisotropic() {
+        // 4x2x4 leafs, 64x8x64 intermediate nodes and a 256x16x256 top node.
+        type MyTreeRoot = hierarchy!((2, 1, 2), (4, 2, 4), (2, 1, 2));
+        type MyTree = Tree<MyTreeRoot>;
+        const MASK: UVec3 = MyTree::META_MASK;
+        assert_eq!(
+            MASK,
+            UVec3 {
+                x: 0b10100010,
+                y: 0b1101,
+                z: 0b10100010
+            }
+        );
+        let root_level = MyTreeRoot::LEVEL as u32;
+        let lca = |b: UVec3| lowest_common_ancestor_level(UVec3::ZERO, b, MASK, root_level);
+        assert_eq!(lca(UVec3::new(0, 1, 0)), 0);
+        assert_eq!(lca(UVec3::new(3, 0, 3)), 0);
+        assert_eq!(lca(UVec3::new(0, 2, 0)), 1);
+        assert_eq!(lca(UVec3::new(0, 3, 0)), 1);
+        assert_eq!(lca(UVec3::new(63, 7, 63)), 1);
+        assert_eq!(lca(UVec3::new(0, 8, 0)), 2);
+        assert_eq!(lca(UVec3::new(64, 0, 0)), 2);
+        assert_eq!(lca(UVec3::new(255, 15, 255)), 2);
+    }
+
+    #[test]
+    fn test_anisotropic_lca_matches_extents() {
+        use rand::prelude::*;
+        let mut rng = rand::thread_rng();
+
+        type MyTreeRoot = hierarchy!((1, 3, 1), (2, 1, 2), (3, 2, 3));
+        type MyTree = Tree<MyTreeRoot>;
+        let metas = &<MyTree as TreeMeta<_>>::METAS;
+        let top = metas[MyTreeRoot::LEVEL].extent_log2;
+        for _ in 0..1000 {
+            let mut gen = || {
+                UVec3::new(
+                    rng.gen_range(0..1 << top.x),
+                    rng.gen_range(0..1 << top.y),
+                    rng.gen_range(0..1 << top.z),
+                )
+            };
+            let (a, b) = (gen(), gen());
+            // The lowest level whose node covers both coordinates.
+            let expected = metas
+                .iter()
+                .position(|meta| a >> meta.extent_log2 == b >> meta.extent_log2)
+                .unwrap() as u32;
+            assert_eq!(
+                lowest_common_ancestor_level(a, b, MyTree::META_MASK, MyTreeRoot::LEVEL as u32),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_anisotropic_accessor() {
+        use rand::prelude::*;
+        let mut rng = rand::thread_rng();
+
+        type MyTree = Tree<hierarchy!(#, (3, 1, 3), (3, 1, 3); u8)>;
+        let mut tree = MyTree::new();
+        let mut accessor = tree.accessor_mut();
+
+        let mut set_locations: Vec<(IVec3, u8)> = Vec::with_capacity(200);
+        for _i in 0..200 {
+            let location = IVec3::new(
+                rng.gen_range(-100..100),
+                rng.gen_range(-10..10),
+                rng.gen_range(-100..100),
+            );
+            let value: u8 = rng.gen();
+            accessor.set(location, Some(value));
+            set_locations.retain(|(l, _)| *l != location);
+            set_locations.push((location, value));
+        }
+        for (location, value) in set_locations.choose_multiple(&mut rng, 200) {
+            assert_eq!(accessor.get(*location), Some(*value));
+        }
+        for (location, value) in set_locations.iter() {
+            assert_eq!(tree.get_value(*location), Some(*value));
+        }
+    }
+
     #[test]
     fn test_accessor() {
         use rand::prelude::*;
@@ -177,12 +360,12 @@ mod tests {
         type MyTree = Tree<hierarchy!(2, 4, 2)>;
         let mut tree = MyTree::new();
 
-        let mut set_locations: Vec<UVec3> = Vec::with_capacity(100);
+        let mut set_locations: Vec<IVec3> = Vec::with_capacity(100);
         for _i in 0..100 {
             let x: u8 = rng.gen();
             let y: u8 = rng.gen();
             let z: u8 = rng.gen();
-            let location = UVec3::new(x as u32, y as u32, z as u32);
+            let location = IVec3::new(x as i32, y as i32, z as i32);
             set_locations.push(location);
             tree.set_value(location, Some(true));
         }
@@ -193,4 +376,82 @@ mod tests {
             assert_eq!(result, Some(true));
         }
     }
+
+    #[test]
+    fn test_accessor_values() {
+        use rand::prelude::*;
+        let mut rng = rand::thread_rng();
+
+        type MyTree = Tree<hierarchy!(2, 4, 2; u8)>;
+        let mut tree = MyTree::new();
+
+        let mut set_locations: Vec<(IVec3, u8)> = Vec::with_capacity(100);
+        for _i in 0..100 {
+            let x: u8 = rng.gen();
+            let y: u8 = rng.gen();
+            let z: u8 = rng.gen();
+            let value: u8 = rng.gen();
+            let location = IVec3::new(x as i32, y as i32, z as i32);
+            tree.set_value(location, Some(value));
+            set_locations.retain(|(l, _)| *l != location);
+            set_locations.push((location, value));
+        }
+
+        let mut accessor = tree.accessor();
+        for (location, value) in set_locations.choose_multiple(&mut rng, 100) {
+            assert_eq!(accessor.get(*location), Some(*value));
+        }
+    }
+
+    #[test]
+    fn test_accessor_clear() {
+        type MyTree = Tree<hierarchy!(2, 4, 2)>;
+        let mut tree = MyTree::new();
+        let mut accessor = tree.accessor_mut();
+        for x in 0..8_i32 {
+            accessor.set(IVec3::new(x, 0, 0), Some(true));
+        }
+        for x in 0..8_i32 {
+            accessor.set(IVec3::new(x, 0, 0), None);
+            assert_eq!(accessor.get(IVec3::new(x, 0, 0)), None);
+        }
+        accessor.set(IVec3::new(1, 0, 0), Some(true));
+        assert_eq!(accessor.get(IVec3::new(1, 0, 0)), Some(true));
+        assert_eq!(accessor.get(IVec3::new(2, 0, 0)), None);
+        assert_eq!(tree.pool[0].count(), 1);
+        assert_eq!(tree.pool[1].count(), 1);
+    }
+
+    #[test]
+    fn test_accessor_negative_coords() {
+        type MyTree = Tree<hierarchy!(#, 2, 2; u8)>;
+        let mut tree = MyTree::new();
+        let mut accessor = tree.accessor_mut();
+        for x in -8..8 {
+            accessor.set(IVec3::new(x, -x, 1 - x), Some(x as u8));
+        }
+        for x in -8..8 {
+            assert_eq!(accessor.get(IVec3::new(x, -x, 1 - x)), Some(x as u8));
+            assert_eq!(accessor.get(IVec3::new(x, x, x)), None);
+        }
+        for x in (-8..8).rev() {
+            accessor.set(IVec3::new(x, -x, 1 - x), None);
+        }
+        assert!(tree.iter().next().is_none());
+        assert_eq!(tree.pool[0].count(), 0);
+        assert_eq!(tree.pool[1].count(), 0);
+
+        // -1 and 0 differ in every bit, so the only common ancestor is the root.
+        let mask = <MyTree as TreeMeta<_>>::META_MASK;
+        let root_level = <hierarchy!(#, 2, 2; u8)>::LEVEL as u32;
+        assert_eq!(
+            lowest_common_ancestor_level(
+                IVec3::splat(-1).as_uvec3(),
+                UVec3::ZERO,
+                mask,
+                root_level
+            ),
+            root_level
+        );
+    }
 }
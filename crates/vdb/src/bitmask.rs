@@ -61,6 +61,29 @@ where
         }
     }
 
+    /// Set `len` consecutive bits starting from `start` to `val`.
+    pub fn set_range(&mut self, start: usize, len: usize, val: bool) {
+        const NUM_BITS: usize = usize::BITS as usize;
+        let end = start + len;
+        let mut index = start;
+        while index < end {
+            let i = index / NUM_BITS;
+            let j = index - i * NUM_BITS;
+            let count = (NUM_BITS - j).min(end - index);
+            let mask = if count == NUM_BITS {
+                usize::MAX
+            } else {
+                ((1_usize << count) - 1) << j
+            };
+            if val {
+                self.data[i] |= mask;
+            } else {
+                self.data[i] &= !mask;
+            }
+            index += count;
+        }
+    }
+
     pub fn iter_set_bits(&self) -> SetBitIterator<SIZE> {
         SetBitIterator {
             bitmask: self,
@@ -73,9 +96,34 @@ where
         self.data.iter().all(|&a| a == 0)
     }
 
+    pub fn is_full(&self) -> bool {
+        self.data.iter().all(|&a| a == usize::MAX)
+    }
+
     pub fn count_ones(&self) -> usize {
         self.data.iter().map(|a| a.count_ones() as usize).sum()
     }
+
+    /// Set all bits that were set on `other`.
+    pub fn union_with(&mut self, other: &Self) {
+        for (a, b) in self.data.iter_mut().zip(other.data.iter()) {
+            *a |= *b;
+        }
+    }
+
+    /// Clear all bits that were not set on `other`.
+    pub fn intersect_with(&mut self, other: &Self) {
+        for (a, b) in self.data.iter_mut().zip(other.data.iter()) {
+            *a &= *b;
+        }
+    }
+
+    /// Clear all bits that were set on `other`.
+    pub fn subtract(&mut self, other: &Self) {
+        for (a, b) in self.data.iter_mut().zip(other.data.iter()) {
+            *a &= !*b;
+        }
+    }
 }
 
 /// ```
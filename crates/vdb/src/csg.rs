@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use crate::{Node, Tree};
+
+/// Boolean operations between two trees with the same hierarchy.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CsgOp {
+    /// Voxels occupied in either tree.
+    Union,
+    /// Voxels occupied in both trees.
+    Intersection,
+    /// Voxels occupied in the first tree but not the second.
+    Difference,
+}
+
+/// Constructive solid geometry.
+///
+/// These walk both trees in lockstep. Subtrees missing from one of the trees are
+/// copied or skipped wholesale, and leaf nodes are combined with bitmask operations.
+/// When both trees contain a voxel, the value from `self` is kept.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    pub fn csg(&mut self, other: &Tree<ROOT>, op: CsgOp) {
+        self.mark_csg(other, op);
+        Arc::make_mut(&mut self.root).csg(&mut self.pool, &other.root, &other.pool, op);
+    }
+
+    /// Add all voxels of `other` into the tree.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Tree};
+    /// use glam::IVec3;
+    /// let mut a = Tree::<hierarchy!(3, 2; u8)>::new();
+    /// a.set_value(IVec3::new(1, 1, 1), Some(1));
+    /// let mut b = Tree::<hierarchy!(3, 2; u8)>::new();
+    /// b.set_value(IVec3::new(1, 1, 1), Some(2));
+    /// b.set_value(IVec3::new(20, 1, 1), Some(2));
+    /// a.union(&b);
+    /// assert_eq!(a.get_value(IVec3::new(1, 1, 1)), Some(1));
+    /// assert_eq!(a.get_value(IVec3::new(20, 1, 1)), Some(2));
+    /// ```
+    pub fn union(&mut self, other: &Tree<ROOT>) {
+        self.csg(other, CsgOp::Union)
+    }
+
+    /// Remove all voxels not occupied in `other`.
+    pub fn intersect(&mut self, other: &Tree<ROOT>) {
+        self.csg(other, CsgOp::Intersection)
+    }
+
+    /// Remove all voxels occupied in `other`.
+    pub fn difference(&mut self, other: &Tree<ROOT>) {
+        self.csg(other, CsgOp::Difference)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, UVec3};
+    use rand::prelude::*;
+
+    use crate::{hierarchy, Aabb, CsgOp, Tree};
+
+    type MyTree = Tree<hierarchy!(#, 2, 2; u8)>;
+
+    fn random_tree(rng: &mut impl Rng, value: u8) -> MyTree {
+        let mut tree = MyTree::new();
+        for _ in 0..500 {
+            let location = IVec3::new(
+                rng.gen_range(0..48),
+                rng.gen_range(0..48),
+                rng.gen_range(0..48),
+            );
+            tree.set_value(location, Some(value));
+        }
+        let min = IVec3::new(
+            rng.gen_range(0..32),
+            rng.gen_range(0..32),
+            rng.gen_range(0..32),
+        );
+        tree.fill(Aabb::from_extent(min, UVec3::splat(16)), value);
+        tree
+    }
+
+    #[test]
+    fn test_csg() {
+        let mut rng = rand::thread_rng();
+        for op in [CsgOp::Union, CsgOp::Intersection, CsgOp::Difference] {
+            let a = random_tree(&mut rng, 1);
+            let b = random_tree(&mut rng, 2);
+            let mut result = a.extract(Aabb::new(IVec3::ZERO, IVec3::splat(64)));
+            result.csg(&b, op);
+            for x in 0..64 {
+                for y in 0..64 {
+                    for z in 0..64 {
+                        let coords = IVec3::new(x, y, z);
+                        let (a, b) = (a.get_value(coords), b.get_value(coords));
+                        let expected = match op {
+                            CsgOp::Union => a.or(b),
+                            CsgOp::Intersection => a.filter(|_| b.is_some()),
+                            CsgOp::Difference => a.filter(|_| b.is_none()),
+                        };
+                        assert_eq!(result.get_value(coords), expected);
+                    }
+                }
+            }
+        }
+    }
+}
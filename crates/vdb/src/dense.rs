@@ -0,0 +1,261 @@
+use std::sync::Arc;
+
+use glam::{IVec3, UVec3};
+
+use crate::{Aabb, IsLeaf, Node, NodeConst, Tree, VisitedNode};
+
+/// Strides of a dense array of size `extent` with x varying fastest.
+pub(crate) fn dense_strides(extent: UVec3) -> [usize; 3] {
+    [1, extent.x as usize, extent.x as usize * extent.y as usize]
+}
+
+/// Returns the index of the last element of a strided array, or None if it is empty.
+fn last_index(extent: UVec3, strides: [usize; 3]) -> Option<usize> {
+    if extent.cmpeq(UVec3::ZERO).any() {
+        return None;
+    }
+    Some(
+        (extent.x - 1) as usize * strides[0]
+            + (extent.y - 1) as usize * strides[1]
+            + (extent.z - 1) as usize * strides[2],
+    )
+}
+
+/// Conversion from and to dense arrays.
+///
+/// Dense arrays are laid out with x varying fastest, so that the voxel at `coords` relative
+/// to the first voxel is located at `x + extent.x * (y + extent.y * z)`. The strided
+/// variants accept any layout given the distance between neighboring elements along each axis.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Build a tree from the voxels of a dense array of size `extent` located at `offset`.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Aabb, Tree};
+    /// use glam::{IVec3, UVec3};
+    /// let data: Vec<Option<u8>> = (0..27).map(|i| (i % 2 == 0).then_some(i)).collect();
+    /// let tree = Tree::<hierarchy!(#, 2, 2; u8)>::from_dense(&data, UVec3::splat(3), IVec3::splat(-1));
+    /// assert_eq!(tree.get_value(IVec3::new(-1, -1, -1)), Some(0));
+    /// assert_eq!(tree.get_value(IVec3::new(0, -1, -1)), None);
+    /// assert_eq!(tree.get_value(IVec3::new(1, 1, 1)), Some(26));
+    /// assert_eq!(tree.to_dense(Aabb::new(IVec3::splat(-1), IVec3::splat(2))), data);
+    /// ```
+    pub fn from_dense(data: &[Option<ROOT::Voxel>], extent: UVec3, offset: IVec3) -> Self
+    where
+        ROOT: ~const NodeConst,
+    {
+        Self::from_dense_strided(data, extent, dense_strides(extent), offset)
+    }
+
+    /// Build a tree from the voxels of a dense array of size `extent` located at `offset`.
+    /// The voxel at `coords` relative to `offset` is located at the dot product of `coords`
+    /// and `strides`.
+    ///
+    /// The array is converted a leaf node at a time. Blocks filled with a single value
+    /// become tiles, and empty blocks are skipped.
+    pub fn from_dense_strided(
+        data: &[Option<ROOT::Voxel>],
+        extent: UVec3,
+        strides: [usize; 3],
+        offset: IVec3,
+    ) -> Self
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut tree = Self::new();
+        let Some(last) = last_index(extent, strides) else {
+            return tree;
+        };
+        assert!(last < data.len(), "The dense array is too small");
+        let region = Aabb::from_extent(offset, extent).intersect(&Self::extent());
+        if region.is_empty() {
+            return tree;
+        }
+        let index_of = |coords: IVec3| {
+            let local = coords.wrapping_sub(offset).as_uvec3();
+            local.x as usize * strides[0]
+                + local.y as usize * strides[1]
+                + local.z as usize * strides[2]
+        };
+
+        let leaf_extent = <ROOT::LeafType as Node>::EXTENT;
+        let leaf_extent_log2 = <ROOT::LeafType as Node>::EXTENT_LOG2.as_ivec3();
+        let first = region.min >> leaf_extent_log2;
+        let last = (region.max - IVec3::ONE) >> leaf_extent_log2;
+        for x in first.x..=last.x {
+            for y in first.y..=last.y {
+                for z in first.z..=last.z {
+                    let leaf_aabb =
+                        Aabb::from_extent(IVec3::new(x, y, z) << leaf_extent_log2, leaf_extent);
+                    let clipped = leaf_aabb.intersect(&region);
+                    let first_value = data[index_of(clipped.min)];
+                    let mut occupied = false;
+                    let mut uniform = clipped == leaf_aabb;
+                    for x in clipped.min.x..clipped.max.x {
+                        for y in clipped.min.y..clipped.max.y {
+                            for z in clipped.min.z..clipped.max.z {
+                                let value = data[index_of(IVec3::new(x, y, z))];
+                                occupied |= value.is_some();
+                                uniform &= value == first_value;
+                            }
+                        }
+                    }
+                    if uniform {
+                        if let Some(value) = first_value {
+                            tree.fill(leaf_aabb, value);
+                        }
+                        continue;
+                    }
+                    if !occupied {
+                        continue;
+                    }
+                    let origin = leaf_aabb.min;
+                    let leaf = unsafe {
+                        &mut *Arc::make_mut(&mut tree.root)
+                            .touch_leaf(&mut tree.pool, origin.as_uvec3())
+                    };
+                    leaf.write_voxels(|local| {
+                        let coords = origin + local.as_ivec3();
+                        if clipped.contains(coords) {
+                            data[index_of(coords)]
+                        } else {
+                            None
+                        }
+                    });
+                }
+            }
+        }
+        tree
+    }
+
+    /// Returns the voxels within `aabb` as a dense array.
+    pub fn to_dense(&self, aabb: Aabb) -> Vec<Option<ROOT::Voxel>> {
+        let extent = aabb.extent();
+        let len = extent.x as usize * extent.y as usize * extent.z as usize;
+        let mut data = vec![None; len];
+        self.to_dense_strided(aabb, &mut data, dense_strides(extent));
+        data
+    }
+
+    /// Write the voxels within `aabb` into a dense array. The voxel at `coords` relative to
+    /// `aabb.min` is written at the dot product of `coords` and `strides`.
+    ///
+    /// Tiles are written directly, and leaf nodes are converted one at a time.
+    pub fn to_dense_strided(
+        &self,
+        aabb: Aabb,
+        data: &mut [Option<ROOT::Voxel>],
+        strides: [usize; 3],
+    ) {
+        let Some(last) = last_index(aabb.extent(), strides) else {
+            return;
+        };
+        assert!(last < data.len(), "The dense array is too small");
+        let index_of = |coords: IVec3| {
+            let local = coords.wrapping_sub(aabb.min).as_uvec3();
+            local.x as usize * strides[0]
+                + local.y as usize * strides[1]
+                + local.z as usize * strides[2]
+        };
+        for x in aabb.min.x..aabb.max.x {
+            for y in aabb.min.y..aabb.max.y {
+                for z in aabb.min.z..aabb.max.z {
+                    data[index_of(IVec3::new(x, y, z))] = None;
+                }
+            }
+        }
+
+        let leaf_extent = <ROOT::LeafType as Node>::EXTENT;
+        let region = aabb.intersect(&Self::extent());
+        self.root
+            .visit(&self.pool, IVec3::ZERO, &region, &mut |node| match node {
+                VisitedNode::Tile(tile, value) => {
+                    let tile = tile.intersect(&region);
+                    for x in tile.min.x..tile.max.x {
+                        for y in tile.min.y..tile.max.y {
+                            for z in tile.min.z..tile.max.z {
+                                data[index_of(IVec3::new(x, y, z))] = Some(value);
+                            }
+                        }
+                    }
+                }
+                VisitedNode::Leaf(origin, leaf) => {
+                    let clipped = Aabb::from_extent(origin, leaf_extent).intersect(&region);
+                    for x in clipped.min.x..clipped.max.x {
+                        for y in clipped.min.y..clipped.max.y {
+                            for z in clipped.min.z..clipped.max.z {
+                                let coords = IVec3::new(x, y, z);
+                                let local = (coords - origin).as_uvec3();
+                                data[index_of(coords)] = leaf.get(&[], local, &mut []);
+                            }
+                        }
+                    }
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, UVec3};
+    use rand::prelude::*;
+
+    use crate::{hierarchy, Aabb, Tree};
+
+    type MyTree = Tree<hierarchy!(#, 2, 2; u8)>;
+
+    #[test]
+    fn test_dense_roundtrip() {
+        let mut rng = rand::thread_rng();
+        let extent = UVec3::new(13, 20, 9);
+        let offset = IVec3::new(-7, 2, -4);
+        let mut data: Vec<Option<u8>> = (0..extent.x * extent.y * extent.z)
+            .map(|_| rng.gen::<bool>().then(|| rng.gen_range(1..4)))
+            .collect();
+        // A uniform block covering whole leaf nodes.
+        for z in 0..8 {
+            for y in 10..18 {
+                for x in 3..11 {
+                    data[(x + extent.x * (y + extent.y * z)) as usize] = Some(7);
+                }
+            }
+        }
+        let tree = MyTree::from_dense(&data, extent, offset);
+        for z in 0..extent.z {
+            for y in 0..extent.y {
+                for x in 0..extent.x {
+                    let coords = UVec3::new(x, y, z);
+                    assert_eq!(
+                        tree.get_value(offset + coords.as_ivec3()),
+                        data[(x + extent.x * (y + extent.y * z)) as usize]
+                    );
+                }
+            }
+        }
+        assert_eq!(tree.get_value(offset - IVec3::ONE), None);
+        assert_eq!(tree.get_value(offset + extent.as_ivec3()), None);
+        // The 4x4x4 blocks at (-4..4, 12..20, -4..4) are tiles.
+        assert_eq!(tree.get_value(IVec3::new(-4, 12, -4)), Some(7));
+        let num_leaves = tree.iter_leaf().count();
+        assert!(num_leaves < 4 * 6 * 3);
+
+        let aabb = Aabb::from_extent(offset, extent);
+        assert_eq!(tree.to_dense(aabb), data);
+
+        // A larger box in a z-fastest layout.
+        let aabb = Aabb::new(offset - IVec3::ONE, offset + extent.as_ivec3() + IVec3::ONE);
+        let big_extent = aabb.extent();
+        let strides = [
+            (big_extent.y * big_extent.z) as usize,
+            big_extent.z as usize,
+            1,
+        ];
+        let mut dense = vec![Some(100); (big_extent.x * big_extent.y * big_extent.z) as usize];
+        tree.to_dense_strided(aabb, &mut dense, strides);
+        assert_eq!(dense[0], None);
+        let read = MyTree::from_dense_strided(&dense, big_extent, strides, aabb.min);
+        assert_eq!(read.to_dense(aabb), tree.to_dense(aabb));
+    }
+}
@@ -0,0 +1,435 @@
+use std::sync::Arc;
+
+use fxhash::FxHashMap;
+use glam::{IVec3, UVec3};
+
+use crate::{
+    morphology::{face_masks, for_each_boundary_block, shift_down, shift_up, stride_log2},
+    Aabb, Connectivity, IsLeaf, Node, NodeConst, Tree, VisitedNode,
+};
+
+/// Content of a leaf-sized block of voxels.
+enum Block<'a, L, V> {
+    /// A leaf node and its occupancy.
+    Leaf(&'a L, Vec<u64>),
+    /// A tile covering the block.
+    Tile(Aabb, V),
+    Empty,
+}
+
+/// Voxels reached by a flood fill.
+struct Region<V> {
+    /// Reached voxels of the leaf nodes, by the origin of the leaf node.
+    leaves: FxHashMap<IVec3, Vec<u64>>,
+    /// Reached tiles, by their first voxel.
+    tiles: FxHashMap<IVec3, (Aabb, V)>,
+}
+
+impl<V> Default for Region<V> {
+    fn default() -> Self {
+        Self {
+            leaves: FxHashMap::default(),
+            tiles: FxHashMap::default(),
+        }
+    }
+}
+
+/// Connectivity analysis.
+///
+/// The flood fill spreads a mask of reached voxels within each leaf node a word at a
+/// time, and carries the bits on the faces of the leaf node over into its neighbors.
+/// A tile is reached as a whole, and spreads out from the leaf-sized blocks on its
+/// boundary only.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Returns a new tree with the occupied voxels connected to `seed`, keeping their
+    /// values and coordinates. The tree is empty if `seed` is not occupied.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Connectivity, Tree};
+    /// use glam::IVec3;
+    /// let mut tree = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+    /// tree.set_value(IVec3::new(0, 0, 0), Some(1));
+    /// tree.set_value(IVec3::new(1, 1, 0), Some(2));
+    /// tree.set_value(IVec3::new(5, 0, 0), Some(3));
+    ///
+    /// let island = tree.flood_fill(IVec3::new(0, 0, 0), Connectivity::Edges);
+    /// assert_eq!(island.get_value(IVec3::new(1, 1, 0)), Some(2));
+    /// assert_eq!(island.iter().count(), 2);
+    /// let island = tree.flood_fill(IVec3::new(0, 0, 0), Connectivity::Faces);
+    /// assert_eq!(island.iter().count(), 1);
+    /// assert_eq!(tree.connected_components(Connectivity::Vertices).len(), 2);
+    /// ```
+    pub fn flood_fill(&self, seed: IVec3, connectivity: Connectivity) -> Self
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut flood = Flood::new(self, connectivity);
+        let origin = seed & !<ROOT::LeafType as Node>::EXTENT_MASK.as_ivec3();
+        let region = flood.fill(origin, flood.voxel_mask(seed));
+        flood.to_tree(&region)
+    }
+
+    /// Split the occupied voxels into the sets of connected voxels, returned as separate
+    /// trees in no particular order. Voxels keep their values and coordinates.
+    pub fn connected_components(&self, connectivity: Connectivity) -> Vec<Self>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut flood = Flood::new(self, connectivity);
+        let mut seeds = Vec::new();
+        self.root.visit(
+            &self.pool,
+            IVec3::ZERO,
+            &Self::extent(),
+            &mut |node| match node {
+                VisitedNode::Leaf(origin, leaf) => {
+                    let mut occupancy = vec![0; flood.full.len()];
+                    leaf.get_occupancy(&mut occupancy);
+                    flood.blocks.insert(origin, Block::Leaf(leaf, occupancy));
+                    seeds.push(origin);
+                }
+                VisitedNode::Tile(aabb, _) => seeds.push(aabb.min),
+            },
+        );
+
+        // Voxels already assigned to a component.
+        let mut labelled = Region::default();
+        let full = flood.full.clone();
+        let mut components = Vec::new();
+        for origin in seeds {
+            loop {
+                let seed = match flood.block(origin) {
+                    Block::Leaf(_, occupancy) => {
+                        let done = labelled.leaves.get(&origin);
+                        occupancy.iter().enumerate().find_map(|(i, word)| {
+                            let remaining = word & !done.map_or(0, |done| done[i]);
+                            (remaining != 0).then(|| {
+                                let mut mask = vec![0; occupancy.len()];
+                                mask[i] = 1 << remaining.trailing_zeros();
+                                mask
+                            })
+                        })
+                    }
+                    Block::Tile(aabb, _) => {
+                        (!labelled.tiles.contains_key(&aabb.min)).then(|| full.clone())
+                    }
+                    Block::Empty => None,
+                };
+                let Some(seed) = seed else {
+                    break;
+                };
+                let region = flood.fill(origin, seed);
+                components.push(flood.to_tree(&region));
+                for (origin, mask) in region.leaves {
+                    let done = labelled
+                        .leaves
+                        .entry(origin)
+                        .or_insert_with(|| vec![0; mask.len()]);
+                    for (d, m) in done.iter_mut().zip(mask) {
+                        *d |= m;
+                    }
+                }
+                labelled.tiles.extend(region.tiles);
+            }
+        }
+        components
+    }
+}
+
+struct Flood<'a, ROOT: Node>
+where
+    [(); ROOT::LEVEL as usize]: Sized,
+{
+    tree: &'a Tree<ROOT>,
+    connectivity: Connectivity,
+    /// Content of the blocks looked up so far, by their origin.
+    blocks: FxHashMap<IVec3, Block<'a, ROOT::LeafType, ROOT::Voxel>>,
+    /// Bits of the voxels on the lower and upper face of a block, for each axis.
+    faces: [[Vec<u64>; 2]; 3],
+    full: Vec<u64>,
+}
+
+impl<'a, ROOT: Node> Flood<'a, ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    fn new(tree: &'a Tree<ROOT>, connectivity: Connectivity) -> Self {
+        let log2 = <ROOT::LeafType as Node>::EXTENT_LOG2;
+        let num_bits = 1_usize << (log2.x + log2.y + log2.z);
+        Self {
+            tree,
+            connectivity,
+            blocks: FxHashMap::default(),
+            faces: face_masks::<ROOT::LeafType>(),
+            full: vec![u64::MAX; num_bits / u64::BITS as usize],
+        }
+    }
+
+    /// Returns the mask with only the bit of the voxel at `coords` set.
+    fn voxel_mask(&self, coords: IVec3) -> Vec<u64> {
+        let local = coords.as_uvec3() & <ROOT::LeafType as Node>::EXTENT_MASK;
+        let index = (0..3)
+            .map(|axis| (local[axis] as usize) << stride_log2::<ROOT::LeafType>(axis))
+            .sum::<usize>();
+        let mut mask = vec![0; self.full.len()];
+        mask[index / 64] = 1 << (index % 64);
+        mask
+    }
+
+    fn block(&mut self, origin: IVec3) -> &Block<'a, ROOT::LeafType, ROOT::Voxel> {
+        let tree = self.tree;
+        let num_words = self.full.len();
+        self.blocks.entry(origin).or_insert_with(|| {
+            let (value, extent_log2) = tree.root.probe(&tree.pool, origin.as_uvec3());
+            if extent_log2 == UVec3::ZERO {
+                let aabb = Aabb::from_extent(origin, <ROOT::LeafType as Node>::EXTENT);
+                let mut block = Block::Empty;
+                tree.root
+                    .visit(&tree.pool, IVec3::ZERO, &aabb, &mut |node| {
+                        if let VisitedNode::Leaf(_, leaf) = node {
+                            let mut occupancy = vec![0; num_words];
+                            leaf.get_occupancy(&mut occupancy);
+                            block = Block::Leaf(leaf, occupancy);
+                        }
+                    });
+                block
+            } else if let Some(value) = value {
+                let extent = UVec3::ONE << extent_log2;
+                let min = origin.as_uvec3() & !(extent - UVec3::ONE);
+                Block::Tile(Aabb::from_extent(min.as_ivec3(), extent), value)
+            } else {
+                Block::Empty
+            }
+        })
+    }
+
+    /// Returns the occupied voxels connected to the voxels in `mask` within the block
+    /// at `origin`.
+    fn fill(&mut self, origin: IVec3, mask: Vec<u64>) -> Region<ROOT::Voxel> {
+        let mut region = Region::default();
+        let mut queue = Vec::new();
+        self.reach(&mut region, &mut queue, origin, &mask);
+        let offsets = Aabb::new(IVec3::NEG_ONE, IVec3::splat(2));
+        while let Some((origin, frontier)) = queue.pop() {
+            for direction in offsets.iter() {
+                let Some(neighbor) = self.neighbor(origin, direction) else {
+                    continue;
+                };
+                if let Some(mask) = self.spread(&frontier, direction) {
+                    self.reach(&mut region, &mut queue, neighbor, &mask);
+                }
+            }
+        }
+        region
+    }
+
+    /// Add the occupied voxels in `mask` within the block at `origin` to the region,
+    /// queueing the newly reached voxels to spread from.
+    fn reach(
+        &mut self,
+        region: &mut Region<ROOT::Voxel>,
+        queue: &mut Vec<(IVec3, Vec<u64>)>,
+        origin: IVec3,
+        mask: &[u64],
+    ) {
+        match self.block(origin) {
+            Block::Leaf(_, occupancy) => {
+                let reached = region.leaves.get(&origin);
+                let new: Vec<u64> = (0..mask.len())
+                    .map(|i| mask[i] & occupancy[i] & !reached.map_or(0, |reached| reached[i]))
+                    .collect();
+                if new.iter().all(|word| *word == 0) {
+                    return;
+                }
+                let reached = region
+                    .leaves
+                    .entry(origin)
+                    .or_insert_with(|| vec![0; mask.len()]);
+                for (r, n) in reached.iter_mut().zip(new.iter()) {
+                    *r |= n;
+                }
+                queue.push((origin, new));
+            }
+            Block::Tile(aabb, value) => {
+                let (aabb, value) = (*aabb, *value);
+                if region.tiles.contains_key(&aabb.min) {
+                    return;
+                }
+                region.tiles.insert(aabb.min, (aabb, value));
+                for_each_boundary_block::<ROOT::LeafType>(aabb, |origin| {
+                    queue.push((origin, self.full.clone()));
+                });
+            }
+            Block::Empty => (),
+        }
+    }
+
+    /// Returns the origin of the block next to the block at `origin` in `direction`.
+    fn neighbor(&self, origin: IVec3, direction: IVec3) -> Option<IVec3> {
+        let extent = <ROOT::LeafType as Node>::EXTENT.as_ivec3();
+        let mut neighbor = origin;
+        for axis in 0..3 {
+            neighbor[axis] = origin[axis].checked_add(direction[axis] * extent[axis])?;
+        }
+        Tree::<ROOT>::extent()
+            .contains(neighbor)
+            .then_some(neighbor)
+    }
+
+    /// Returns the voxels within the block next to the block of `mask` in `direction`
+    /// that are neighbors of the voxels in `mask`, or None if there are none.
+    fn spread(&self, mask: &[u64], direction: IVec3) -> Option<Vec<u64>> {
+        let mut result: Option<Vec<u64>> = None;
+        for axes in self.connectivity.sweeps() {
+            // Axes outside of the sweep can not cross into another block.
+            if (0..3).any(|axis| direction[axis] != 0 && !axes.contains(&axis)) {
+                continue;
+            }
+            let mut spread = mask.to_vec();
+            for axis in axes.iter() {
+                spread = self.spread_along(&spread, *axis, direction[*axis]);
+            }
+            match result.as_mut() {
+                Some(result) => {
+                    for (r, s) in result.iter_mut().zip(spread) {
+                        *r |= s;
+                    }
+                }
+                None => result = Some(spread),
+            }
+        }
+        result.filter(|result| result.iter().any(|word| *word != 0))
+    }
+
+    /// Spread `mask` by one voxel along `axis`, within the same block if `direction` is
+    /// zero, or into the neighboring block otherwise.
+    fn spread_along(&self, mask: &[u64], axis: usize, direction: i32) -> Vec<u64> {
+        let stride = 1_usize << stride_log2::<ROOT::LeafType>(axis);
+        let last = (1_usize << <ROOT::LeafType as Node>::EXTENT_LOG2[axis]) - 1;
+        let [lower_face, upper_face] = &self.faces[axis];
+        let on_face =
+            |face: &[u64]| -> Vec<u64> { mask.iter().zip(face).map(|(m, f)| m & f).collect() };
+        match direction {
+            0 => {
+                let up = shift_up(mask, stride);
+                let down = shift_down(mask, stride);
+                (0..mask.len())
+                    .map(|i| mask[i] | (up[i] & !lower_face[i]) | (down[i] & !upper_face[i]))
+                    .collect()
+            }
+            1 => shift_down(&on_face(upper_face), stride * last),
+            _ => shift_up(&on_face(lower_face), stride * last),
+        }
+    }
+
+    fn to_tree(&self, region: &Region<ROOT::Voxel>) -> Tree<ROOT>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut tree = Tree::new();
+        for (aabb, value) in region.tiles.values() {
+            tree.fill(*aabb, *value);
+        }
+        for (origin, mask) in region.leaves.iter() {
+            let Some(Block::Leaf(leaf, _)) = self.blocks.get(origin) else {
+                unreachable!();
+            };
+            let dst = unsafe {
+                &mut *Arc::make_mut(&mut tree.root).touch_leaf(&mut tree.pool, origin.as_uvec3())
+            };
+            *dst = (*leaf).clone();
+            dst.set_occupancy(mask, Default::default());
+        }
+        tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fxhash::FxHashSet;
+    use glam::IVec3;
+    use rand::prelude::*;
+
+    use crate::{
+        test_utils::{neighborhood, occupied, random_tree, TestTree},
+        Aabb, Connectivity,
+    };
+
+    /// Reference implementation working on individual voxels.
+    fn flood(voxels: &FxHashSet<IVec3>, seed: IVec3, connectivity: Connectivity) -> Vec<IVec3> {
+        let offsets = neighborhood(connectivity);
+        let mut reached = FxHashSet::default();
+        let mut queue = vec![seed];
+        while let Some(v) = queue.pop() {
+            if voxels.contains(&v) && reached.insert(v) {
+                queue.extend(offsets.iter().map(|d| v + *d));
+            }
+        }
+        let mut reached: Vec<_> = reached.into_iter().collect();
+        reached.sort_by_key(|v| v.to_array());
+        reached
+    }
+
+    fn sorted(tree: &TestTree, bounds: Aabb) -> Vec<IVec3> {
+        let mut voxels: Vec<_> = occupied(tree, bounds).into_iter().collect();
+        voxels.sort_by_key(|v| v.to_array());
+        voxels
+    }
+
+    #[test]
+    fn test_components_random() {
+        let mut rng = rand::thread_rng();
+        let bounds = Aabb::new(IVec3::splat(-20), IVec3::splat(20));
+        for connectivity in [
+            Connectivity::Faces,
+            Connectivity::Edges,
+            Connectivity::Vertices,
+        ] {
+            let tree = random_tree(&mut rng, 600, 1..4);
+            let voxels = occupied(&tree, bounds);
+
+            let components = tree.connected_components(connectivity);
+            let mut total = 0;
+            for component in components.iter() {
+                let component_voxels = sorted(component, bounds);
+                assert!(!component_voxels.is_empty());
+                total += component_voxels.len();
+                assert_eq!(
+                    flood(&voxels, component_voxels[0], connectivity),
+                    component_voxels
+                );
+                for v in component_voxels {
+                    assert_eq!(component.get_value(v), tree.get_value(v));
+                }
+            }
+            assert_eq!(total, voxels.len());
+
+            for _ in 0..10 {
+                let seed = *voxels.iter().choose(&mut rng).unwrap();
+                let filled = tree.flood_fill(seed, connectivity);
+                assert_eq!(sorted(&filled, bounds), flood(&voxels, seed, connectivity));
+            }
+            assert!(tree
+                .flood_fill(IVec3::splat(100), connectivity)
+                .iter()
+                .next()
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn test_floating_island() {
+        let mut tree = TestTree::new();
+        tree.fill(Aabb::new(IVec3::ZERO, IVec3::new(64, 8, 8)), 1);
+        assert_eq!(tree.connected_components(Connectivity::Faces).len(), 1);
+        // Cut through the structure.
+        tree.clear(Aabb::new(IVec3::new(30, 0, 0), IVec3::new(31, 8, 8)));
+        let components = tree.connected_components(Connectivity::Faces);
+        assert_eq!(components.len(), 2);
+        let counts: FxHashSet<u64> = components.iter().map(|c| c.voxel_count()).collect();
+        assert_eq!(counts, [30 * 64, 33 * 64].into_iter().collect());
+    }
+}
@@ -0,0 +1,218 @@
+"""Generates the OpenVDB fixtures read by the tests in `io/openvdb.rs`.
+
+The files are written byte by byte, following the layout of `io::File::write` in
+OpenVDB 10.0 with `setCompression(COMPRESS_ZIP | COMPRESS_ACTIVE_MASK)` and grid
+statistics enabled. They were not produced by OpenVDB itself, so they only check
+the reader against this reading of the format, not against files written by
+OpenVDB, Houdini or Blender.
+
+Run `python3 generate.py` in this directory to regenerate `density.vdb` and `mask.vdb`.
+"""
+import struct, zlib
+
+MAGIC = 0x56444220
+
+def s(x):
+    return struct.pack("<I", len(x)) + x
+
+def zip_data(raw):
+    z = zlib.compress(raw, -1)
+    if len(z) < len(raw):
+        return struct.pack("<q", len(z)) + z
+    return struct.pack("<q", -len(raw)) + raw
+
+def mask_bytes(bits, size):
+    words = [0] * ((size + 63) // 64)
+    for b in bits:
+        words[b // 64] |= 1 << (b % 64)
+    return b"".join(struct.pack("<Q", w) for w in words)
+
+class Grid:
+    def __init__(self, vfmt, vsize, uuid, is_mask=False):
+        # OpenVDB writes a random UUID into each file. Fixed ones keep the output stable.
+        self.uuid = uuid
+        self.vfmt = vfmt
+        self.vsize = vsize
+        self.is_mask = is_mask
+        self.bg = False if is_mask else 0.0
+        self.root_tiles = {}   # key -> value (active)
+        self.leaves = {}       # leaf origin -> {index: (value, active)}
+        self.tiles4 = {}       # 8^3 tile origin -> value (active)
+
+    def pack(self, v):
+        return struct.pack("<" + self.vfmt, v)
+
+    def set(self, c, v, active=True):
+        o = tuple(x & ~7 for x in c)
+        leaf = self.leaves.setdefault(o, {})
+        i = ((c[0] - o[0]) << 6) | ((c[1] - o[1]) << 3) | (c[2] - o[2])
+        leaf[i] = (v, active)
+
+    def compressed(self, values, value_mask, child_mask):
+        # MaskCompress
+        inactive = [self.bg, self.bg]
+        n = 0
+        for i in range(len(values)):
+            if i in value_mask or i in child_mask:
+                continue
+            v = values[i]
+            unique = not ((n > 0 and v == inactive[0]) or (n > 1 and v == inactive[1]))
+            if unique:
+                if n < 2:
+                    inactive[n] = v
+                n += 1
+            if n >= 3:
+                break
+        meta = 0
+        if n == 1:
+            if inactive[0] != self.bg:
+                meta = 2
+        elif n == 2:
+            if inactive[0] != self.bg and inactive[1] != self.bg:
+                meta = 5
+            elif inactive[1] == self.bg:
+                meta = 4
+            elif inactive[0] == self.bg:
+                inactive = [inactive[1], inactive[0]]
+                meta = 4
+        elif n > 2:
+            meta = 6
+        out = struct.pack("<b", meta)
+        if meta in (2, 4, 5):
+            out += self.pack(inactive[0])
+            if meta == 5:
+                out += self.pack(inactive[1])
+        if meta == 6:
+            data = values
+        else:
+            data = [values[i] for i in sorted(value_mask)]
+            if meta in (4, 5):
+                sel = [i for i in range(len(values))
+                       if i not in value_mask and values[i] == inactive[1]]
+                out += mask_bytes(sel, len(values))
+        out += zip_data(b"".join(self.pack(v) for v in data))
+        return out
+
+    def write(self, name):
+        # Build the node hierarchy: root key -> i4 origin -> {leaves, tiles}
+        roots = {}
+        for o in self.leaves:
+            k5 = tuple(x & ~4095 for x in o)
+            k4 = tuple(x & ~127 for x in o)
+            roots.setdefault(k5, {}).setdefault(k4, {"leaves": [], "tiles": []})["leaves"].append(o)
+        for o in self.tiles4:
+            k5 = tuple(x & ~4095 for x in o)
+            k4 = tuple(x & ~127 for x in o)
+            roots.setdefault(k5, {}).setdefault(k4, {"leaves": [], "tiles": []})["tiles"].append(o)
+
+        topo = struct.pack("<i", 1) + self.pack(self.bg)
+        topo += struct.pack("<II", len(self.root_tiles), len(roots))
+        for k in sorted(self.root_tiles):
+            topo += struct.pack("<3i", *k) + self.pack(self.root_tiles[k]) + b"\x01"
+        buffers = b""
+        for k5 in sorted(roots):
+            topo += struct.pack("<3i", *k5)
+            i5 = {}
+            for k4 in roots[k5]:
+                off = [(a - b) >> 7 for a, b in zip(k4, k5)]
+                i5[(off[0] << 10) | (off[1] << 5) | off[2]] = k4
+            child5 = set(i5)
+            topo += mask_bytes(child5, 32768) + mask_bytes([], 32768)
+            topo += self.compressed([self.bg] * 32768, set(), child5)
+            for idx in sorted(i5):
+                k4 = i5[idx]
+                node = roots[k5][k4]
+                children = {}
+                for o in node["leaves"]:
+                    off = [(a - b) >> 3 for a, b in zip(o, k4)]
+                    children[(off[0] << 8) | (off[1] << 4) | off[2]] = o
+                tiles = {}
+                for o in node["tiles"]:
+                    off = [(a - b) >> 3 for a, b in zip(o, k4)]
+                    tiles[(off[0] << 8) | (off[1] << 4) | off[2]] = self.tiles4[o]
+                values = [self.bg] * 4096
+                for i, v in tiles.items():
+                    values[i] = v
+                topo += mask_bytes(children, 4096) + mask_bytes(tiles, 4096)
+                topo += self.compressed(values, set(tiles), set(children))
+                for ci in sorted(children):
+                    o = children[ci]
+                    leaf = self.leaves[o]
+                    active = {i for i, (v, a) in leaf.items() if a}
+                    topo += mask_bytes(active, 512)
+                    buffers += mask_bytes(active, 512)
+                    if self.is_mask:
+                        buffers += struct.pack("<3i", *o)
+                    else:
+                        values = [self.bg] * 512
+                        for i, (v, a) in leaf.items():
+                            values[i] = v
+                        buffers += self.compressed(values, active, set())
+
+        # Stats
+        lo = [1 << 40] * 3
+        hi = [-(1 << 40)] * 3
+        count = 0
+        def grow(a, b):
+            for d in range(3):
+                lo[d] = min(lo[d], a[d])
+                hi[d] = max(hi[d], b[d])
+        for k, v in self.root_tiles.items():
+            grow(k, [x + 4095 for x in k]); count += 4096 ** 3
+        for o in self.tiles4:
+            grow(o, [x + 7 for x in o]); count += 512
+        for o, leaf in self.leaves.items():
+            for i, (v, a) in leaf.items():
+                if a:
+                    c = (o[0] + (i >> 6), o[1] + ((i >> 3) & 7), o[2] + (i & 7))
+                    grow(c, c); count += 1
+        # Tree::memUsage: root table entries, internal nodes and leaves.
+        leaf_bytes = 96 if self.is_mask else 2144
+        mem = 72 + 56 * (len(roots) + len(self.root_tiles))
+        mem += sum(270352 + 33808 * len(r) for r in roots.values())
+        mem += leaf_bytes * len(self.leaves)
+
+        meta = [
+            (b"file_bbox_max", b"vec3i", struct.pack("<3i", *hi)),
+            (b"file_bbox_min", b"vec3i", struct.pack("<3i", *lo)),
+            (b"file_compression", b"string", b"zip + active values"),
+            (b"file_mem_bytes", b"int64", struct.pack("<q", mem)),
+            (b"file_voxel_count", b"int64", struct.pack("<q", count)),
+            (b"name", b"string", name),
+        ]
+        grid = struct.pack("<I", 0x1 | 0x2)
+        grid += struct.pack("<I", len(meta))
+        for n, t, v in meta:
+            grid += s(n) + s(t) + struct.pack("<I", len(v)) + v
+        grid += s(b"UniformScaleMap")
+        for v in [1.0, 1.0, 1.0, 1.0, 0.5]:
+            grid += struct.pack("<3d", v, v, v)
+        grid_type = b"Tree_mask_5_4_3" if self.is_mask else b"Tree_float_5_4_3"
+
+        header = struct.pack("<qIII", MAGIC, 224, 10, 0) + b"\x01" + self.uuid
+        header += struct.pack("<I", 0) + struct.pack("<i", 1)
+        header += s(name) + s(grid_type) + s(b"")
+        grid_pos = len(header) + 24
+        block_pos = grid_pos + len(grid) + len(topo)
+        end_pos = block_pos + len(buffers)
+        header += struct.pack("<3q", grid_pos, block_pos, end_pos)
+        return header + grid + topo + buffers
+
+density = Grid("f", 4, b"3b1f7c2e-9a64-4d0b-8e15-6f2a90c4d7b1")
+density.set((1, 2, 3), 0.5)
+density.set((1, 2, 4), -1.25)
+density.set((2, 2, 3), 2.0, active=False)
+density.tiles4[(16, 0, 0)] = 2.0
+for x in range(8):
+    for y in range(8):
+        for z in range(8):
+            density.set((-8 + x, 96 + y, z), float(x * 64 + y * 8 + z))
+density.root_tiles[(8192, 0, 0)] = 9.0
+open("density.vdb", "wb").write(density.write(b"density"))
+
+mask = Grid("?", 1, b"a7d04c19-52e3-4f8a-b6c1-0e9d3f7a2854", is_mask=True)
+mask.set((1, 2, 3), True)
+mask.set((7, 7, 7), True)
+mask.set((-1, -1, -1), True)
+mask.tiles4[(8, 0, 0)] = True
+open("mask.vdb", "wb").write(mask.write(b"mask"))
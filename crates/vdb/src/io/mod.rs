@@ -0,0 +1,208 @@
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::sync::Arc;
+
+use crate::{tree::TreeMeta, BitMask, Node, NodeConst, Tree};
+
+mod openvdb;
+pub use openvdb::VdbValue;
+
+const MAGIC: [u8; 4] = *b"DVDB";
+const VERSION: u32 = 1;
+
+/// Voxel values that can be written into the binary tree format.
+pub trait Serializable: Sized {
+    /// Identifies the value type in serialized data, so that values are never read back
+    /// as a different type of the same size.
+    const TYPE_TAG: [u8; 4];
+    fn write_to(&self, writer: &mut impl Write) -> Result<()>;
+    fn read_from(reader: &mut impl Read) -> Result<Self>;
+}
+
+macro_rules! impl_serializable_num {
+    ($($t: ty => $tag: literal),*) => {
+        $(
+            impl Serializable for $t {
+                const TYPE_TAG: [u8; 4] = *$tag;
+                fn write_to(&self, writer: &mut impl Write) -> Result<()> {
+                    writer.write_all(&self.to_le_bytes())
+                }
+                fn read_from(reader: &mut impl Read) -> Result<Self> {
+                    let mut bytes = [0; std::mem::size_of::<$t>()];
+                    reader.read_exact(&mut bytes)?;
+                    Ok(Self::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+impl_serializable_num!(
+    u8 => b"u8  ",
+    u16 => b"u16 ",
+    u32 => b"u32 ",
+    u64 => b"u64 ",
+    i8 => b"i8  ",
+    i16 => b"i16 ",
+    i32 => b"i32 ",
+    i64 => b"i64 ",
+    f32 => b"f32 ",
+    f64 => b"f64 "
+);
+
+impl Serializable for bool {
+    const TYPE_TAG: [u8; 4] = *b"bool";
+    fn write_to(&self, writer: &mut impl Write) -> Result<()> {
+        (*self as u8).write_to(writer)
+    }
+    fn read_from(reader: &mut impl Read) -> Result<Self> {
+        match u8::read_from(reader)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Error::new(ErrorKind::InvalidData, "invalid bool value")),
+        }
+    }
+}
+
+impl Serializable for () {
+    const TYPE_TAG: [u8; 4] = *b"()  ";
+    fn write_to(&self, _writer: &mut impl Write) -> Result<()> {
+        Ok(())
+    }
+    fn read_from(_reader: &mut impl Read) -> Result<Self> {
+        Ok(())
+    }
+}
+
+impl<const SIZE: usize> BitMask<SIZE>
+where
+    [(); SIZE / std::mem::size_of::<usize>() / 8]: Sized,
+{
+    pub(crate) fn write_words(&self, writer: &mut impl Write) -> Result<()> {
+        for word in self.data.iter() {
+            (*word as u64).write_to(writer)?;
+        }
+        Ok(())
+    }
+    pub(crate) fn read_words(&mut self, reader: &mut impl Read) -> Result<()> {
+        for word in self.data.iter_mut() {
+            *word = u64::read_from(reader)? as usize;
+        }
+        Ok(())
+    }
+}
+
+/// Binary serialization.
+///
+/// The format starts with a header containing the magic number `DVDB`, the format version,
+/// the hierarchy ID of the tree and the [`Serializable::TYPE_TAG`] of the voxel values.
+/// Nodes are then written depth-first, starting from the root. Each node writes its
+/// bitmasks followed by its tile or voxel values, and internal nodes write their child
+/// nodes in `child_mask` order.
+/// All numbers are little endian.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+    ROOT::Voxel: Serializable,
+{
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Tree};
+    /// use glam::IVec3;
+    /// let mut tree = Tree::<hierarchy!(#, 3, 2; u16)>::new();
+    /// tree.set_value(IVec3::new(1, 2, 3), Some(1000));
+    ///
+    /// let mut data: Vec<u8> = Vec::new();
+    /// tree.write_to(&mut data).unwrap();
+    /// let tree = Tree::<hierarchy!(#, 3, 2; u16)>::read_from(data.as_slice()).unwrap();
+    /// assert_eq!(tree.get_value(IVec3::new(1, 2, 3)), Some(1000));
+    ///
+    /// // Trees with a different hierarchy or value type are rejected.
+    /// assert!(Tree::<hierarchy!(#, 2, 2; u16)>::read_from(data.as_slice()).is_err());
+    /// assert!(Tree::<hierarchy!(#, 3, 2; i16)>::read_from(data.as_slice()).is_err());
+    /// ```
+    pub fn write_to(&self, mut writer: impl Write) -> Result<()>
+    where
+        ROOT: ~const NodeConst,
+    {
+        writer.write_all(&MAGIC)?;
+        VERSION.write_to(&mut writer)?;
+        <Self as TreeMeta<ROOT>>::ID.write_to(&mut writer)?;
+        writer.write_all(&ROOT::Voxel::TYPE_TAG)?;
+        self.root.write_to(&self.pool, &mut writer)
+    }
+
+    pub fn read_from(mut reader: impl Read) -> Result<Self>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut magic = [0_u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a dust_vdb tree"));
+        }
+        let version = u32::read_from(&mut reader)?;
+        if version != VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unsupported version {}", version),
+            ));
+        }
+        if u64::read_from(&mut reader)? != <Self as TreeMeta<ROOT>>::ID {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "mismatched tree hierarchy",
+            ));
+        }
+        let mut type_tag = [0_u8; 4];
+        reader.read_exact(&mut type_tag)?;
+        if type_tag != ROOT::Voxel::TYPE_TAG {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "mismatched voxel value type",
+            ));
+        }
+        let mut tree = Self::new();
+        Arc::make_mut(&mut tree.root).read_from(&mut tree.pool, &mut reader)?;
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use crate::{hierarchy, Aabb, Tree};
+
+    #[test]
+    fn test_roundtrip() {
+        let mut tree = Tree::<hierarchy!(#, 3, 2; u8)>::new();
+        tree.fill(Aabb::new(IVec3::ZERO, IVec3::new(64, 64, 32)), 3);
+        tree.fill(Aabb::new(IVec3::new(64, 0, 0), IVec3::new(72, 8, 8)), 5);
+        tree.set_value(IVec3::new(1, 2, 3), Some(7));
+        tree.set_value(IVec3::new(100, 20, 7), Some(9));
+        tree.set_value(IVec3::new(1000, 2000, 3000), Some(11));
+        tree.set_value(IVec3::new(-1000, 20, -3), Some(13));
+
+        let mut data: Vec<u8> = Vec::new();
+        tree.write_to(&mut data).unwrap();
+        let read = Tree::<hierarchy!(#, 3, 2; u8)>::read_from(data.as_slice()).unwrap();
+        for (i, pool) in tree.pool.iter().enumerate() {
+            assert_eq!(pool.count(), read.pool[i].count());
+        }
+        for x in 0..128 {
+            for y in 0..128 {
+                for z in 0..64 {
+                    let coords = IVec3::new(x, y, z);
+                    assert_eq!(tree.get_value(coords), read.get_value(coords));
+                }
+            }
+        }
+        assert_eq!(read.get_value(IVec3::new(1000, 2000, 3000)), Some(11));
+        assert_eq!(read.get_value(IVec3::new(-1000, 20, -3)), Some(13));
+
+        // Truncated data is rejected.
+        assert!(Tree::<hierarchy!(#, 3, 2; u8)>::read_from(&data[..data.len() - 1]).is_err());
+        // So is data of another value type with the same size.
+        assert!(Tree::<hierarchy!(#, 3, 2; i8)>::read_from(data.as_slice()).is_err());
+        assert!(Tree::<hierarchy!(#, 3, 2; bool)>::read_from(data.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,1022 @@
+//! Reader and writer for grids in the OpenVDB file format (`.vdb`).
+//!
+//! Only the parts of the format needed to exchange voxel data are supported:
+//! trees with a root node and uniform fanouts (e.g. `Tree_float_5_4_3`), stored with
+//! no compression or zip compression. Transforms and metadata are skipped when reading,
+//! and voxels are kept in index space.
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::sync::Arc;
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use glam::{IVec3, UVec3};
+
+use super::Serializable;
+use crate::{tree::TreeMeta, Aabb, Node, NodeConst, Tree, VisitedNode};
+
+/// `" BDV"` as a 64 bit integer.
+const MAGIC: i64 = 0x56444220;
+const FILE_VERSION: u32 = 224;
+/// Oldest supported file version. This version added compression metadata to value buffers.
+const FILE_VERSION_NODE_MASK_COMPRESSION: u32 = 222;
+const LIBRARY_VERSION: [u32; 2] = [9, 0];
+
+const COMPRESS_ZIP: u32 = 0x1;
+const COMPRESS_ACTIVE_MASK: u32 = 0x2;
+const COMPRESS_BLOSC: u32 = 0x4;
+
+// Describes which inactive values are stored alongside a compressed value buffer.
+const NO_MASK_OR_INACTIVE_VALS: i8 = 0;
+const NO_MASK_AND_ONE_INACTIVE_VAL: i8 = 2;
+const MASK_AND_NO_INACTIVE_VALS: i8 = 3;
+const MASK_AND_ONE_INACTIVE_VAL: i8 = 4;
+const MASK_AND_TWO_INACTIVE_VALS: i8 = 5;
+const NO_MASK_AND_ALL_VALS: i8 = 6;
+
+const HALF_FLOAT_SUFFIX: &str = "_HalfFloat";
+/// Separates the grid name from the suffix used to make grid names unique within a file.
+const UNIQUE_NAME_SEPARATOR: char = '\x1e';
+
+/// Voxel values that can be stored in OpenVDB grids.
+///
+/// `mask` grids can be read into trees of any value type, with occupied voxels
+/// set to `Self::from(true)`.
+pub trait VdbValue: Serializable + Copy + Default + PartialEq + From<bool> + 'static {
+    /// Name of the value type within grid type names, e.g. `float` in `Tree_float_5_4_3`.
+    const TYPE_NAME: &'static str;
+    /// Read a value saved as a 16 bit float.
+    fn read_half(_reader: &mut impl Read) -> Result<Self> {
+        Err(Error::new(
+            ErrorKind::InvalidData,
+            "half float values are only supported for floating point grids",
+        ))
+    }
+}
+
+impl VdbValue for bool {
+    const TYPE_NAME: &'static str = "bool";
+}
+impl VdbValue for i32 {
+    const TYPE_NAME: &'static str = "int32";
+}
+impl VdbValue for i64 {
+    const TYPE_NAME: &'static str = "int64";
+}
+impl VdbValue for f32 {
+    const TYPE_NAME: &'static str = "float";
+    fn read_half(reader: &mut impl Read) -> Result<Self> {
+        Ok(half_to_f32(u16::read_from(reader)?))
+    }
+}
+impl VdbValue for f64 {
+    const TYPE_NAME: &'static str = "double";
+    fn read_half(reader: &mut impl Read) -> Result<Self> {
+        Ok(half_to_f32(u16::read_from(reader)?) as f64)
+    }
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = ((half >> 15) as u32) << 31;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        0 => {
+            // Subnormal numbers are normalized in single precision.
+            let mut exponent = 127 - 15 + 1;
+            let mut mantissa = mantissa;
+            while mantissa & 0x400 == 0 {
+                mantissa <<= 1;
+                exponent -= 1;
+            }
+            sign | (exponent << 23) | ((mantissa & 0x3ff) << 13)
+        }
+        0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn mask_words(size: usize) -> usize {
+    (size + 63) / 64
+}
+
+fn mask_get(mask: &[u64], index: usize) -> bool {
+    mask[index / 64] & (1 << (index % 64)) != 0
+}
+
+fn mask_set(mask: &mut [u64], index: usize) {
+    mask[index / 64] |= 1 << (index % 64);
+}
+
+fn mask_iter(mask: &[u64]) -> impl Iterator<Item = usize> + '_ {
+    (0..mask.len() * 64).filter(|index| mask_get(mask, *index))
+}
+
+/// Index of the child at `offset` within a node of uniform fanout `log2`, in OpenVDB order.
+fn child_index(offset: UVec3, log2: u32) -> usize {
+    ((offset.x as usize) << (2 * log2)) | ((offset.y as usize) << log2) | offset.z as usize
+}
+
+fn child_offset(index: usize, log2: u32) -> UVec3 {
+    let mask = (1 << log2) - 1;
+    UVec3 {
+        x: (index >> (2 * log2)) as u32,
+        y: (index >> log2) as u32 & mask,
+        z: index as u32 & mask,
+    }
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String> {
+    let len = u32::read_from(reader)? as u64;
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|_| invalid_data("invalid string"))
+}
+
+fn write_string(writer: &mut impl Write, string: &str) -> Result<()> {
+    (string.len() as u32).write_to(writer)?;
+    writer.write_all(string.as_bytes())
+}
+
+fn skip(reader: &mut impl Read, len: u64) -> Result<()> {
+    if std::io::copy(&mut reader.take(len), &mut std::io::sink())? != len {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+/// Skip a map of metadata, which is written for the file and for each grid.
+fn skip_metadata(reader: &mut impl Read) -> Result<()> {
+    let count = u32::read_from(reader)?;
+    for _ in 0..count {
+        let _name = read_string(reader)?;
+        let _type_name = read_string(reader)?;
+        let len = u32::read_from(reader)?;
+        skip(reader, len as u64)?;
+    }
+    Ok(())
+}
+
+fn skip_transform(reader: &mut impl Read) -> Result<()> {
+    const VEC3D: u64 = 3 * 8;
+    let len = match read_string(reader)?.as_str() {
+        "TranslationMap" => VEC3D,
+        "ScaleMap" | "UniformScaleMap" => 5 * VEC3D,
+        "ScaleTranslateMap" | "UniformScaleTranslateMap" => 6 * VEC3D,
+        "AffineMap" | "UnitaryMap" => 16 * 8,
+        map => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("unsupported transform {}", map),
+            ))
+        }
+    };
+    skip(reader, len)
+}
+
+/// Write an identity transform.
+fn write_transform(writer: &mut impl Write) -> Result<()> {
+    write_string(writer, "UniformScaleMap")?;
+    // Scale, voxel size, inverse scale, inverse scale squared and half inverse scale.
+    for value in [1.0_f64, 1.0, 1.0, 1.0, 0.5] {
+        for _ in 0..3 {
+            value.write_to(writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Parse a grid type name like `Tree_float_5_4_3_HalfFloat` into the value type name,
+/// the log2 fanouts of the nodes below the root and whether values were saved as half floats.
+fn parse_grid_type(grid_type: &str) -> Option<(String, Vec<u32>, bool)> {
+    let (grid_type, half) = match grid_type.strip_suffix(HALF_FLOAT_SUFFIX) {
+        Some(grid_type) => (grid_type, true),
+        None => (grid_type, false),
+    };
+    let mut parts: Vec<&str> = grid_type.strip_prefix("Tree_")?.split('_').collect();
+    let mut fanouts = Vec::new();
+    while let Some(fanout) = parts.last().and_then(|part| part.parse::<u32>().ok()) {
+        fanouts.push(fanout);
+        parts.pop();
+    }
+    if parts.is_empty() || fanouts.is_empty() {
+        return None;
+    }
+    fanouts.reverse();
+    Some((parts.join("_"), fanouts, half))
+}
+
+/// Reads value buffers following the compression settings of a grid.
+struct GridReader<R> {
+    reader: R,
+    compression: u32,
+    half: bool,
+    /// Values of `mask` grids are saved as `bool`, regardless of the value type of the tree.
+    mask: bool,
+}
+
+impl<R: Read> GridReader<R> {
+    fn read_value<V: VdbValue>(&mut self) -> Result<V> {
+        if self.mask {
+            Ok(bool::read_from(&mut self.reader)?.into())
+        } else {
+            V::read_from(&mut self.reader)
+        }
+    }
+    fn read_mask(&mut self, size: usize) -> Result<Vec<u64>> {
+        (0..mask_words(size))
+            .map(|_| u64::read_from(&mut self.reader))
+            .collect()
+    }
+    fn read_data<V: VdbValue>(&mut self, values: &mut [V]) -> Result<()> {
+        if self.compression & COMPRESS_BLOSC != 0 {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "blosc compression is not supported",
+            ));
+        }
+        let value_size = if self.half {
+            2
+        } else {
+            std::mem::size_of::<V>()
+        };
+        let mut bytes = vec![0_u8; values.len() * value_size];
+        if self.compression & COMPRESS_ZIP != 0 {
+            let num_bytes = i64::read_from(&mut self.reader)?;
+            if num_bytes <= 0 {
+                // Data that didn't compress well is saved as is.
+                if num_bytes.unsigned_abs() != bytes.len() as u64 {
+                    return Err(invalid_data("mismatched value buffer size"));
+                }
+                self.reader.read_exact(&mut bytes)?;
+            } else {
+                let mut zipped = Vec::new();
+                (&mut self.reader)
+                    .take(num_bytes as u64)
+                    .read_to_end(&mut zipped)?;
+                if zipped.len() as u64 != num_bytes as u64 {
+                    return Err(ErrorKind::UnexpectedEof.into());
+                }
+                ZlibDecoder::new(zipped.as_slice()).read_exact(&mut bytes)?;
+            }
+        } else {
+            self.reader.read_exact(&mut bytes)?;
+        }
+        let mut bytes = bytes.as_slice();
+        for value in values.iter_mut() {
+            *value = if self.half {
+                V::read_half(&mut bytes)?
+            } else {
+                V::read_from(&mut bytes)?
+            };
+        }
+        Ok(())
+    }
+    /// Read the values of a node. Only values selected by `value_mask` are guaranteed
+    /// to be read, as inactive values may have been left out.
+    fn read_compressed_values<V: VdbValue>(
+        &mut self,
+        value_mask: &[u64],
+        values: &mut [V],
+    ) -> Result<()> {
+        if !self.mask {
+            return self.read_compressed_buffer(value_mask, values);
+        }
+        let mut mask_values = vec![false; values.len()];
+        self.read_compressed_buffer(value_mask, &mut mask_values)?;
+        for (value, mask_value) in values.iter_mut().zip(mask_values) {
+            *value = mask_value.into();
+        }
+        Ok(())
+    }
+    fn read_compressed_buffer<V: VdbValue>(
+        &mut self,
+        value_mask: &[u64],
+        values: &mut [V],
+    ) -> Result<()> {
+        let metadata = i8::read_from(&mut self.reader)?;
+        if !(NO_MASK_OR_INACTIVE_VALS..=NO_MASK_AND_ALL_VALS).contains(&metadata) {
+            return Err(invalid_data("invalid value buffer metadata"));
+        }
+        // Inactive values are not kept, so they can be skipped.
+        let num_inactive_values = match metadata {
+            NO_MASK_AND_ONE_INACTIVE_VAL | MASK_AND_ONE_INACTIVE_VAL => 1,
+            MASK_AND_TWO_INACTIVE_VALS => 2,
+            _ => 0,
+        };
+        skip(
+            &mut self.reader,
+            num_inactive_values * std::mem::size_of::<V>() as u64,
+        )?;
+        if (MASK_AND_NO_INACTIVE_VALS..=MASK_AND_TWO_INACTIVE_VALS).contains(&metadata) {
+            self.read_mask(values.len())?;
+        }
+        if self.compression & COMPRESS_ACTIVE_MASK != 0 && metadata != NO_MASK_AND_ALL_VALS {
+            let mut active_values = vec![V::default(); mask_iter(value_mask).count()];
+            self.read_data(&mut active_values)?;
+            for (index, value) in mask_iter(value_mask).zip(active_values) {
+                values[index] = value;
+            }
+            Ok(())
+        } else {
+            self.read_data(values)
+        }
+    }
+}
+
+/// Writes value buffers with zip and active mask compression.
+struct GridWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> GridWriter<W> {
+    const COMPRESSION: u32 = COMPRESS_ZIP | COMPRESS_ACTIVE_MASK;
+    fn write_mask(&mut self, mask: &[u64]) -> Result<()> {
+        for word in mask.iter() {
+            word.write_to(&mut self.writer)?;
+        }
+        Ok(())
+    }
+    fn write_data<V: VdbValue>(&mut self, values: &[V]) -> Result<()> {
+        let mut bytes = Vec::with_capacity(std::mem::size_of_val(values));
+        for value in values.iter() {
+            value.write_to(&mut bytes)?;
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&bytes)?;
+        let zipped = encoder.finish()?;
+        if zipped.len() < bytes.len() {
+            (zipped.len() as i64).write_to(&mut self.writer)?;
+            self.writer.write_all(&zipped)
+        } else {
+            (-(bytes.len() as i64)).write_to(&mut self.writer)?;
+            self.writer.write_all(&bytes)
+        }
+    }
+    /// Write the values selected by `value_mask`. Inactive values are restored to the
+    /// background value when read.
+    fn write_compressed_values<V: VdbValue>(
+        &mut self,
+        value_mask: &[u64],
+        values: &[V],
+    ) -> Result<()> {
+        NO_MASK_OR_INACTIVE_VALS.write_to(&mut self.writer)?;
+        let active_values: Vec<V> = mask_iter(value_mask).map(|i| values[i]).collect();
+        self.write_data(&active_values)
+    }
+}
+
+enum VdbEntry<'a, L, V> {
+    Tile(V),
+    Leaf(&'a L),
+    Node(BTreeMap<usize, VdbEntry<'a, L, V>>),
+}
+
+/// Value mask and values of a leaf node, in OpenVDB order.
+struct VdbLeaf<V> {
+    origin: IVec3,
+    value_mask: Vec<u64>,
+    values: Vec<V>,
+}
+
+/// Import and export of grids in the OpenVDB file format.
+///
+/// The tree must have a root node, and its fanouts must be the same along all axes.
+/// For example, the standard OpenVDB `Tree_float_5_4_3` grids map to
+/// `hierarchy!(#, 5, 4, 3; f32)`. Occupied voxels and tiles map to active voxels and tiles
+/// in OpenVDB, so inactive values, like the inside of a level set, are not kept.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+    ROOT::Voxel: VdbValue,
+{
+    /// The log2 fanouts of the nodes below the root, from the top down.
+    /// Returns None if the tree can not be represented in OpenVDB.
+    fn vdb_fanouts() -> Option<Vec<u32>>
+    where
+        ROOT: ~const NodeConst,
+    {
+        // Only the root node has an unbounded number of children,
+        // and OpenVDB trees have at least one internal node.
+        if ROOT::SIZE != usize::MAX || ROOT::LEVEL < 2 {
+            return None;
+        }
+        <Self as TreeMeta<ROOT>>::METAS[..ROOT::LEVEL]
+            .iter()
+            .rev()
+            .map(|meta| {
+                let log2 = meta.fanout_log2;
+                (log2.x == log2.y && log2.y == log2.z).then_some(log2.x)
+            })
+            .collect()
+    }
+
+    /// Log2 extent of the nodes at `level`.
+    fn vdb_extent_log2(level: usize) -> u32
+    where
+        ROOT: ~const NodeConst,
+    {
+        <Self as TreeMeta<ROOT>>::METAS[level].extent_log2.x
+    }
+
+    /// Read a grid from an OpenVDB file. The first grid named `grid_name` is read,
+    /// or the first grid with a matching value type if `grid_name` is None.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Tree};
+    /// use glam::IVec3;
+    /// let mut tree = Tree::<hierarchy!(#, 5, 4, 3; f32)>::new();
+    /// tree.set_value(IVec3::new(1, 2, 3), Some(0.5));
+    ///
+    /// let mut data: Vec<u8> = Vec::new();
+    /// tree.write_vdb(&mut data, "density").unwrap();
+    /// let tree = Tree::<hierarchy!(#, 5, 4, 3; f32)>::read_vdb(data.as_slice(), Some("density")).unwrap();
+    /// assert_eq!(tree.get_value(IVec3::new(1, 2, 3)), Some(0.5));
+    /// ```
+    pub fn read_vdb(reader: impl Read, grid_name: Option<&str>) -> Result<Self>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let fanouts =
+            Self::vdb_fanouts().ok_or_else(|| invalid_data("unsupported tree hierarchy"))?;
+        let mut reader = CountingReader {
+            reader,
+            position: 0,
+        };
+
+        if i64::read_from(&mut reader)? != MAGIC {
+            return Err(invalid_data("not an OpenVDB file"));
+        }
+        let version = u32::read_from(&mut reader)?;
+        if version < FILE_VERSION_NODE_MASK_COMPRESSION {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("unsupported OpenVDB file version {}", version),
+            ));
+        }
+        let _library_version = [u32::read_from(&mut reader)?, u32::read_from(&mut reader)?];
+        let _has_grid_offsets = u8::read_from(&mut reader)?;
+        skip(&mut reader, 36)?; // UUID
+        skip_metadata(&mut reader)?;
+
+        let grid_count = i32::read_from(&mut reader)?;
+        for _ in 0..grid_count {
+            let unique_name = read_string(&mut reader)?;
+            let grid_type = read_string(&mut reader)?;
+            let instance_parent = read_string(&mut reader)?;
+            let _grid_position = i64::read_from(&mut reader)?;
+            let _block_position = i64::read_from(&mut reader)?;
+            let end_position = i64::read_from(&mut reader)?;
+
+            let name = unique_name
+                .split(UNIQUE_NAME_SEPARATOR)
+                .next()
+                .unwrap_or_default();
+            let parsed = parse_grid_type(&grid_type);
+            let type_matches = matches!(&parsed, Some((value_type, _, _))
+                if value_type == ROOT::Voxel::TYPE_NAME || value_type == "mask");
+            let selected = match grid_name {
+                Some(grid_name) => grid_name == name,
+                None => type_matches,
+            };
+            if !selected {
+                let len = (end_position as u64)
+                    .checked_sub(reader.position)
+                    .ok_or_else(|| invalid_data("invalid grid offsets"))?;
+                skip(&mut reader, len)?;
+                continue;
+            }
+            if !type_matches {
+                return Err(invalid_data("mismatched grid value type"));
+            }
+            let (value_type, grid_fanouts, half) = parsed.unwrap();
+            if grid_fanouts != fanouts {
+                return Err(invalid_data("mismatched tree hierarchy"));
+            }
+            if !instance_parent.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    "instanced grids are not supported",
+                ));
+            }
+            return Self::read_vdb_grid(&mut reader, &value_type, half);
+        }
+        Err(Error::new(ErrorKind::NotFound, "grid not found"))
+    }
+
+    fn read_vdb_grid(reader: &mut impl Read, value_type: &str, half: bool) -> Result<Self>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let compression = u32::read_from(reader)?;
+        skip_metadata(reader)?;
+        skip_transform(reader)?;
+        let mut reader = GridReader {
+            reader,
+            compression,
+            half,
+            mask: value_type == "mask",
+        };
+        let mut tree = Self::new();
+
+        // Topology
+        let _buffer_count = i32::read_from(&mut reader.reader)?;
+        let _background: ROOT::Voxel = reader.read_value()?;
+        let num_tiles = u32::read_from(&mut reader.reader)?;
+        let num_children = u32::read_from(&mut reader.reader)?;
+        let child_extent_log2 = Self::vdb_extent_log2(ROOT::LEVEL - 1);
+        let read_origin = |reader: &mut GridReader<_>| -> Result<IVec3> {
+            let origin = IVec3::new(
+                i32::read_from(&mut reader.reader)?,
+                i32::read_from(&mut reader.reader)?,
+                i32::read_from(&mut reader.reader)?,
+            );
+            if origin & ((1 << child_extent_log2) - 1) != IVec3::ZERO {
+                return Err(invalid_data("misaligned root entry"));
+            }
+            Ok(origin)
+        };
+        for _ in 0..num_tiles {
+            let origin = read_origin(&mut reader)?;
+            let value = reader.read_value()?;
+            let active = bool::read_from(&mut reader.reader)?;
+            if active {
+                tree.fill(
+                    Aabb::from_extent(origin, UVec3::splat(1 << child_extent_log2)),
+                    value,
+                );
+            }
+        }
+        let mut leaves = Vec::new();
+        let mut root_children = Vec::new();
+        for _ in 0..num_children {
+            let origin = read_origin(&mut reader)?;
+            let first_leaf = leaves.len();
+            tree.read_vdb_node(&mut reader, ROOT::LEVEL - 1, origin, &mut leaves)?;
+            root_children.push((origin, first_leaf..leaves.len()));
+        }
+
+        // Buffers are written in the order of the root entries.
+        root_children.sort_by_key(|(origin, _)| origin.to_array());
+        let leaf_size = 1 << (3 * Self::vdb_extent_log2(0));
+        for (_, range) in root_children {
+            for &origin in &leaves[range] {
+                let value_mask = reader.read_mask(leaf_size)?;
+                let mut values = vec![ROOT::Voxel::default(); leaf_size];
+                match value_type {
+                    "bool" => {
+                        skip(&mut reader.reader, 3 * 4)?;
+                        let data = reader.read_mask(leaf_size)?;
+                        for (index, value) in values.iter_mut().enumerate() {
+                            *value = mask_get(&data, index).into();
+                        }
+                    }
+                    "mask" => {
+                        skip(&mut reader.reader, 3 * 4)?;
+                        values.fill(true.into());
+                    }
+                    _ => reader.read_compressed_values(&value_mask, &mut values)?,
+                }
+                let leaf = unsafe {
+                    &mut *Arc::make_mut(&mut tree.root)
+                        .touch_leaf(&mut tree.pool, origin.as_uvec3())
+                };
+                for index in mask_iter(&value_mask) {
+                    let coords = child_offset(index, Self::vdb_extent_log2(0));
+                    leaf.set(&mut [], coords, Some(values[index]), &mut []);
+                }
+            }
+        }
+        // Leaves without active voxels were allocated above.
+        tree.prune();
+        Ok(tree)
+    }
+
+    /// Read the topology of an internal node at `level`, filling in its tiles
+    /// and recording the origins of its leaf nodes.
+    fn read_vdb_node(
+        &mut self,
+        reader: &mut GridReader<impl Read>,
+        level: usize,
+        origin: IVec3,
+        leaves: &mut Vec<IVec3>,
+    ) -> Result<()>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let fanout_log2 = Self::vdb_extent_log2(level) - Self::vdb_extent_log2(level - 1);
+        let child_extent_log2 = Self::vdb_extent_log2(level - 1);
+        let size = 1 << (3 * fanout_log2);
+        let child_mask = reader.read_mask(size)?;
+        let value_mask = reader.read_mask(size)?;
+        let mut values = vec![ROOT::Voxel::default(); size];
+        reader.read_compressed_values(&value_mask, &mut values)?;
+        for index in mask_iter(&value_mask) {
+            if mask_get(&child_mask, index) {
+                continue;
+            }
+            let tile_origin =
+                origin + (child_offset(index, fanout_log2) << child_extent_log2).as_ivec3();
+            self.fill(
+                Aabb::from_extent(tile_origin, UVec3::splat(1 << child_extent_log2)),
+                values[index],
+            );
+        }
+        for index in mask_iter(&child_mask) {
+            let child_origin =
+                origin + (child_offset(index, fanout_log2) << child_extent_log2).as_ivec3();
+            if level == 1 {
+                // The value mask of the leaf is saved again with its buffers.
+                reader.read_mask(1 << (3 * child_extent_log2))?;
+                leaves.push(child_origin);
+            } else {
+                self.read_vdb_node(reader, level - 1, child_origin, leaves)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the tree to an OpenVDB file as a single grid named `grid_name`.
+    pub fn write_vdb(&self, mut writer: impl Write, grid_name: &str) -> Result<()>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let fanouts = Self::vdb_fanouts()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "unsupported tree hierarchy"))?;
+        let mut grid_type = format!("Tree_{}", ROOT::Voxel::TYPE_NAME);
+        for fanout in fanouts {
+            grid_type += &format!("_{}", fanout);
+        }
+
+        let mut header = Vec::new();
+        MAGIC.write_to(&mut header)?;
+        FILE_VERSION.write_to(&mut header)?;
+        LIBRARY_VERSION[0].write_to(&mut header)?;
+        LIBRARY_VERSION[1].write_to(&mut header)?;
+        1_u8.write_to(&mut header)?; // has grid offsets
+        header.extend_from_slice(b"00000000-0000-0000-0000-000000000000");
+        0_u32.write_to(&mut header)?; // file metadata
+        1_i32.write_to(&mut header)?; // grid count
+        write_string(&mut header, grid_name)?;
+        write_string(&mut header, &grid_type)?;
+        write_string(&mut header, "")?; // instance parent
+
+        let mut grid = GridWriter { writer: Vec::new() };
+        GridWriter::<Vec<u8>>::COMPRESSION.write_to(&mut grid.writer)?;
+        0_u32.write_to(&mut grid.writer)?; // grid metadata
+        write_transform(&mut grid.writer)?;
+        let block_offset = self.write_vdb_topology(&mut grid)?;
+
+        let grid_position = header.len() as i64 + 3 * 8;
+        grid_position.write_to(&mut header)?;
+        (grid_position + block_offset as i64).write_to(&mut header)?;
+        (grid_position + grid.writer.len() as i64).write_to(&mut header)?;
+        writer.write_all(&header)?;
+        writer.write_all(&grid.writer)
+    }
+
+    /// Write the topology and buffers of the tree.
+    /// Returns the offset of the buffers within the grid.
+    fn write_vdb_topology(&self, grid: &mut GridWriter<Vec<u8>>) -> Result<usize>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let root_extent_log2 = Self::vdb_extent_log2(ROOT::LEVEL - 1);
+        let mut root_entries: BTreeMap<[i32; 3], VdbEntry<ROOT::LeafType, ROOT::Voxel>> =
+            BTreeMap::new();
+        self.root
+            .visit(&self.pool, IVec3::ZERO, &Self::extent(), &mut |node| {
+                let (origin, extent, entry) = match node {
+                    VisitedNode::Leaf(origin, leaf) => (
+                        origin,
+                        <ROOT::LeafType as Node>::EXTENT,
+                        VdbEntry::Leaf(leaf),
+                    ),
+                    VisitedNode::Tile(aabb, value) => {
+                        (aabb.min, aabb.extent(), VdbEntry::Tile(value))
+                    }
+                };
+                let key = ((origin >> root_extent_log2) << root_extent_log2).to_array();
+                if extent.x == 1 << root_extent_log2 {
+                    root_entries.insert(key, entry);
+                    return;
+                }
+                let mut children = match root_entries
+                    .entry(key)
+                    .or_insert_with(|| VdbEntry::Node(BTreeMap::new()))
+                {
+                    VdbEntry::Node(children) => children,
+                    _ => unreachable!(),
+                };
+                for level in (1..ROOT::LEVEL).rev() {
+                    let child_extent_log2 = Self::vdb_extent_log2(level - 1);
+                    let fanout_log2 = Self::vdb_extent_log2(level) - child_extent_log2;
+                    let offset = (origin >> child_extent_log2) & ((1 << fanout_log2) - 1);
+                    let index = child_index(offset.as_uvec3(), fanout_log2);
+                    if extent.x == 1 << child_extent_log2 {
+                        children.insert(index, entry);
+                        return;
+                    }
+                    children = match children
+                        .entry(index)
+                        .or_insert_with(|| VdbEntry::Node(BTreeMap::new()))
+                    {
+                        VdbEntry::Node(children) => children,
+                        _ => unreachable!(),
+                    };
+                }
+            });
+
+        let background = ROOT::Voxel::default();
+        1_i32.write_to(&mut grid.writer)?; // buffer count
+        background.write_to(&mut grid.writer)?;
+        let num_tiles = root_entries
+            .values()
+            .filter(|entry| matches!(entry, VdbEntry::Tile(_)))
+            .count();
+        (num_tiles as u32).write_to(&mut grid.writer)?;
+        ((root_entries.len() - num_tiles) as u32).write_to(&mut grid.writer)?;
+        for (origin, entry) in root_entries.iter() {
+            if let VdbEntry::Tile(value) = entry {
+                for x in origin {
+                    x.write_to(&mut grid.writer)?;
+                }
+                value.write_to(&mut grid.writer)?;
+                true.write_to(&mut grid.writer)?;
+            }
+        }
+        let mut leaves = Vec::new();
+        for (origin, entry) in root_entries.iter() {
+            let origin_coords = IVec3::from_array(*origin);
+            match entry {
+                VdbEntry::Tile(_) => continue,
+                VdbEntry::Node(children) => {
+                    for x in origin {
+                        x.write_to(&mut grid.writer)?;
+                    }
+                    Self::write_vdb_node(
+                        grid,
+                        children,
+                        ROOT::LEVEL - 1,
+                        origin_coords,
+                        &mut leaves,
+                    )?;
+                }
+                VdbEntry::Leaf(_) => unreachable!(),
+            }
+        }
+
+        let block_offset = grid.writer.len();
+        for leaf in leaves {
+            grid.write_mask(&leaf.value_mask)?;
+            if ROOT::Voxel::TYPE_NAME == "bool" {
+                for x in leaf.origin.to_array() {
+                    x.write_to(&mut grid.writer)?;
+                }
+                let mut data = vec![0; leaf.value_mask.len()];
+                for (index, value) in leaf.values.iter().enumerate() {
+                    if *value != ROOT::Voxel::default() {
+                        mask_set(&mut data, index);
+                    }
+                }
+                grid.write_mask(&data)?;
+            } else {
+                grid.write_compressed_values(&leaf.value_mask, &leaf.values)?;
+            }
+        }
+        Ok(block_offset)
+    }
+
+    fn write_vdb_node(
+        grid: &mut GridWriter<Vec<u8>>,
+        children: &BTreeMap<usize, VdbEntry<ROOT::LeafType, ROOT::Voxel>>,
+        level: usize,
+        origin: IVec3,
+        leaves: &mut Vec<VdbLeaf<ROOT::Voxel>>,
+    ) -> Result<()>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let child_extent_log2 = Self::vdb_extent_log2(level - 1);
+        let fanout_log2 = Self::vdb_extent_log2(level) - child_extent_log2;
+        let size = 1 << (3 * fanout_log2);
+        let mut child_mask = vec![0; mask_words(size)];
+        let mut value_mask = vec![0; mask_words(size)];
+        let mut values = vec![ROOT::Voxel::default(); size];
+        for (&index, entry) in children.iter() {
+            match entry {
+                VdbEntry::Tile(value) => {
+                    mask_set(&mut value_mask, index);
+                    values[index] = *value;
+                }
+                _ => mask_set(&mut child_mask, index),
+            }
+        }
+        grid.write_mask(&child_mask)?;
+        grid.write_mask(&value_mask)?;
+        grid.write_compressed_values(&value_mask, &values)?;
+
+        for (&index, entry) in children.iter() {
+            let child_origin =
+                origin + (child_offset(index, fanout_log2) << child_extent_log2).as_ivec3();
+            match entry {
+                VdbEntry::Tile(_) => continue,
+                VdbEntry::Node(children) => {
+                    Self::write_vdb_node(grid, children, level - 1, child_origin, leaves)?
+                }
+                VdbEntry::Leaf(leaf) => {
+                    let leaf_size = 1 << (3 * child_extent_log2);
+                    let mut leaf_data = VdbLeaf {
+                        origin: child_origin,
+                        value_mask: vec![0; mask_words(leaf_size)],
+                        values: vec![ROOT::Voxel::default(); leaf_size],
+                    };
+                    for (index, value) in leaf_data.values.iter_mut().enumerate() {
+                        let coords = child_offset(index, child_extent_log2);
+                        if let Some(voxel) = leaf.get(&[], coords, &mut []) {
+                            mask_set(&mut leaf_data.value_mask, index);
+                            *value = voxel;
+                        }
+                    }
+                    grid.write_mask(&leaf_data.value_mask)?;
+                    leaves.push(leaf_data);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keeps track of the position in the file to skip over grids.
+struct CountingReader<R> {
+    reader: R,
+    position: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = self.reader.read(buf)?;
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, UVec3};
+
+    use super::{half_to_f32, parse_grid_type};
+    use crate::{hierarchy, Aabb, Tree};
+
+    #[test]
+    fn test_parse_grid_type() {
+        assert_eq!(
+            parse_grid_type("Tree_float_5_4_3"),
+            Some(("float".to_string(), vec![5, 4, 3], false))
+        );
+        assert_eq!(
+            parse_grid_type("Tree_vec3s_5_4_3_HalfFloat"),
+            Some(("vec3s".to_string(), vec![5, 4, 3], true))
+        );
+        assert_eq!(parse_grid_type("Tree_5_4_3"), None);
+        assert_eq!(parse_grid_type("float_5_4_3"), None);
+    }
+
+    #[test]
+    fn test_half_to_f32() {
+        assert_eq!(half_to_f32(0x3c00), 1.0);
+        assert_eq!(half_to_f32(0xc000), -2.0);
+        assert_eq!(half_to_f32(0x0001), 2.0_f32.powi(-24));
+        assert_eq!(half_to_f32(0x7c00), f32::INFINITY);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut tree = Tree::<hierarchy!(#, 5, 4, 3; f32)>::new();
+        tree.fill(Aabb::new(IVec3::ZERO, IVec3::splat(4096)), 1.0);
+        tree.fill(
+            Aabb::from_extent(IVec3::splat(4096), UVec3::splat(128)),
+            2.0,
+        );
+        for i in 0..100 {
+            tree.set_value(IVec3::new(5000 + i, 4100 + i * 3, 8200), Some(i as f32));
+        }
+        tree.fill(Aabb::new(IVec3::splat(-4200), IVec3::splat(-4000)), 3.0);
+        tree.set_value(IVec3::new(-1, -2, 3), Some(4.0));
+
+        let mut data: Vec<u8> = Vec::new();
+        tree.write_vdb(&mut data, "density").unwrap();
+        let read = Tree::<hierarchy!(#, 5, 4, 3; f32)>::read_vdb(data.as_slice(), None).unwrap();
+        for (i, pool) in tree.pool.iter().enumerate() {
+            assert_eq!(pool.count(), read.pool[i].count());
+        }
+        assert_eq!(read.get_value(IVec3::new(0, 100, 4095)), Some(1.0));
+        assert_eq!(read.get_value(IVec3::new(4096, 4100, 4223)), Some(2.0));
+        assert_eq!(read.get_value(IVec3::new(4096, 4100, 4224)), None);
+        assert_eq!(read.get_value(IVec3::splat(-4200)), Some(3.0));
+        assert_eq!(read.get_value(IVec3::splat(-4001)), Some(3.0));
+        assert_eq!(read.get_value(IVec3::splat(-4000)), None);
+        assert_eq!(read.get_value(IVec3::new(-1, -2, 3)), Some(4.0));
+        for i in 0..100 {
+            let coords = IVec3::new(5000 + i, 4100 + i * 3, 8200);
+            assert_eq!(read.get_value(coords), Some(i as f32));
+            assert_eq!(
+                read.get_value(coords + IVec3::X),
+                tree.get_value(coords + IVec3::X)
+            );
+        }
+
+        assert_eq!(
+            Tree::<hierarchy!(#, 5, 4, 3; f32)>::read_vdb(data.as_slice(), Some("missing"))
+                .err()
+                .unwrap()
+                .kind(),
+            std::io::ErrorKind::NotFound
+        );
+        assert!(Tree::<hierarchy!(#, 5, 4, 3; f64)>::read_vdb(data.as_slice(), None).is_err());
+        assert!(Tree::<hierarchy!(#, 4, 3, 3; f32)>::read_vdb(data.as_slice(), None).is_err());
+    }
+
+    // The fixtures are generated by `fixtures/generate.py`, following the layout written
+    // by `io::File::write` in OpenVDB 10.0 with `COMPRESS_ZIP | COMPRESS_ACTIVE_MASK`
+    // and grid statistics. They were not written by OpenVDB, so reading files from
+    // OpenVDB, Houdini or Blender is not covered by these tests.
+
+    #[test]
+    fn test_read_openvdb_float() {
+        let data = include_bytes!("fixtures/density.vdb");
+        let tree = Tree::<hierarchy!(#, 5, 4, 3; f32)>::read_vdb(data.as_slice(), Some("density"))
+            .unwrap();
+        assert_eq!(tree.get_value(IVec3::new(1, 2, 3)), Some(0.5));
+        assert_eq!(tree.get_value(IVec3::new(1, 2, 4)), Some(-1.25));
+        // Inactive voxels with a value other than the background are skipped.
+        assert_eq!(tree.get_value(IVec3::new(2, 2, 3)), None);
+        // A tile within an internal node, and a tile of the root node.
+        assert_eq!(tree.get_value(IVec3::new(16, 0, 0)), Some(2.0));
+        assert_eq!(tree.get_value(IVec3::new(23, 7, 7)), Some(2.0));
+        assert_eq!(tree.get_value(IVec3::new(24, 0, 0)), None);
+        assert_eq!(tree.get_value(IVec3::new(8192, 4095, 0)), Some(9.0));
+        assert_eq!(tree.get_value(IVec3::new(12288, 0, 0)), None);
+        // A full leaf with zip compressed values.
+        let origin = IVec3::new(-8, 96, 0);
+        for coords in Aabb::from_extent(origin, UVec3::splat(8)).iter() {
+            let local = coords - origin;
+            let index = local.x * 64 + local.y * 8 + local.z;
+            assert_eq!(tree.get_value(coords), Some(index as f32));
+        }
+        // Voxels of both leaves and both tiles.
+        assert_eq!(tree.voxel_count(), 2 + 512 + 512 + 4096_u64.pow(3));
+        assert_eq!(
+            tree.iter_in(Aabb::new(IVec3::ZERO, IVec3::splat(128)))
+                .count(),
+            2 + 512
+        );
+    }
+
+    #[test]
+    fn test_read_openvdb_mask() {
+        let data = include_bytes!("fixtures/mask.vdb");
+        let tree = Tree::<hierarchy!(#, 5, 4, 3)>::read_vdb(data.as_slice(), None).unwrap();
+        assert_eq!(tree.get_value(IVec3::new(1, 2, 3)), Some(true));
+        assert_eq!(tree.get_value(IVec3::new(7, 7, 7)), Some(true));
+        assert_eq!(tree.get_value(IVec3::new(-1, -1, -1)), Some(true));
+        assert_eq!(tree.get_value(IVec3::new(1, 2, 4)), None);
+        assert_eq!(tree.iter().count(), 3 + 512);
+        assert_eq!(
+            tree.iter_in(Aabb::new(IVec3::new(8, 0, 0), IVec3::new(16, 8, 8)))
+                .count(),
+            512
+        );
+
+        // Mask grids can be read into trees of any value type.
+        let tree = Tree::<hierarchy!(#, 5, 4, 3; f32)>::read_vdb(data.as_slice(), None).unwrap();
+        assert_eq!(tree.get_value(IVec3::new(-1, -1, -1)), Some(1.0));
+        assert_eq!(tree.get_value(IVec3::new(15, 7, 7)), Some(1.0));
+        assert_eq!(tree.get_value(IVec3::new(16, 7, 7)), None);
+    }
+
+    #[test]
+    fn test_roundtrip_bool() {
+        let mut tree = Tree::<hierarchy!(#, 5, 4, 3)>::new();
+        tree.set_value(IVec3::new(1, 2, 3), Some(true));
+        tree.set_value(IVec3::new(1, 2, 4), Some(false));
+        tree.set_value(IVec3::new(700, 2, 4), Some(true));
+
+        let mut data: Vec<u8> = Vec::new();
+        tree.write_vdb(&mut data, "mask").unwrap();
+        let read = Tree::<hierarchy!(#, 5, 4, 3)>::read_vdb(data.as_slice(), Some("mask")).unwrap();
+        assert_eq!(read.get_value(IVec3::new(1, 2, 3)), Some(true));
+        assert_eq!(read.get_value(IVec3::new(1, 2, 4)), Some(false));
+        assert_eq!(read.get_value(IVec3::new(1, 2, 5)), None);
+        assert_eq!(read.get_value(IVec3::new(700, 2, 4)), Some(true));
+        assert_eq!(read.iter().count(), 3);
+    }
+}
@@ -0,0 +1,429 @@
+use fxhash::FxHashMap;
+use glam::{IVec3, UVec3};
+
+use crate::{Aabb, CsgOp, Node, Tree, VisitedNode};
+
+/// Leaf nodes changed since the last call to [`Tree::take_changes`], identified by the
+/// location of their first voxel. Each list is sorted and free of duplicates.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Changes {
+    /// Leaf nodes that were allocated.
+    pub created: Vec<IVec3>,
+    /// Leaf nodes that existed before and may contain different voxels now.
+    pub modified: Vec<IVec3>,
+    /// Leaf nodes that were freed, either because they became empty or because they
+    /// were replaced by a tile.
+    pub destroyed: Vec<IVec3>,
+    /// Boxes that were edited as a whole, for example by [`Tree::fill`].
+    /// Tiles within these boxes may have been added, changed or removed.
+    pub regions: Vec<Aabb>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty()
+            && self.modified.is_empty()
+            && self.destroyed.is_empty()
+            && self.regions.is_empty()
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Journal {
+    /// Dirty leaf nodes, and whether each of them existed when first marked dirty.
+    leaves: FxHashMap<IVec3, bool>,
+    regions: Vec<Aabb>,
+}
+
+impl Journal {
+    /// Mark a leaf node dirty that is known to exist and is about to be edited.
+    pub(crate) fn mark_existing_leaf(&mut self, origin: IVec3) {
+        self.leaves.entry(origin).or_insert(true);
+    }
+
+    pub(crate) fn contains_leaf(&self, origin: IVec3) -> bool {
+        self.leaves.contains_key(&origin)
+    }
+
+    /// Mark a leaf node dirty before it is edited, if it wasn't already.
+    pub(crate) fn mark_leaf(&mut self, origin: IVec3, existed: bool) {
+        self.leaves.entry(origin).or_insert(existed);
+    }
+}
+
+/// Change tracking.
+///
+/// While enabled, edits through [`Tree::set_value`], [`Tree::fill`], [`Tree::clear`],
+/// [`Tree::paste`], the CSG operations, [`Tree::iter_leaf_mut`] and the mutable accessor
+/// record the leaf nodes they touch. Reading the changes with [`Tree::take_changes`]
+/// compares them against the current tree to tell created, modified and destroyed
+/// leaf nodes apart.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Enable or disable change tracking. Disabling it discards all recorded changes.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Tree};
+    /// use glam::IVec3;
+    /// let mut tree = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+    /// tree.set_value(IVec3::new(0, 0, 0), Some(1));
+    /// tree.track_changes(true);
+    /// tree.set_value(IVec3::new(1, 0, 0), Some(1));
+    /// tree.set_value(IVec3::new(-1, 0, 0), Some(1));
+    /// let changes = tree.take_changes();
+    /// assert_eq!(changes.created, vec![IVec3::new(-4, 0, 0)]);
+    /// assert_eq!(changes.modified, vec![IVec3::new(0, 0, 0)]);
+    ///
+    /// tree.set_value(IVec3::new(-1, 0, 0), None);
+    /// assert_eq!(tree.take_changes().destroyed, vec![IVec3::new(-4, 0, 0)]);
+    /// assert!(tree.take_changes().is_empty());
+    /// ```
+    pub fn track_changes(&mut self, enabled: bool) {
+        self.journal = enabled.then(Journal::default);
+    }
+
+    pub fn is_tracking_changes(&self) -> bool {
+        self.journal.is_some()
+    }
+
+    /// Returns the changes recorded since the last call, and starts recording anew.
+    /// Returns no changes if change tracking is disabled.
+    pub fn take_changes(&mut self) -> Changes {
+        let Some(journal) = self.journal.as_mut() else {
+            return Changes::default();
+        };
+        let journal = std::mem::take(journal);
+        let mut changes = Changes {
+            regions: journal.regions,
+            ..Default::default()
+        };
+        for (origin, existed) in journal.leaves {
+            match (existed, self.has_leaf(origin)) {
+                (false, true) => changes.created.push(origin),
+                (true, true) => changes.modified.push(origin),
+                (true, false) => changes.destroyed.push(origin),
+                (false, false) => (),
+            }
+        }
+        changes.created.sort_by_key(|v| v.to_array());
+        changes.modified.sort_by_key(|v| v.to_array());
+        changes.destroyed.sort_by_key(|v| v.to_array());
+        changes
+    }
+
+    fn has_leaf(&self, coords: IVec3) -> bool {
+        self.root.probe(&self.pool, coords.as_uvec3()).1 == UVec3::ZERO
+    }
+
+    /// Mark the leaf node containing `coords` dirty before it is edited.
+    #[inline]
+    pub(crate) fn mark_leaf(&mut self, coords: IVec3) {
+        if self.journal.is_none() {
+            return;
+        }
+        let origin = coords & !<ROOT::LeafType as Node>::EXTENT_MASK.as_ivec3();
+        if self.journal.as_ref().unwrap().leaves.contains_key(&origin) {
+            return;
+        }
+        let existed = self.has_leaf(origin);
+        self.journal
+            .as_mut()
+            .unwrap()
+            .leaves
+            .insert(origin, existed);
+    }
+
+    /// Run the edit `f` confined to `aabb`, marking all leaf nodes within `aabb` dirty.
+    pub(crate) fn track_region(&mut self, aabb: Aabb, f: impl FnOnce(&mut Self)) {
+        if self.journal.is_none() {
+            f(self);
+            return;
+        }
+        let before: Vec<IVec3> = self.iter_leaf_in(aabb).map(|(origin, _)| origin).collect();
+        f(self);
+        let after: Vec<IVec3> = self.iter_leaf_in(aabb).map(|(origin, _)| origin).collect();
+        let journal = self.journal.as_mut().unwrap();
+        for origin in before {
+            journal.leaves.entry(origin).or_insert(true);
+        }
+        for origin in after {
+            // Leaf nodes not seen before the edit were created by it.
+            journal.leaves.entry(origin).or_insert(false);
+        }
+        journal.regions.push(aabb);
+    }
+
+    /// Mark the leaf nodes a CSG operation with `other` may edit dirty, before it runs.
+    pub(crate) fn mark_csg(&mut self, other: &Self, op: CsgOp) {
+        if self.journal.is_none() {
+            return;
+        }
+        let mut leaves = Vec::new();
+        let mut tiles = Vec::new();
+        other.root.visit(
+            &other.pool,
+            IVec3::ZERO,
+            &Self::extent(),
+            &mut |node| match node {
+                VisitedNode::Leaf(origin, _) => leaves.push(origin),
+                VisitedNode::Tile(aabb, _) => tiles.push(aabb),
+            },
+        );
+        for origin in leaves {
+            self.mark_leaf(origin);
+        }
+        if op == CsgOp::Intersection {
+            // Tiles of `other` keep the voxels within them. Everything else may be removed.
+            let mut leaves = Vec::new();
+            let journal = self.journal.as_mut().unwrap();
+            self.root.visit(
+                &self.pool,
+                IVec3::ZERO,
+                &Self::extent(),
+                &mut |node| match node {
+                    VisitedNode::Leaf(origin, _) => {
+                        let (value, extent) = other.root.probe(&other.pool, origin.as_uvec3());
+                        if value.is_none() || extent == UVec3::ZERO {
+                            leaves.push(origin);
+                        }
+                    }
+                    VisitedNode::Tile(aabb, _) => journal.regions.push(aabb),
+                },
+            );
+            for origin in leaves {
+                journal.mark_existing_leaf(origin);
+            }
+        } else {
+            // Tiles of `other` replace or clear the leaf nodes within them.
+            for aabb in tiles {
+                self.track_region(aabb, |_| ());
+            }
+        }
+    }
+
+    /// Mark the leaf nodes and tiles that differ from `other` dirty, before the tree
+    /// is replaced by it. Leaf nodes shared with `other` through the node pools are
+    /// known to be unchanged.
+    pub(crate) fn mark_replaced(&mut self, other: &Self) {
+        let Some(journal) = self.journal.as_mut() else {
+            return;
+        };
+        let mut leaves: FxHashMap<IVec3, *const ROOT::LeafType> = FxHashMap::default();
+        let mut tiles: FxHashMap<[IVec3; 2], ROOT::Voxel> = FxHashMap::default();
+        self.root.visit(
+            &self.pool,
+            IVec3::ZERO,
+            &Self::extent(),
+            &mut |node| match node {
+                VisitedNode::Leaf(origin, leaf) => {
+                    leaves.insert(origin, leaf);
+                }
+                VisitedNode::Tile(aabb, value) => {
+                    tiles.insert([aabb.min, aabb.max], value);
+                }
+            },
+        );
+        other.root.visit(
+            &other.pool,
+            IVec3::ZERO,
+            &Self::extent(),
+            &mut |node| match node {
+                VisitedNode::Leaf(origin, leaf) => match leaves.remove(&origin) {
+                    Some(ptr) if std::ptr::eq(ptr, leaf) => (),
+                    Some(_) => journal.mark_existing_leaf(origin),
+                    None => journal.mark_leaf(origin, false),
+                },
+                VisitedNode::Tile(aabb, value) => {
+                    if tiles.remove(&[aabb.min, aabb.max]) != Some(value) {
+                        journal.regions.push(aabb);
+                    }
+                }
+            },
+        );
+        // Whatever is left only exists in the tree.
+        for origin in leaves.into_keys() {
+            journal.mark_existing_leaf(origin);
+        }
+        for [min, max] in tiles.into_keys() {
+            journal.regions.push(Aabb::new(min, max));
+        }
+    }
+
+    /// Mark leaf nodes freed by `f` dirty, for edits that may free any leaf node.
+    pub(crate) fn track_freed(&mut self, f: impl FnOnce(&mut Self)) {
+        if self.journal.is_none() {
+            f(self);
+            return;
+        }
+        let before: Vec<IVec3> = self.iter_leaf().map(|(origin, _)| origin).collect();
+        f(self);
+        for origin in before {
+            if !self.has_leaf(origin) {
+                self.journal
+                    .as_mut()
+                    .unwrap()
+                    .leaves
+                    .entry(origin)
+                    .or_insert(true);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use crate::{hierarchy, Aabb, Tree};
+
+    type MyTree = Tree<hierarchy!(#, 2, 2; u8)>;
+
+    #[test]
+    fn test_untracked() {
+        let mut tree = MyTree::new();
+        assert!(!tree.is_tracking_changes());
+        tree.set_value(IVec3::ZERO, Some(1));
+        assert!(tree.take_changes().is_empty());
+        tree.track_changes(true);
+        assert!(tree.take_changes().is_empty());
+        tree.set_value(IVec3::ZERO, Some(2));
+        tree.track_changes(false);
+        tree.track_changes(true);
+        assert!(tree.take_changes().is_empty());
+    }
+
+    #[test]
+    fn test_track_edits() {
+        let mut tree = MyTree::new();
+        tree.set_value(IVec3::new(0, 0, 0), Some(1));
+        tree.set_value(IVec3::new(20, 0, 0), Some(1));
+        tree.set_value(IVec3::new(40, 0, 0), Some(1));
+        tree.track_changes(true);
+
+        // Creating and destroying a leaf in between is not reported.
+        tree.set_value(IVec3::new(100, 0, 0), Some(1));
+        tree.set_value(IVec3::new(100, 0, 0), None);
+        // Filling over a leaf replaces it by a tile, and creates leaves at the border.
+        tree.fill(Aabb::new(IVec3::new(16, -2, 0), IVec3::new(32, 16, 16)), 2);
+        {
+            let mut accessor = tree.accessor_mut();
+            accessor.set(IVec3::new(40, 0, 0), None);
+            accessor.set(IVec3::new(-5, 0, 0), Some(3));
+        }
+        for (origin, leaf) in tree.iter_leaf_mut() {
+            if origin == IVec3::ZERO {
+                leaf.occupancy.set(0, false);
+            }
+        }
+        tree.prune();
+
+        let changes = tree.take_changes();
+        assert_eq!(
+            changes.created,
+            vec![
+                IVec3::new(-8, 0, 0),
+                IVec3::new(16, -4, 0),
+                IVec3::new(16, -4, 4),
+                IVec3::new(16, -4, 8),
+                IVec3::new(16, -4, 12),
+                IVec3::new(20, -4, 0),
+                IVec3::new(20, -4, 4),
+                IVec3::new(20, -4, 8),
+                IVec3::new(20, -4, 12),
+                IVec3::new(24, -4, 0),
+                IVec3::new(24, -4, 4),
+                IVec3::new(24, -4, 8),
+                IVec3::new(24, -4, 12),
+                IVec3::new(28, -4, 0),
+                IVec3::new(28, -4, 4),
+                IVec3::new(28, -4, 8),
+                IVec3::new(28, -4, 12),
+            ]
+        );
+        assert!(changes.modified.is_empty());
+        assert_eq!(
+            changes.destroyed,
+            vec![
+                IVec3::new(0, 0, 0),
+                IVec3::new(20, 0, 0),
+                IVec3::new(40, 0, 0)
+            ]
+        );
+        assert_eq!(
+            changes.regions,
+            vec![Aabb::new(IVec3::new(16, -2, 0), IVec3::new(32, 16, 16))]
+        );
+        assert!(tree.take_changes().is_empty());
+    }
+
+    #[test]
+    fn test_track_csg_and_paste() {
+        let mut tree = MyTree::new();
+        tree.set_value(IVec3::new(0, 0, 0), Some(1));
+        tree.set_value(IVec3::new(20, 0, 0), Some(1));
+        tree.track_changes(true);
+
+        let mut other = MyTree::new();
+        other.set_value(IVec3::new(1, 0, 0), Some(2));
+        other.set_value(IVec3::new(-20, 0, 0), Some(2));
+        tree.union(&other);
+        let changes = tree.take_changes();
+        assert_eq!(changes.created, vec![IVec3::new(-20, 0, 0)]);
+        // Leaf nodes missing from `other` are left alone.
+        assert_eq!(changes.modified, vec![IVec3::new(0, 0, 0)]);
+
+        let mut mask = MyTree::new();
+        mask.fill(Aabb::new(IVec3::ZERO, IVec3::splat(16)), 1);
+        mask.set_value(IVec3::new(-20, 0, 0), Some(1));
+        tree.intersect(&mask);
+        let changes = tree.take_changes();
+        assert!(changes.created.is_empty());
+        assert_eq!(changes.modified, vec![IVec3::new(-20, 0, 0)]);
+        assert_eq!(changes.destroyed, vec![IVec3::new(20, 0, 0)]);
+        tree.set_value(IVec3::new(20, 0, 0), Some(1));
+        tree.take_changes();
+
+        tree.paste(
+            &other,
+            Aabb::new(IVec3::ZERO, IVec3::splat(4)),
+            IVec3::new(20, 0, 0),
+        );
+        let changes = tree.take_changes();
+        assert!(changes.created.is_empty());
+        assert_eq!(changes.modified, vec![IVec3::new(20, 0, 0)]);
+        assert_eq!(tree.get_value(IVec3::new(21, 0, 0)), Some(2));
+        assert_eq!(tree.get_value(IVec3::new(20, 0, 0)), None);
+
+        let mut other = MyTree::new();
+        other.set_value(IVec3::new(22, 0, 0), Some(3));
+        other.set_value(IVec3::new(40, 0, 0), Some(3));
+        tree.merge(other);
+        let changes = tree.take_changes();
+        assert_eq!(changes.created, vec![IVec3::new(40, 0, 0)]);
+        assert_eq!(changes.modified, vec![IVec3::new(20, 0, 0)]);
+    }
+
+    #[test]
+    fn test_track_restore() {
+        let mut tree = MyTree::new();
+        tree.set_value(IVec3::new(0, 0, 0), Some(1));
+        tree.set_value(IVec3::new(20, 0, 0), Some(1));
+        tree.fill(Aabb::new(IVec3::new(32, 0, 0), IVec3::new(48, 16, 16)), 1);
+        tree.track_changes(true);
+        let snapshot = tree.snapshot();
+
+        tree.set_value(IVec3::new(1, 0, 0), Some(2));
+        tree.set_value(IVec3::new(-20, 0, 0), Some(2));
+        tree.set_value(IVec3::new(20, 0, 0), None);
+        tree.take_changes();
+        tree.restore(&snapshot);
+        let changes = tree.take_changes();
+        assert_eq!(changes.created, vec![IVec3::new(20, 0, 0)]);
+        assert_eq!(changes.modified, vec![IVec3::new(0, 0, 0)]);
+        assert_eq!(changes.destroyed, vec![IVec3::new(-20, 0, 0)]);
+        // The tile is shared with the snapshot.
+        assert!(changes.regions.is_empty());
+    }
+}
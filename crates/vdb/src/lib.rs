@@ -13,17 +13,49 @@
 #![feature(const_intoiterator_identity)]
 #![feature(portable_simd)]
 
+mod aabb;
 mod accessor;
 mod bitmask;
+mod csg;
+mod dense;
+mod flood;
+mod io;
+mod journal;
+mod linear;
+mod lod;
+mod merge;
+mod mesh;
+mod morphology;
 mod node;
 mod pool;
+mod raycast;
+mod region;
+mod sdf;
+mod shape;
+mod snapshot;
+mod stats;
+mod surface;
+#[cfg(test)]
+mod test_utils;
 mod tree;
 
+pub use aabb::Aabb;
 pub use bitmask::BitMask;
+pub use csg::CsgOp;
+pub use io::{Serializable, VdbValue};
+pub use journal::Changes;
+pub use linear::LinearTree;
+pub use lod::{LodChain, LodValue, Reduction};
+pub use mesh::Mesh;
+pub use morphology::Connectivity;
 pub use pool::Pool;
+pub use raycast::RayHit;
+pub use shape::{Frustum, Shape, Sphere};
+pub use snapshot::Snapshot;
+pub use stats::{PoolStats, TreeStats};
 pub use tree::Tree;
 
-pub use accessor::Accessor;
+pub use accessor::{Accessor, AccessorMut};
 pub use node::*;
 
 pub extern crate self as dust_vdb;
@@ -0,0 +1,688 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    marker::PhantomData,
+};
+
+use fxhash::FxHashMap;
+use glam::{IVec3, UVec3};
+
+use crate::{tree::TreeMeta, Node, NodeConst, Serializable, Tree, VisitedNode};
+
+const MAGIC: u32 = u32::from_le_bytes(*b"DVLN");
+const VERSION: u32 = 1;
+/// Number of words before the level descriptors.
+const HEADER_SIZE: usize = 9;
+/// Number of words of each level descriptor.
+const LEVEL_SIZE: usize = 9;
+/// Number of words of each root entry.
+const ROOT_ENTRY_SIZE: usize = 4;
+/// Set on root entries referring to a tile value instead of a node.
+const TILE_BIT: u32 = 1 << 31;
+
+/// Index of the cell at `offset` along a Z-order curve through a grid with an extent of
+/// `1 << log2`. Bits are interleaved from the least significant bit up, in z, y, x order.
+/// Axes with fewer bits drop out once their bits are used up.
+pub fn morton(offset: UVec3, log2: UVec3) -> u128 {
+    let mut code = 0_u128;
+    let mut n = 0;
+    for bit in 0..log2.max_element() {
+        for axis in [2, 1, 0] {
+            if bit < log2[axis] {
+                code |= (((offset[axis] >> bit) & 1) as u128) << n;
+                n += 1;
+            }
+        }
+    }
+    code
+}
+
+/// Morton code of the root entry at `origin`, ordering signed coordinates from the most
+/// negative to the most positive.
+fn root_key(origin: IVec3) -> u128 {
+    morton(origin.as_uvec3() ^ UVec3::splat(1 << 31), UVec3::splat(32))
+}
+
+/// Number of bytes written for each value.
+fn value_size<V: Serializable + Default>() -> usize {
+    let mut writer = Vec::new();
+    V::default().write_to(&mut writer).unwrap();
+    writer.len()
+}
+
+fn mask_words(log2: UVec3) -> usize {
+    (1_usize << (log2.x + log2.y + log2.z)).div_ceil(32)
+}
+
+fn align_down(coords: IVec3, log2: UVec3) -> IVec3 {
+    (coords.as_uvec3() & !((UVec3::ONE << log2) - UVec3::ONE)).as_ivec3()
+}
+
+/// Children of a node while building the linear tree.
+struct NodeBuilder<V> {
+    children: Vec<IVec3>,
+    tiles: Vec<(IVec3, V)>,
+}
+
+impl<V> Default for NodeBuilder<V> {
+    fn default() -> Self {
+        Self {
+            children: Vec::new(),
+            tiles: Vec::new(),
+        }
+    }
+}
+
+/// Linearization.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Flatten the tree into a single buffer without pointers, to be uploaded to the GPU
+    /// or written to disk and memory-mapped. Read it back with [`LinearTree`].
+    ///
+    /// The buffer is made of `u32` words. Values are written with [`Serializable`] into
+    /// a byte array at the end, packed into words in little-endian order.
+    /// - Header: magic `"DVLN"`, version, number of levels below the root node,
+    ///   [`Serializable::TYPE_TAG`] of the values, size of a value in bytes, number of
+    ///   root entries, word offset of the root entries, word offset of the values, and
+    ///   length of the values in bytes.
+    /// - For each level from the leaf nodes up: `extent_log2` and `fanout_log2` of the
+    ///   nodes as 3 words each, number of nodes, word offset of the nodes, and size of a
+    ///   node in words.
+    /// - Root entries, sorted by the Morton code of their origin: the origin as 3 signed
+    ///   words, and either the index of a node on the top level, or the index of a tile
+    ///   value with the highest bit set.
+    /// - Internal nodes: the child mask, the tile mask, the index of the first child on
+    ///   the level below and the index of the first tile value.
+    /// - Leaf nodes: the occupancy mask and the index of the first value.
+    ///
+    /// All masks are indexed by the Morton code of the child or voxel within the node.
+    /// The children of a node are stored next to each other in Morton order, so the
+    /// child with a set bit is found at the first child plus the number of set bits
+    /// before it. The same goes for tile values and voxel values. As the nodes of each
+    /// level are ordered by their parents, each level is in Morton order as a whole.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Aabb, LinearTree, Tree};
+    /// use glam::IVec3;
+    /// let mut tree = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+    /// tree.set_value(IVec3::new(1, 2, 3), Some(1));
+    /// tree.fill(Aabb::new(IVec3::new(-16, 0, 0), IVec3::new(0, 16, 16)), 2);
+    /// let words = tree.linearize();
+    /// let linear = LinearTree::<u8>::new(&words).unwrap();
+    /// assert_eq!(linear.get(IVec3::new(1, 2, 3)), Some(1));
+    /// assert_eq!(linear.get(IVec3::new(-5, 5, 5)), Some(2));
+    /// assert_eq!(linear.get(IVec3::new(2, 2, 3)), None);
+    /// ```
+    pub fn linearize(&self) -> Vec<u32>
+    where
+        ROOT: ~const NodeConst,
+        ROOT::Voxel: Serializable,
+    {
+        let levels = ROOT::LEVEL;
+        let metas = &<Self as TreeMeta<ROOT>>::METAS;
+        let extent_log2 = |level: usize| metas[level].extent_log2;
+
+        // Nodes by level and origin. The root node is the only node on its level.
+        let mut nodes: Vec<FxHashMap<IVec3, NodeBuilder<ROOT::Voxel>>> =
+            (0..=levels).map(|_| FxHashMap::default()).collect();
+        let mut leaves = FxHashMap::default();
+        let parent_of = |level: usize, origin: IVec3| {
+            if level == levels {
+                IVec3::ZERO
+            } else {
+                align_down(origin, extent_log2(level))
+            }
+        };
+        // Add the parents of a new node on `level` up to the root node.
+        let add_parents = |nodes: &mut Vec<FxHashMap<_, NodeBuilder<_>>>, level, origin| {
+            let mut child = origin;
+            for level in level..levels {
+                let parent = parent_of(level + 1, child);
+                let is_new = !nodes[level + 1].contains_key(&parent);
+                nodes[level + 1]
+                    .entry(parent)
+                    .or_default()
+                    .children
+                    .push(child);
+                if !is_new {
+                    break;
+                }
+                child = parent;
+            }
+        };
+        self.root.visit(
+            &self.pool,
+            IVec3::ZERO,
+            &Self::extent(),
+            &mut |node| match node {
+                VisitedNode::Leaf(origin, leaf) => {
+                    leaves.insert(origin, leaf);
+                    add_parents(&mut nodes, 0, origin);
+                }
+                VisitedNode::Tile(aabb, value) => {
+                    // Tiles are held by the lowest level with children at least as large.
+                    let extent = aabb.extent().as_u64vec3();
+                    let level = (1..=levels)
+                        .find(|level| {
+                            let child_extent = (UVec3::ONE << extent_log2(level - 1)).as_u64vec3();
+                            child_extent.cmpge(extent).all()
+                        })
+                        .unwrap();
+                    let parent = parent_of(level, aabb.min);
+                    let is_new = !nodes[level].contains_key(&parent);
+                    nodes[level]
+                        .entry(parent)
+                        .or_default()
+                        .tiles
+                        .push((aabb.min, value));
+                    if is_new && level < levels {
+                        add_parents(&mut nodes, level, parent);
+                    }
+                }
+            },
+        );
+
+        // Order the nodes of each level by their parents, and their children by Morton code.
+        let child_key = |level: usize, child: IVec3| {
+            if level == levels {
+                root_key(child)
+            } else {
+                let local = child.as_uvec3() & ((UVec3::ONE << extent_log2(level)) - UVec3::ONE);
+                morton(local >> extent_log2(level - 1), metas[level].fanout_log2)
+            }
+        };
+        let mut order: Vec<Vec<IVec3>> = vec![Vec::new(); levels + 1];
+        order[levels].push(IVec3::ZERO);
+        let value_size = value_size::<ROOT::Voxel>();
+        let mut values = Vec::new();
+        let write_value = |values: &mut Vec<u8>, value: &ROOT::Voxel| {
+            let index = values.len() / value_size.max(1);
+            value.write_to(values).unwrap();
+            index as u32
+        };
+
+        let mut words = vec![0_u32; HEADER_SIZE + LEVEL_SIZE * levels];
+        // Encoded internal nodes of each level, in the order of `order`.
+        let mut internal_nodes: Vec<Vec<u32>> = vec![Vec::new(); levels];
+        let mut root_entries = Vec::new();
+        for level in (1..=levels).rev() {
+            let (lower, upper) = order.split_at_mut(level);
+            for parent in upper[0].iter() {
+                let node = nodes[level].remove(parent).unwrap_or_default();
+                let mut children: Vec<(u128, IVec3)> = node
+                    .children
+                    .iter()
+                    .map(|child| (child_key(level, *child), *child))
+                    .collect();
+                children.sort_unstable_by_key(|(key, _)| *key);
+                let mut tiles: Vec<(u128, IVec3, ROOT::Voxel)> = node
+                    .tiles
+                    .iter()
+                    .map(|(min, value)| (child_key(level, *min), *min, *value))
+                    .collect();
+                tiles.sort_unstable_by_key(|(key, _, _)| *key);
+
+                let first_child = lower[level - 1].len() as u32;
+                let first_tile = values.len() as u32 / value_size.max(1) as u32;
+                if level == levels {
+                    // The root node refers to each child directly.
+                    let mut entries: Vec<(u128, IVec3, u32)> = Vec::new();
+                    for (i, (key, child)) in children.iter().enumerate() {
+                        entries.push((*key, *child, first_child + i as u32));
+                    }
+                    for (key, min, value) in tiles.iter() {
+                        entries.push((*key, *min, write_value(&mut values, value) | TILE_BIT));
+                    }
+                    entries.sort_unstable_by_key(|(key, _, _)| *key);
+                    root_entries = entries;
+                } else {
+                    let num_words = mask_words(metas[level].fanout_log2);
+                    let mut child_mask = vec![0_u32; num_words];
+                    let mut tile_mask = vec![0_u32; num_words];
+                    for (key, _) in children.iter() {
+                        child_mask[*key as usize / 32] |= 1 << (*key % 32);
+                    }
+                    for (key, _, value) in tiles.iter() {
+                        tile_mask[*key as usize / 32] |= 1 << (*key % 32);
+                        write_value(&mut values, value);
+                    }
+                    let node = &mut internal_nodes[level];
+                    node.extend(child_mask);
+                    node.extend(tile_mask);
+                    node.push(first_child);
+                    node.push(first_tile);
+                }
+                lower[level - 1].extend(children.iter().map(|(_, child)| *child));
+            }
+        }
+
+        // Root entries.
+        let root_offset = words.len();
+        for (_, origin, reference) in root_entries.iter() {
+            words.extend(origin.to_array().map(|x| x as u32));
+            words.push(*reference);
+        }
+
+        // Nodes from the top level down.
+        let mut level_offsets = vec![0; levels];
+        for level in (1..levels).rev() {
+            level_offsets[level] = words.len();
+            words.extend(internal_nodes[level].iter());
+        }
+        level_offsets[0] = words.len();
+        let leaf_log2 = extent_log2(0);
+        let num_words = mask_words(leaf_log2);
+        for origin in order[0].iter() {
+            let leaf = leaves[origin];
+            let mut voxels: Vec<(u128, ROOT::Voxel)> = leaf
+                .iter(&[], *origin)
+                .map(|coords| {
+                    let local = (coords - *origin).as_uvec3();
+                    (
+                        morton(local, leaf_log2),
+                        leaf.get(&[], local, &mut []).unwrap(),
+                    )
+                })
+                .collect();
+            voxels.sort_unstable_by_key(|(key, _)| *key);
+            let mut occupancy = vec![0_u32; num_words];
+            let mut first_value = None;
+            for (key, value) in voxels.iter() {
+                occupancy[*key as usize / 32] |= 1 << (*key % 32);
+                let index = write_value(&mut values, value);
+                first_value.get_or_insert(index);
+            }
+            words.extend(occupancy);
+            words.push(first_value.unwrap_or(0));
+        }
+
+        let values_offset = words.len();
+        let values_len = values.len();
+        values.resize(values.len().next_multiple_of(4), 0);
+        words.extend(
+            values
+                .chunks_exact(4)
+                .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap())),
+        );
+
+        words[..HEADER_SIZE].copy_from_slice(&[
+            MAGIC,
+            VERSION,
+            levels as u32,
+            u32::from_le_bytes(ROOT::Voxel::TYPE_TAG),
+            value_size as u32,
+            root_entries.len() as u32,
+            root_offset as u32,
+            values_offset as u32,
+            values_len as u32,
+        ]);
+        for level in 0..levels {
+            let meta = &metas[level];
+            let node_size = if level == 0 {
+                mask_words(meta.extent_log2) + 1
+            } else {
+                mask_words(meta.fanout_log2) * 2 + 2
+            };
+            let descriptor = &mut words[HEADER_SIZE + LEVEL_SIZE * level..][..LEVEL_SIZE];
+            descriptor[0..3].copy_from_slice(&meta.extent_log2.to_array());
+            descriptor[3..6].copy_from_slice(&meta.fanout_log2.to_array());
+            descriptor[6] = order[level].len() as u32;
+            descriptor[7] = level_offsets[level] as u32;
+            descriptor[8] = node_size as u32;
+        }
+        words
+    }
+}
+
+/// Read-only view of a tree flattened with [`Tree::linearize`].
+/// Lookups follow the same steps a GPU shader would take.
+pub struct LinearTree<'a, V> {
+    words: &'a [u32],
+    _marker: PhantomData<V>,
+}
+
+/// A level of nodes within a [`LinearTree`].
+struct Level {
+    extent_log2: UVec3,
+    fanout_log2: UVec3,
+    offset: usize,
+    node_size: usize,
+}
+
+impl<'a, V: Serializable + Default> LinearTree<'a, V> {
+    /// Returns an error if `words` is not a linear tree of values of type `V`.
+    ///
+    /// All offsets and references within the buffer are checked up front, so that
+    /// lookups never read out of bounds. This visits every node once.
+    pub fn new(words: &'a [u32]) -> Result<Self> {
+        let invalid = |message| Error::new(ErrorKind::InvalidData, message);
+        if words.len() < HEADER_SIZE || words[0] != MAGIC {
+            return Err(invalid("not a linear tree"));
+        }
+        if words[1] != VERSION {
+            return Err(invalid("unsupported linear tree version"));
+        }
+        if words[3] != u32::from_le_bytes(V::TYPE_TAG) || words[4] as usize != value_size::<V>() {
+            return Err(invalid("linear tree has a different value type"));
+        }
+        let tree = Self {
+            words,
+            _marker: PhantomData,
+        };
+        tree.validate().map_err(invalid)?;
+        Ok(tree)
+    }
+
+    fn validate(&self) -> std::result::Result<(), &'static str> {
+        const TRUNCATED: &str = "linear tree is truncated";
+        const CORRUPTED: &str = "linear tree is corrupted";
+        let words = self.words;
+        let within = |offset: usize, len: usize| {
+            offset
+                .checked_add(len)
+                .map_or(false, |end| end <= words.len())
+        };
+        let levels = self.num_levels();
+        if levels == 0 {
+            return Err(CORRUPTED);
+        }
+        if !within(HEADER_SIZE, LEVEL_SIZE * levels) {
+            return Err(TRUNCATED);
+        }
+
+        let value_size = words[4] as usize;
+        let values_len = words[8] as usize;
+        if !within(words[7] as usize, values_len.div_ceil(4)) {
+            return Err(TRUNCATED);
+        }
+        // Values without any bytes may be referenced by any index.
+        let num_values = match value_size {
+            0 => u32::MAX as usize,
+            size => values_len / size,
+        };
+        if value_size > 0 && (0..num_values).any(|i| self.read_value(i as u32).is_err()) {
+            return Err(CORRUPTED);
+        }
+
+        for level in 0..levels {
+            let Level {
+                extent_log2,
+                fanout_log2,
+                offset,
+                node_size,
+            } = self.level(level);
+            let mask_log2 = if level == 0 { extent_log2 } else { fanout_log2 };
+            if extent_log2.max(fanout_log2).max_element() >= 32 || mask_log2.dot(UVec3::ONE) > 32 {
+                return Err(CORRUPTED);
+            }
+            let expected_size = if level == 0 {
+                mask_words(extent_log2) + 1
+            } else {
+                if self.level(level - 1).extent_log2 + fanout_log2 != extent_log2 {
+                    return Err(CORRUPTED);
+                }
+                mask_words(fanout_log2) * 2 + 2
+            };
+            if node_size != expected_size {
+                return Err(CORRUPTED);
+            }
+            if !within(offset, self.num_nodes(level) * node_size) {
+                return Err(TRUNCATED);
+            }
+        }
+
+        let num_entries = words[5] as usize;
+        let root_offset = words[6] as usize;
+        if !within(root_offset, num_entries * ROOT_ENTRY_SIZE) {
+            return Err(TRUNCATED);
+        }
+        for entry in words[root_offset..][..num_entries * ROOT_ENTRY_SIZE].chunks_exact(4) {
+            let reference = entry[3];
+            let valid = if reference & TILE_BIT != 0 {
+                ((reference & !TILE_BIT) as usize) < num_values
+            } else {
+                (reference as usize) < self.num_nodes(levels - 1)
+            };
+            if !valid {
+                return Err(CORRUPTED);
+            }
+        }
+
+        // The children and values referenced by each node have to exist.
+        for level in 0..levels {
+            let Level {
+                offset, node_size, ..
+            } = self.level(level);
+            let nodes = &words[offset..][..self.num_nodes(level) * node_size];
+            for node in nodes.chunks_exact(node_size) {
+                let valid = if level == 0 {
+                    let first_value = node[node_size - 1] as usize;
+                    first_value + count_ones(&node[..node_size - 1]) <= num_values
+                } else {
+                    let num_words = (node_size - 2) / 2;
+                    let (child_mask, tile_mask) = node[..num_words * 2].split_at(num_words);
+                    let first_child = node[num_words * 2] as usize;
+                    let first_tile = node[num_words * 2 + 1] as usize;
+                    first_child + count_ones(child_mask) <= self.num_nodes(level - 1)
+                        && first_tile + count_ones(tile_mask) <= num_values
+                };
+                if !valid {
+                    return Err(CORRUPTED);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn num_levels(&self) -> usize {
+        self.words[2] as usize
+    }
+
+    /// Returns the number of nodes on `level`, with the leaf nodes on level 0.
+    pub fn num_nodes(&self, level: usize) -> usize {
+        self.words[HEADER_SIZE + LEVEL_SIZE * level + 6] as usize
+    }
+
+    fn level(&self, level: usize) -> Level {
+        let descriptor = &self.words[HEADER_SIZE + LEVEL_SIZE * level..][..LEVEL_SIZE];
+        Level {
+            extent_log2: UVec3::from_slice(&descriptor[0..3]),
+            fanout_log2: UVec3::from_slice(&descriptor[3..6]),
+            offset: descriptor[7] as usize,
+            node_size: descriptor[8] as usize,
+        }
+    }
+
+    fn read_value(&self, index: u32) -> Result<V> {
+        let size = self.words[4] as usize;
+        let values = self.words[7] as usize;
+        let start = index as usize * size;
+        let bytes: Vec<u8> = (start..start + size)
+            .map(|i| self.words[values + i / 4].to_le_bytes()[i % 4])
+            .collect();
+        V::read_from(&mut bytes.as_slice())
+    }
+
+    /// All values were read once by [`LinearTree::new`].
+    fn value(&self, index: u32) -> V {
+        self.read_value(index).unwrap()
+    }
+
+    /// Returns the value of the voxel at `coords`.
+    pub fn get(&self, coords: IVec3) -> Option<V> {
+        let levels = self.num_levels();
+        let top = self.level(levels - 1);
+        let origin = align_down(coords, top.extent_log2);
+
+        // Binary search through the root entries.
+        let root_offset = self.words[6] as usize;
+        let entries = &self.words[root_offset..][..self.words[5] as usize * ROOT_ENTRY_SIZE];
+        let entry_origin = |i: usize| {
+            IVec3::from_array([0, 1, 2].map(|axis| entries[i * ROOT_ENTRY_SIZE + axis] as i32))
+        };
+        let key = root_key(origin);
+        let (mut low, mut high) = (0, entries.len() / ROOT_ENTRY_SIZE);
+        while low < high {
+            let mid = (low + high) / 2;
+            if root_key(entry_origin(mid)) < key {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        if low == entries.len() / ROOT_ENTRY_SIZE || entry_origin(low) != origin {
+            return None;
+        }
+        let reference = entries[low * ROOT_ENTRY_SIZE + 3];
+        if reference & TILE_BIT != 0 {
+            return Some(self.value(reference & !TILE_BIT));
+        }
+
+        let mut node = reference as usize;
+        for level in (0..levels).rev() {
+            let Level {
+                extent_log2,
+                fanout_log2,
+                offset,
+                node_size,
+            } = self.level(level);
+            let words = &self.words[offset + node * node_size..][..node_size];
+            let local = coords.as_uvec3() & ((UVec3::ONE << extent_log2) - UVec3::ONE);
+            if level == 0 {
+                let index = morton(local, extent_log2) as usize;
+                let occupancy = &words[..node_size - 1];
+                if !bit(occupancy, index) {
+                    return None;
+                }
+                return Some(self.value(words[node_size - 1] + rank(occupancy, index)));
+            }
+            let offset = local >> (extent_log2 - fanout_log2);
+            let index = morton(offset, fanout_log2) as usize;
+            let num_words = (node_size - 2) / 2;
+            let (child_mask, tile_mask) = words[..num_words * 2].split_at(num_words);
+            if bit(child_mask, index) {
+                node = (words[num_words * 2] + rank(child_mask, index)) as usize;
+            } else if bit(tile_mask, index) {
+                return Some(self.value(words[num_words * 2 + 1] + rank(tile_mask, index)));
+            } else {
+                return None;
+            }
+        }
+        unreachable!()
+    }
+}
+
+fn count_ones(mask: &[u32]) -> usize {
+    mask.iter().map(|word| word.count_ones() as usize).sum()
+}
+
+fn bit(mask: &[u32], index: usize) -> bool {
+    mask[index / 32] & (1 << (index % 32)) != 0
+}
+
+/// Number of set bits before `index`.
+fn rank(mask: &[u32], index: usize) -> u32 {
+    let before: u32 = mask[..index / 32]
+        .iter()
+        .map(|word| word.count_ones())
+        .sum();
+    before + (mask[index / 32] & ((1 << (index % 32)) - 1)).count_ones()
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, UVec3};
+    use rand::prelude::*;
+
+    use super::{morton, LinearTree};
+    use crate::{hierarchy, Aabb, Tree};
+
+    #[test]
+    fn test_morton() {
+        let log2 = UVec3::splat(2);
+        assert_eq!(morton(UVec3::new(0, 0, 1), log2), 1);
+        assert_eq!(morton(UVec3::new(0, 1, 0), log2), 2);
+        assert_eq!(morton(UVec3::new(1, 0, 0), log2), 4);
+        assert_eq!(morton(UVec3::new(0, 0, 2), log2), 8);
+        assert_eq!(morton(UVec3::new(3, 3, 3), log2), 63);
+        // Anisotropic grids continue with the remaining axes.
+        let log2 = UVec3::new(1, 2, 1);
+        assert_eq!(morton(UVec3::new(0, 2, 0), log2), 8);
+        assert_eq!(morton(UVec3::new(1, 3, 1), log2), 15);
+    }
+
+    #[test]
+    fn test_linearize_random() {
+        let mut rng = rand::thread_rng();
+        let mut tree = Tree::<hierarchy!(#, 3, 2; u16)>::new();
+        for _ in 0..1000 {
+            let coords = IVec3::new(
+                rng.gen_range(-100..100),
+                rng.gen_range(-100..100),
+                rng.gen_range(-100..100),
+            );
+            tree.set_value(coords, Some(rng.gen()));
+        }
+        tree.fill(Aabb::new(IVec3::splat(-32), IVec3::splat(0)), 7);
+        tree.fill(Aabb::new(IVec3::new(32, 0, 0), IVec3::new(36, 4, 4)), 8);
+        let words = tree.linearize();
+        let linear = LinearTree::<u16>::new(&words).unwrap();
+        assert_eq!(linear.num_levels(), 2);
+        assert_eq!(linear.num_nodes(0), tree.iter_leaf().count());
+        for coords in tree.iter() {
+            assert_eq!(linear.get(coords), tree.get_value(coords));
+        }
+        for _ in 0..10000 {
+            let coords = IVec3::new(
+                rng.gen_range(-120..120),
+                rng.gen_range(-120..120),
+                rng.gen_range(-120..120),
+            );
+            assert_eq!(linear.get(coords), tree.get_value(coords));
+        }
+        assert!(LinearTree::<u8>::new(&words).is_err());
+        assert!(LinearTree::<i16>::new(&words).is_err());
+        assert!(LinearTree::<u16>::new(&words[..4]).is_err());
+        assert!(LinearTree::<u16>::new(&words[..words.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_linearize_bounded() {
+        let mut tree = Tree::<hierarchy!(2, 4, 2; u8)>::new();
+        tree.set_value(IVec3::new(1, 2, 3), Some(1));
+        tree.set_value(IVec3::new(60, 2, 3), Some(2));
+        tree.fill(Aabb::new(IVec3::new(16, 16, 16), IVec3::new(32, 32, 32)), 3);
+        let words = tree.linearize();
+        let linear = LinearTree::<u8>::new(&words).unwrap();
+        for coords in Aabb::new(IVec3::ZERO, IVec3::splat(64)).iter() {
+            assert_eq!(linear.get(coords), tree.get_value(coords));
+        }
+    }
+
+    #[test]
+    fn test_linear_corrupted() {
+        let mut tree = Tree::<hierarchy!(#, 2, 2; bool)>::new();
+        tree.set_value(IVec3::new(1, 2, 3), Some(true));
+        tree.fill(Aabb::new(IVec3::new(16, 0, 0), IVec3::new(20, 4, 4)), false);
+        let words = tree.linearize();
+        assert!(LinearTree::<bool>::new(&words).is_ok());
+        let corrupt = |index: usize, word: u32| {
+            let mut words = words.clone();
+            words[index] = word;
+            LinearTree::<bool>::new(&words).is_err()
+        };
+        // Number of levels, offset of the root entries, and a level descriptor.
+        assert!(corrupt(2, 100));
+        assert!(corrupt(6, u32::MAX));
+        assert!(corrupt(9, 40));
+        // The node referenced by the root entry, and the first value of the leaf node.
+        let root_offset = words[6] as usize;
+        assert!(corrupt(root_offset + 3, 5));
+        let leaf_offset = words[9 + 7] as usize;
+        assert!(corrupt(leaf_offset + 2, 2));
+        // A boolean value other than 0 or 1.
+        let values_offset = words[7] as usize;
+        assert!(corrupt(values_offset, 2));
+    }
+}
@@ -0,0 +1,395 @@
+use fxhash::FxHashMap;
+use glam::{I64Vec3, IVec3};
+
+use crate::{Aabb, Node, NodeConst, Tree, VisitedNode};
+
+/// How the voxels of a block are combined into a single voxel when down-sampling.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Reduction {
+    /// The voxel is occupied if any voxel of the block is occupied.
+    /// Its value is the most common value within the block.
+    AnyOccupied,
+    /// The voxel is occupied if more than half of the block is occupied.
+    /// Its value is the most common value within the block.
+    Majority,
+    /// The voxel is occupied if any voxel of the block is occupied.
+    /// Its value is the average of the occupied voxels, see [`LodValue::average`].
+    Average,
+}
+
+/// Voxel values that can be down-sampled.
+pub trait LodValue: Copy + PartialEq {
+    /// Combine `(value, weight)` pairs into a single value. `samples` is never empty.
+    fn average(samples: &[(Self, u32)]) -> Self;
+}
+
+impl LodValue for bool {
+    fn average(samples: &[(Self, u32)]) -> Self {
+        let (mut true_weight, mut false_weight) = (0, 0);
+        for (value, weight) in samples {
+            if *value {
+                true_weight += weight;
+            } else {
+                false_weight += weight;
+            }
+        }
+        true_weight >= false_weight
+    }
+}
+
+macro_rules! impl_lod_value {
+    ($($t: ty),*) => {
+        $(
+            impl LodValue for $t {
+                fn average(samples: &[(Self, u32)]) -> Self {
+                    let mut sum = 0.0_f64;
+                    let mut total = 0.0_f64;
+                    for (value, weight) in samples {
+                        sum += *value as f64 * *weight as f64;
+                        total += *weight as f64;
+                    }
+                    (sum / total) as $t
+                }
+            }
+        )*
+    };
+}
+impl_lod_value!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl LodValue for f32 {
+    fn average(samples: &[(Self, u32)]) -> Self {
+        f64::average(
+            &samples
+                .iter()
+                .map(|(value, weight)| (*value as f64, *weight))
+                .collect::<Vec<_>>(),
+        ) as f32
+    }
+}
+
+impl LodValue for f64 {
+    fn average(samples: &[(Self, u32)]) -> Self {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        for (value, weight) in samples {
+            sum += *value * *weight as f64;
+            total += *weight as f64;
+        }
+        sum / total
+    }
+}
+
+/// Down-sampling.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+    ROOT::Voxel: LodValue,
+{
+    /// Returns the tree at `1 / 2^log2` of the resolution. Each voxel of the new tree
+    /// covers a block of `2^log2` voxels along each axis, starting at `coords << log2`.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Aabb, Reduction, Tree};
+    /// use glam::IVec3;
+    /// let mut tree = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+    /// tree.fill(Aabb::new(IVec3::ZERO, IVec3::new(2, 2, 1)), 4);
+    /// tree.set_value(IVec3::new(-1, 0, 0), Some(10));
+    ///
+    /// let lod = tree.downsample(1, Reduction::AnyOccupied);
+    /// assert_eq!(lod.get_value(IVec3::new(0, 0, 0)), Some(4));
+    /// assert_eq!(lod.get_value(IVec3::new(-1, 0, 0)), Some(10));
+    ///
+    /// let lod = tree.downsample(1, Reduction::Majority);
+    /// assert_eq!(lod.get_value(IVec3::new(0, 0, 0)), None);
+    /// assert_eq!(lod.get_value(IVec3::new(-1, 0, 0)), None);
+    ///
+    /// tree.set_value(IVec3::new(0, 0, 1), Some(8));
+    /// let lod = tree.downsample(1, Reduction::Average);
+    /// assert_eq!(lod.get_value(IVec3::new(0, 0, 0)), Some(4 * 4 / 5 + 8 / 5));
+    /// ```
+    pub fn downsample(&self, log2: u32, reduction: Reduction) -> Self
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut lod = Self::new();
+        lod.downsample_from(self, Self::extent(), log2, reduction);
+        lod
+    }
+
+    /// Replace the voxels of the tree covering `src_aabb` with the down-sampled voxels
+    /// of `src`. `src_aabb` is extended to whole blocks of `2^log2` voxels.
+    pub fn downsample_from(&mut self, src: &Self, src_aabb: Aabb, log2: u32, reduction: Reduction)
+    where
+        ROOT: ~const NodeConst,
+    {
+        assert!(log2 < 32);
+        let extent = Self::extent();
+        let src_aabb = src_aabb.intersect(&extent);
+        if src_aabb.is_empty() {
+            return;
+        }
+        let block_min = src_aabb.min >> log2;
+        let block_max = ((src_aabb.max - IVec3::ONE) >> log2) + IVec3::ONE;
+        let src_aabb = Aabb::new(
+            clamp_i64(block_min.as_i64vec3() << log2 as i64),
+            clamp_i64(block_max.as_i64vec3() << log2 as i64),
+        )
+        .intersect(&extent);
+        self.clear(Aabb::new(block_min, block_max));
+
+        let block_size = 1_i32 << log2;
+        // Ties between the most common values are broken by the first voxel, so that the
+        // result does not depend on the layout of the tree.
+        let mut blocks: FxHashMap<IVec3, Samples<ROOT::Voxel>> = FxHashMap::default();
+        let mut add_sample = |coords: IVec3, value: ROOT::Voxel, weight: u32| {
+            let samples = blocks.entry(coords >> log2).or_default();
+            match samples.iter_mut().find(|(v, _, _)| *v == value) {
+                Some((_, w, first)) => {
+                    *w += weight;
+                    *first = (*first).min(coords.to_array());
+                }
+                None => samples.push((value, weight, coords.to_array())),
+            }
+        };
+        let mut tiles = Vec::new();
+        src.root
+            .visit(&src.pool, IVec3::ZERO, &src_aabb, &mut |node| match node {
+                VisitedNode::Tile(aabb, value) => {
+                    let aabb = aabb.intersect(&src_aabb);
+                    let aligned = (aabb.min & (block_size - 1)) == IVec3::ZERO
+                        && (aabb.max.as_i64vec3() & (block_size as i64 - 1)) == I64Vec3::ZERO;
+                    let (min, max) = (aabb.min >> log2, ((aabb.max - IVec3::ONE) >> log2) + 1);
+                    if aligned {
+                        // Whole blocks within the tile keep the value of the tile.
+                        tiles.push((Aabb::new(min, max), value));
+                        return;
+                    }
+                    for x in min.x..max.x {
+                        for y in min.y..max.y {
+                            for z in min.z..max.z {
+                                let block = IVec3::new(x, y, z);
+                                let block_aabb = Aabb::new(
+                                    block << log2,
+                                    clamp_i64((block.as_i64vec3() + 1) << log2 as i64),
+                                );
+                                let overlap = block_aabb.intersect(&aabb);
+                                let extent = overlap.extent();
+                                add_sample(overlap.min, value, extent.x * extent.y * extent.z);
+                            }
+                        }
+                    }
+                }
+                VisitedNode::Leaf(origin, leaf) => {
+                    for local in leaf.iter(&[], IVec3::ZERO) {
+                        let coords = origin + local;
+                        if !src_aabb.contains(coords) {
+                            continue;
+                        }
+                        let value = leaf.get(&[], local.as_uvec3(), &mut []).unwrap();
+                        add_sample(coords, value, 1);
+                    }
+                }
+            });
+
+        for (aabb, value) in tiles {
+            self.fill(aabb, value);
+        }
+        let mut accessor = self.accessor_mut();
+        let total = 1_u64 << (3 * log2);
+        for (block, samples) in blocks {
+            let occupied: u64 = samples.iter().map(|(_, weight, _)| *weight as u64).sum();
+            if reduction == Reduction::Majority && occupied * 2 <= total {
+                continue;
+            }
+            let value = if reduction == Reduction::Average {
+                let samples: Vec<_> = samples.iter().map(|(v, w, _)| (*v, *w)).collect();
+                ROOT::Voxel::average(&samples)
+            } else {
+                let (value, _, _) = samples
+                    .iter()
+                    .max_by(|(_, a, a_first), (_, b, b_first)| a.cmp(b).then(b_first.cmp(a_first)))
+                    .unwrap();
+                *value
+            };
+            accessor.set(block, Some(value));
+        }
+    }
+}
+
+/// Values within a block with their weights and the first voxel they were found at.
+type Samples<V> = Vec<(V, u32, [i32; 3])>;
+
+fn clamp_i64(v: I64Vec3) -> IVec3 {
+    v.clamp(
+        I64Vec3::splat(i32::MIN as i64),
+        I64Vec3::splat(i32::MAX as i64),
+    )
+    .as_ivec3()
+}
+
+/// A tree with a chain of down-sampled copies, each at half the resolution of the previous.
+/// Edits made through the chain are propagated to all levels of detail.
+pub struct LodChain<ROOT: Node>
+where
+    [(); ROOT::LEVEL as usize]: Sized,
+{
+    levels: Vec<Tree<ROOT>>,
+    reduction: Reduction,
+}
+
+impl<ROOT: Node> LodChain<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+    ROOT::Voxel: LodValue,
+{
+    /// Build `count` levels of detail below `tree`.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, LodChain, Reduction, Tree};
+    /// use glam::IVec3;
+    /// let mut chain = LodChain::new(Tree::<hierarchy!(#, 2, 2)>::new(), 2, Reduction::AnyOccupied);
+    /// chain.set_value(IVec3::new(5, 6, 7), Some(true));
+    /// assert_eq!(chain.level(1).get_value(IVec3::new(2, 3, 3)), Some(true));
+    /// assert_eq!(chain.level(2).get_value(IVec3::new(1, 1, 1)), Some(true));
+    /// chain.set_value(IVec3::new(5, 6, 7), None);
+    /// assert!(chain.level(2).iter().next().is_none());
+    /// ```
+    pub fn new(tree: Tree<ROOT>, count: usize, reduction: Reduction) -> Self
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut levels = vec![tree];
+        for i in 0..count {
+            let lod = levels[i].downsample(1, reduction);
+            levels.push(lod);
+        }
+        Self { levels, reduction }
+    }
+
+    /// Number of levels, including the full resolution tree.
+    pub fn num_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Returns the tree at `1 / 2^lod` of the full resolution.
+    pub fn level(&self, lod: usize) -> &Tree<ROOT> {
+        &self.levels[lod]
+    }
+
+    /// Returns the full resolution tree for editing.
+    /// Call [`LodChain::update`] with the edited region afterwards.
+    pub fn tree_mut(&mut self) -> &mut Tree<ROOT> {
+        &mut self.levels[0]
+    }
+
+    pub fn set_value(&mut self, coords: IVec3, value: Option<ROOT::Voxel>)
+    where
+        ROOT: ~const NodeConst,
+    {
+        self.levels[0].set_value(coords, value);
+        self.update(Aabb::new(coords, coords + IVec3::ONE));
+    }
+
+    /// Recompute the levels of detail after editing the full resolution tree within `aabb`.
+    pub fn update(&mut self, mut aabb: Aabb)
+    where
+        ROOT: ~const NodeConst,
+    {
+        for i in 1..self.levels.len() {
+            let (finer, coarser) = self.levels.split_at_mut(i);
+            coarser[0].downsample_from(&finer[i - 1], aabb, 1, self.reduction);
+            aabb = Aabb::new(aabb.min >> 1, ((aabb.max - IVec3::ONE) >> 1) + IVec3::ONE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+    use rand::prelude::*;
+
+    use super::{LodChain, LodValue, Reduction};
+    use crate::{hierarchy, Aabb, Tree};
+
+    type MyTree = Tree<hierarchy!(#, 2, 2; u8)>;
+
+    /// Compares all voxels, including the voxels of tiles written by the downsampling.
+    fn assert_same(a: &MyTree, b: &MyTree) {
+        let voxels = |tree: &MyTree| -> Vec<_> {
+            tree.iter_in(MyTree::extent())
+                .map(|c| (c.to_array(), tree.get_value(c)))
+                .collect()
+        };
+        let (mut a_voxels, mut b_voxels) = (voxels(a), voxels(b));
+        a_voxels.sort_by_key(|(c, _)| *c);
+        b_voxels.sort_by_key(|(c, _)| *c);
+        assert_eq!(a_voxels, b_voxels);
+    }
+
+    #[test]
+    fn test_average() {
+        assert_eq!(u8::average(&[(2, 1), (4, 3)]), 3);
+        assert_eq!(f32::average(&[(1.0, 1), (2.0, 1)]), 1.5);
+        assert!(bool::average(&[(true, 2), (false, 2)]));
+        assert!(!bool::average(&[(true, 1), (false, 2)]));
+    }
+
+    #[test]
+    fn test_downsample_tiles() {
+        let mut tree = MyTree::new();
+        tree.fill(Aabb::new(IVec3::splat(-32), IVec3::splat(32)), 1);
+        tree.fill(Aabb::new(IVec3::splat(40), IVec3::splat(41)), 2);
+        for reduction in [
+            Reduction::AnyOccupied,
+            Reduction::Majority,
+            Reduction::Average,
+        ] {
+            let lod = tree.downsample(3, reduction);
+            assert_eq!(lod.get_value(IVec3::splat(-4)), Some(1));
+            assert_eq!(lod.get_value(IVec3::splat(3)), Some(1));
+            assert_eq!(lod.get_value(IVec3::splat(4)), None);
+            let expected = (reduction != Reduction::Majority).then_some(2);
+            assert_eq!(lod.get_value(IVec3::splat(5)), expected);
+        }
+    }
+
+    #[test]
+    fn test_lod_chain() {
+        let mut rng = rand::thread_rng();
+        let mut tree = MyTree::new();
+        tree.fill(Aabb::new(IVec3::splat(-16), IVec3::splat(0)), 1);
+        for _ in 0..500 {
+            let coords = IVec3::new(
+                rng.gen_range(-20..20),
+                rng.gen_range(-20..20),
+                rng.gen_range(-20..20),
+            );
+            tree.set_value(coords, Some(rng.gen_range(1..4)));
+        }
+        for reduction in [
+            Reduction::AnyOccupied,
+            Reduction::Majority,
+            Reduction::Average,
+        ] {
+            let mut chain = LodChain::new(tree.extract(MyTree::extent()), 3, reduction);
+            for _ in 0..100 {
+                let coords = IVec3::new(
+                    rng.gen_range(-20..20),
+                    rng.gen_range(-20..20),
+                    rng.gen_range(-20..20),
+                );
+                let value = rng.gen::<bool>().then_some(5);
+                chain.set_value(coords, value);
+            }
+            let min = IVec3::new(-3, 2, 5);
+            chain.tree_mut().fill(Aabb::new(min, min + 10), 6);
+            chain.update(Aabb::new(min, min + 10));
+
+            let rebuilt = LodChain::new(chain.level(0).extract(MyTree::extent()), 3, reduction);
+            assert_eq!(chain.num_levels(), 4);
+            for i in 0..chain.num_levels() {
+                assert_same(chain.level(i), rebuilt.level(i));
+            }
+        }
+    }
+}
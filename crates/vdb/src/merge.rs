@@ -0,0 +1,241 @@
+use std::sync::Arc;
+
+use glam::{IVec3, UVec3};
+
+use crate::{tree::TreeMeta, Aabb, CsgOp, Node, NodeConst, Tree};
+
+/// Parallel construction by partitioning and merging.
+///
+/// Trees are not shared between threads while being written. Instead, split the region
+/// with [`Tree::partition`], populate one tree per part on each thread, and combine
+/// them with [`Tree::merge_all`]. Parts aligned to the nodes at `level` never share
+/// nodes at or below `level`, so merging moves whole subtrees without combining leaves.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Split `aabb` into boxes aligned to the extent of the nodes at `level`.
+    /// `level` must be below the root level.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Aabb, Tree};
+    /// use glam::IVec3;
+    /// type MyTree = Tree<hierarchy!(#, 2, 2; u8)>;
+    /// let parts = MyTree::partition(Aabb::new(IVec3::new(-2, 0, 0), IVec3::new(20, 4, 4)), 1);
+    /// assert_eq!(parts.len(), 3);
+    /// assert_eq!(parts[0], Aabb::new(IVec3::new(-2, 0, 0), IVec3::new(0, 4, 4)));
+    /// assert_eq!(parts[2], Aabb::new(IVec3::new(16, 0, 0), IVec3::new(20, 4, 4)));
+    /// ```
+    pub fn partition(aabb: Aabb, level: usize) -> Vec<Aabb>
+    where
+        ROOT: ~const NodeConst,
+    {
+        assert!(level < ROOT::LEVEL, "Can not partition at the root level");
+        let aabb = aabb.intersect(&Self::extent());
+        if aabb.is_empty() {
+            return Vec::new();
+        }
+        let extent_log2 = Self::METAS[level].extent_log2;
+        let first = aabb.min >> extent_log2.as_ivec3();
+        let last = (aabb.max - IVec3::ONE) >> extent_log2.as_ivec3();
+        let mut parts = Vec::new();
+        for x in first.x..=last.x {
+            for y in first.y..=last.y {
+                for z in first.z..=last.z {
+                    let min = IVec3::new(x, y, z) << extent_log2.as_ivec3();
+                    let part = Aabb::from_extent(min, UVec3::ONE << extent_log2);
+                    parts.push(part.intersect(&aabb));
+                }
+            }
+        }
+        parts
+    }
+
+    /// Move all voxels of `other` into the tree.
+    /// When both trees contain a voxel, the value of `self` is kept.
+    ///
+    /// The node pools of `other` are appended to the pools of the tree, so subtrees
+    /// present in only one of the trees are moved over without copying any leaf nodes.
+    pub fn merge(&mut self, mut other: Self) {
+        if self.root.is_empty() {
+            self.track_region(Self::extent(), |tree| {
+                std::mem::swap(&mut tree.root, &mut other.root);
+                std::mem::swap(&mut tree.pool, &mut other.pool);
+            });
+            return;
+        }
+        self.mark_csg(&other, CsgOp::Union);
+        let Tree { mut root, pool, .. } = other;
+        let offsets: Vec<u32> = self
+            .pool
+            .iter_mut()
+            .zip(pool)
+            .map(|(pool, other_pool)| pool.append(other_pool))
+            .collect();
+        let mut root = std::mem::take(Arc::make_mut(&mut root));
+        root.relocate(&mut self.pool, &offsets);
+        Arc::make_mut(&mut self.root).merge(&mut self.pool, root);
+    }
+
+    /// Combine trees built independently, for example on different threads.
+    /// The trees are merged into the one with the most nodes, so that the
+    /// least amount of nodes needs to be relocated.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Aabb, Tree};
+    /// use glam::IVec3;
+    /// type MyTree = Tree<hierarchy!(#, 2, 2; u8)>;
+    /// let aabb = Aabb::new(IVec3::splat(-20), IVec3::splat(20));
+    /// let parts: Vec<MyTree> = std::thread::scope(|s| {
+    ///     let handles: Vec<_> = MyTree::partition(aabb, 1)
+    ///         .into_iter()
+    ///         .map(|part| {
+    ///             s.spawn(move || {
+    ///                 let mut tree = MyTree::new();
+    ///                 tree.fill(part, 1);
+    ///                 tree
+    ///             })
+    ///         })
+    ///         .collect();
+    ///     handles.into_iter().map(|h| h.join().unwrap()).collect()
+    /// });
+    /// let tree = MyTree::merge_all(parts);
+    /// assert_eq!(tree.get_value(IVec3::splat(-20)), Some(1));
+    /// assert_eq!(tree.get_value(IVec3::new(0, 19, -1)), Some(1));
+    /// assert_eq!(tree.get_value(IVec3::new(0, 20, -1)), None);
+    /// ```
+    pub fn merge_all(parts: impl IntoIterator<Item = Self>) -> Self
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut parts: Vec<Self> = parts.into_iter().collect();
+        let Some(largest) = (0..parts.len()).max_by_key(|i| parts[*i].node_count()) else {
+            return Self::new();
+        };
+        let mut tree = parts.swap_remove(largest);
+        for part in parts {
+            tree.merge(part);
+        }
+        tree
+    }
+
+    /// Number of nodes allocated from the pools.
+    fn node_count(&self) -> u32 {
+        self.pool.iter().map(|pool| pool.count()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+    use rand::prelude::*;
+
+    use crate::{hierarchy, Aabb, Tree};
+
+    type MyTree = Tree<hierarchy!(#, 2, 2; u8)>;
+
+    #[test]
+    fn test_partition() {
+        let aabb = Aabb::new(IVec3::new(-17, 3, 0), IVec3::new(40, 4, 33));
+        let parts = MyTree::partition(aabb, 1);
+        assert_eq!(parts.len(), 15);
+        let mut volume = 0;
+        for part in parts.iter() {
+            assert!(aabb.contains_aabb(part));
+            assert_eq!(part.min >> 4, (part.max - IVec3::ONE) >> 4);
+            volume += part.extent().x * part.extent().y * part.extent().z;
+        }
+        assert_eq!(volume, 57 * 33);
+        assert!(MyTree::partition(Aabb::new(IVec3::ZERO, IVec3::ZERO), 0).is_empty());
+    }
+
+    #[test]
+    fn test_merge_overlapping() {
+        let mut rng = rand::thread_rng();
+        let mut build = |value: u8| {
+            let mut tree = MyTree::new();
+            for _ in 0..300 {
+                let location = IVec3::new(
+                    rng.gen_range(-24..24),
+                    rng.gen_range(-24..24),
+                    rng.gen_range(-24..24),
+                );
+                tree.set_value(location, Some(value));
+            }
+            let min = IVec3::new(rng.gen_range(-24..0), rng.gen_range(-24..0), 0);
+            tree.fill(Aabb::new(min, min + IVec3::splat(20)), value);
+            tree
+        };
+        let parts = [build(1), build(2), build(3)];
+        let mut expected = MyTree::new();
+        let mut tree = MyTree::new();
+        tree.set_value(IVec3::new(100, 0, 0), Some(4));
+        expected.set_value(IVec3::new(100, 0, 0), Some(4));
+        for part in parts {
+            expected.union(&part);
+            tree.merge(part);
+        }
+        for (i, pool) in tree.pool.iter().enumerate() {
+            assert_eq!(pool.count(), expected.pool[i].count());
+        }
+        for coords in Aabb::new(IVec3::splat(-24), IVec3::splat(24)).iter() {
+            assert_eq!(tree.get_value(coords), expected.get_value(coords));
+        }
+        assert_eq!(tree.get_value(IVec3::new(100, 0, 0)), Some(4));
+
+        // Freed and appended nodes are reused.
+        tree.clear(Aabb::new(IVec3::splat(-24), IVec3::splat(24)));
+        for location in expected.iter() {
+            tree.set_value(location, expected.get_value(location));
+        }
+        assert_eq!(tree.iter().count(), expected.iter().count());
+    }
+
+    #[test]
+    fn test_merge_all() {
+        let aabb = Aabb::new(IVec3::splat(-40), IVec3::splat(40));
+        let mut locations = Vec::new();
+        let mut rng = rand::thread_rng();
+        for _ in 0..1000 {
+            let location = IVec3::new(
+                rng.gen_range(-40..40),
+                rng.gen_range(-40..40),
+                rng.gen_range(-40..40),
+            );
+            locations.push((location, rng.gen::<u8>()));
+        }
+        let mut expected = MyTree::new();
+        for (location, value) in locations.iter() {
+            expected.set_value(*location, Some(*value));
+        }
+
+        let parts = MyTree::partition(aabb, 1);
+        let trees: Vec<MyTree> = std::thread::scope(|s| {
+            let handles: Vec<_> = parts
+                .iter()
+                .map(|part| {
+                    let locations = &locations;
+                    s.spawn(move || {
+                        let mut tree = MyTree::new();
+                        for (location, value) in locations.iter() {
+                            if part.contains(*location) {
+                                tree.set_value(*location, Some(*value));
+                            }
+                        }
+                        tree
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let tree = MyTree::merge_all(trees);
+        for (i, pool) in tree.pool.iter().enumerate() {
+            assert_eq!(pool.count(), expected.pool[i].count());
+        }
+        for (location, _) in locations.iter() {
+            assert_eq!(tree.get_value(*location), expected.get_value(*location));
+        }
+        assert_eq!(tree.iter().count(), expected.iter().count());
+        assert!(MyTree::merge_all(Vec::new()).iter().next().is_none());
+    }
+}
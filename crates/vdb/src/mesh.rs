@@ -0,0 +1,429 @@
+use fxhash::FxHashMap;
+use glam::{IVec3, UVec3, Vec3};
+
+use crate::{
+    morphology::{face_masks, for_each_boundary_block, shift_along, stride_log2},
+    Accessor, IsLeaf, Node, NodeConst, Tree, VisitedNode,
+};
+
+/// An indexed triangle mesh extracted from a tree. Positions are in voxel space, where
+/// the voxel at `coords` covers the unit cube from `coords` to `coords + 1`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh<V> {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    /// Three vertex indices for each triangle, in counter-clockwise order when seen from
+    /// outside of the occupied voxels.
+    pub indices: Vec<u32>,
+    /// Value of the voxel each triangle belongs to, for each triangle.
+    pub materials: Vec<V>,
+}
+
+impl<V> Mesh<V> {
+    pub fn num_triangles(&self) -> usize {
+        self.materials.len()
+    }
+}
+
+/// A face between an occupied voxel and an empty voxel.
+struct Face<V> {
+    /// The occupied voxel.
+    voxel: IVec3,
+    axis: usize,
+    /// Whether the face is on the upper side of the voxel along `axis`.
+    upper: bool,
+    value: V,
+}
+
+/// The axes spanning the faces perpendicular to `axis`. The cross product of the two
+/// axes points along `axis`.
+fn face_axes(axis: usize) -> (usize, usize) {
+    ((axis + 1) % 3, (axis + 2) % 3)
+}
+
+fn unit(axis: usize) -> IVec3 {
+    let mut unit = IVec3::ZERO;
+    unit[axis] = 1;
+    unit
+}
+
+/// Mesh extraction.
+///
+/// Both modes start from the faces between occupied and empty voxels. These are found a
+/// leaf node at a time by comparing the occupancy mask of the leaf node with the mask
+/// shifted by one voxel along each axis, taking the faces of the leaf node from its
+/// neighbors. Only the boundary of tiles is visited.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Returns a blocky mesh of the occupied voxels. Neighboring faces of voxels with the
+    /// same value are merged into rectangles, and each rectangle has its own vertices
+    /// with flat normals.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Aabb, Tree};
+    /// use glam::{IVec3, Vec3};
+    /// let mut tree = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+    /// tree.fill(Aabb::new(IVec3::ZERO, IVec3::new(5, 3, 2)), 1);
+    /// let mesh = tree.mesh_greedy();
+    /// // One rectangle for each side of the box.
+    /// assert_eq!(mesh.num_triangles(), 12);
+    /// assert_eq!(mesh.positions.len(), 24);
+    /// assert!(mesh.positions.contains(&Vec3::new(5.0, 3.0, 2.0)));
+    /// assert_eq!(mesh.materials, vec![1; 12]);
+    /// ```
+    pub fn mesh_greedy(&self) -> Mesh<ROOT::Voxel> {
+        // Faces grouped by the plane they lie in.
+        type Slice<V> = FxHashMap<(i32, i32), V>;
+        let mut slices: FxHashMap<(usize, bool, i32), Slice<ROOT::Voxel>> = FxHashMap::default();
+        for face in self.exposed_faces() {
+            let (u, v) = face_axes(face.axis);
+            let plane = face.voxel[face.axis].wrapping_add(face.upper as i32);
+            slices
+                .entry((face.axis, face.upper, plane))
+                .or_default()
+                .insert((face.voxel[u], face.voxel[v]), face.value);
+        }
+        let mut keys: Vec<_> = slices.keys().copied().collect();
+        keys.sort_unstable();
+
+        let mut mesh = Mesh::default();
+        for key in keys {
+            let (axis, upper, plane) = key;
+            let (u_axis, v_axis) = face_axes(axis);
+            let mut cells = slices.remove(&key).unwrap();
+            let mut order: Vec<(i32, i32)> = cells.keys().copied().collect();
+            order.sort_unstable_by_key(|(u, v)| (*v, *u));
+            for (u, v) in order {
+                let Some(value) = cells.remove(&(u, v)) else {
+                    // Already merged into a rectangle.
+                    continue;
+                };
+                let mut width = 1;
+                while cells.get(&(u + width, v)) == Some(&value) {
+                    cells.remove(&(u + width, v));
+                    width += 1;
+                }
+                let mut height = 1;
+                while (u..u + width).all(|u| cells.get(&(u, v + height)) == Some(&value)) {
+                    for u in u..u + width {
+                        cells.remove(&(u, v + height));
+                    }
+                    height += 1;
+                }
+
+                let corner = |du: i32, dv: i32| {
+                    let mut position = Vec3::ZERO;
+                    position[axis] = plane as f32;
+                    position[u_axis] = (u + du) as f32;
+                    position[v_axis] = (v + dv) as f32;
+                    position
+                };
+                let mut corners = [
+                    corner(0, 0),
+                    corner(width, 0),
+                    corner(width, height),
+                    corner(0, height),
+                ];
+                let mut normal = unit(axis).as_vec3();
+                if !upper {
+                    corners.reverse();
+                    normal = -normal;
+                }
+                let first = mesh.positions.len() as u32;
+                mesh.positions.extend(corners);
+                mesh.normals.extend([normal; 4]);
+                mesh.indices.extend([0, 1, 2, 0, 2, 3].map(|i| first + i));
+                mesh.materials.extend([value; 2]);
+            }
+        }
+        mesh
+    }
+
+    /// Returns a smooth mesh of the occupied voxels using surface nets. Each cube between
+    /// the centers of 8 voxels with mixed occupancy gets one vertex, placed at the mean of
+    /// the crossings of its edges with the surface. Each face between an occupied and an
+    /// empty voxel becomes a quad between the vertices of the 4 cubes around it.
+    /// Normals are averaged from the triangles around each vertex.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Tree};
+    /// use glam::IVec3;
+    /// let mut tree = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+    /// tree.set_value(IVec3::ZERO, Some(1));
+    /// let mesh = tree.mesh_surface_nets();
+    /// assert_eq!(mesh.positions.len(), 8);
+    /// assert_eq!(mesh.num_triangles(), 12);
+    /// ```
+    pub fn mesh_surface_nets(&self) -> Mesh<ROOT::Voxel>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut accessor = self.accessor();
+        let mut vertices: FxHashMap<IVec3, u32> = FxHashMap::default();
+        let mut mesh = Mesh::default();
+        let mut faces = self.exposed_faces();
+        faces.sort_unstable_by_key(|face| (face.voxel.to_array(), face.axis, face.upper));
+        for face in faces {
+            let (u, v) = face_axes(face.axis);
+            let (unit_u, unit_v) = (unit(u), unit(v));
+            // The lower of the two voxels sharing the face.
+            let lower = if face.upper {
+                face.voxel
+            } else {
+                face.voxel.wrapping_sub(unit(face.axis))
+            };
+            let mut cells = [
+                lower.wrapping_sub(unit_u).wrapping_sub(unit_v),
+                lower.wrapping_sub(unit_v),
+                lower,
+                lower.wrapping_sub(unit_u),
+            ];
+            if !face.upper {
+                cells.reverse();
+            }
+            let quad = cells.map(|cell| {
+                *vertices.entry(cell).or_insert_with(|| {
+                    mesh.positions.push(cell_vertex(&mut accessor, cell));
+                    mesh.normals.push(Vec3::ZERO);
+                    mesh.positions.len() as u32 - 1
+                })
+            });
+            for triangle in [[quad[0], quad[1], quad[2]], [quad[0], quad[2], quad[3]]] {
+                let [a, b, c] = triangle.map(|i| mesh.positions[i as usize]);
+                // Weighted by the area of the triangle.
+                let normal = (b - a).cross(c - a);
+                for i in triangle {
+                    mesh.normals[i as usize] += normal;
+                }
+                mesh.indices.extend(triangle);
+                mesh.materials.push(face.value);
+            }
+        }
+        for normal in mesh.normals.iter_mut() {
+            *normal = normal.normalize_or_zero();
+        }
+        mesh
+    }
+
+    fn exposed_faces(&self) -> Vec<Face<ROOT::Voxel>> {
+        enum Source<'a, L, V> {
+            Leaf(&'a L),
+            Tile(V),
+        }
+        let log2 = <ROOT::LeafType as Node>::EXTENT_LOG2;
+        let num_words = (1_usize << (log2.x + log2.y + log2.z)) / u64::BITS as usize;
+        let full = vec![u64::MAX; num_words];
+        let empty = vec![0; num_words];
+        let mut blocks = FxHashMap::default();
+        self.root.visit(
+            &self.pool,
+            IVec3::ZERO,
+            &Self::extent(),
+            &mut |node| match node {
+                VisitedNode::Leaf(origin, leaf) => {
+                    let mut occupancy = vec![0; num_words];
+                    leaf.get_occupancy(&mut occupancy);
+                    blocks.insert(origin, (occupancy, Source::Leaf(leaf)));
+                }
+                VisitedNode::Tile(aabb, value) => {
+                    for_each_boundary_block::<ROOT::LeafType>(aabb, |origin| {
+                        blocks.insert(origin, (full.clone(), Source::Tile(value)));
+                    });
+                }
+            },
+        );
+        let occupancy_of = |origin: IVec3| -> &[u64] {
+            if let Some((occupancy, _)) = blocks.get(&origin) {
+                return occupancy;
+            }
+            match self.root.probe(&self.pool, origin.as_uvec3()) {
+                (Some(_), extent_log2) if extent_log2 != UVec3::ZERO => &full,
+                _ => &empty,
+            }
+        };
+
+        let faces_masks = face_masks::<ROOT::LeafType>();
+        let extent = <ROOT::LeafType as Node>::EXTENT.as_ivec3();
+        let mut faces = Vec::new();
+        for (origin, (occupancy, source)) in blocks.iter() {
+            for axis in 0..3 {
+                for upper in [false, true] {
+                    let mut neighbor = *origin;
+                    neighbor[axis] = if upper {
+                        origin[axis].checked_add(extent[axis])
+                    } else {
+                        origin[axis].checked_sub(extent[axis])
+                    }
+                    .unwrap_or(origin[axis]);
+                    let neighbor_occupancy =
+                        if neighbor == *origin || !Self::extent().contains(neighbor) {
+                            &empty
+                        } else {
+                            occupancy_of(neighbor)
+                        };
+                    // The occupancy of the next voxel in the direction of the face.
+                    let next = shift_along::<ROOT::LeafType>(
+                        occupancy,
+                        neighbor_occupancy,
+                        axis,
+                        upper,
+                        &faces_masks,
+                    );
+                    for (i, word) in occupancy.iter().enumerate() {
+                        let mut exposed = word & !next[i];
+                        while exposed != 0 {
+                            let index = i * 64 + exposed.trailing_zeros() as usize;
+                            exposed &= exposed - 1;
+                            let local = UVec3::from_array([0, 1, 2].map(|axis| {
+                                let shift = stride_log2::<ROOT::LeafType>(axis);
+                                ((index >> shift) & ((1 << log2[axis]) - 1)) as u32
+                            }));
+                            let value = match source {
+                                Source::Leaf(leaf) => leaf.get(&[], local, &mut []).unwrap(),
+                                Source::Tile(value) => *value,
+                            };
+                            faces.push(Face {
+                                voxel: *origin + local.as_ivec3(),
+                                axis,
+                                upper,
+                                value,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        faces
+    }
+}
+
+/// Returns the surface nets vertex of the cube between the centers of the 8 voxels from
+/// `cell` to `cell + 1`.
+fn cell_vertex<ROOT: Node + NodeConst>(accessor: &mut Accessor<ROOT>, cell: IVec3) -> Vec3
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    let corners: [bool; 8] = std::array::from_fn(|i| {
+        let offset = IVec3::new(i as i32 & 1, (i as i32 >> 1) & 1, (i as i32 >> 2) & 1);
+        accessor.get(cell.wrapping_add(offset)).is_some()
+    });
+    let mut sum = Vec3::ZERO;
+    let mut count = 0;
+    for i in 0..8 {
+        for bit in [1, 2, 4] {
+            let j = i | bit;
+            if j != i && corners[i] != corners[j] {
+                // The surface crosses the edge halfway between the voxel centers.
+                let a = IVec3::new(i as i32 & 1, (i as i32 >> 1) & 1, (i as i32 >> 2) & 1);
+                let b = IVec3::new(j as i32 & 1, (j as i32 >> 1) & 1, (j as i32 >> 2) & 1);
+                sum += (a + b).as_vec3() * 0.5;
+                count += 1;
+            }
+        }
+    }
+    cell.as_vec3() + Vec3::splat(0.5) + sum / count as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use fxhash::FxHashMap;
+    use glam::{IVec3, Vec3};
+
+    use crate::{
+        test_utils::{random_tree, TestTree},
+        Aabb,
+    };
+
+    /// Returns the number of faces between occupied and empty voxels, for each value.
+    fn count_faces(tree: &TestTree) -> FxHashMap<u8, usize> {
+        let mut counts = FxHashMap::default();
+        for coords in Aabb::new(IVec3::splat(-20), IVec3::splat(20)).iter() {
+            let Some(value) = tree.get_value(coords) else {
+                continue;
+            };
+            for axis in 0..3 {
+                for dir in [-1, 1] {
+                    let mut neighbor = coords;
+                    neighbor[axis] += dir;
+                    if tree.get_value(neighbor).is_none() {
+                        *counts.entry(value).or_default() += 1;
+                    }
+                }
+            }
+        }
+        counts
+    }
+
+    fn count_all_faces(tree: &TestTree) -> usize {
+        count_faces(tree).values().sum()
+    }
+
+    #[test]
+    fn test_greedy() {
+        let tree = random_tree(&mut rand::thread_rng(), 500, 1..3);
+        let mesh = tree.mesh_greedy();
+        assert_eq!(mesh.indices.len(), mesh.num_triangles() * 3);
+        assert_eq!(mesh.positions.len(), mesh.normals.len());
+
+        let mut area = FxHashMap::<u8, f32>::default();
+        for (triangle, material) in mesh.indices.chunks(3).zip(mesh.materials.iter()) {
+            let [a, b, c] = [0, 1, 2].map(|i| mesh.positions[triangle[i] as usize]);
+            let normal = mesh.normals[triangle[0] as usize];
+            let cross = (b - a).cross(c - a);
+            // Counter-clockwise when seen from outside.
+            assert!(cross.normalize().abs_diff_eq(normal, 1e-6));
+            *area.entry(*material).or_default() += cross.length() / 2.0;
+
+            // The voxel behind the triangle is occupied and the one in front is empty.
+            let center = (a + b + c) / 3.0;
+            let behind = (center - normal * 0.5).floor().as_ivec3();
+            let front = (center + normal * 0.5).floor().as_ivec3();
+            assert_eq!(tree.get_value(behind), Some(*material));
+            assert_eq!(tree.get_value(front), None);
+        }
+        let counts = count_faces(&tree);
+        assert_eq!(area.len(), counts.len());
+        for (value, count) in counts {
+            assert_eq!(area[&value], count as f32);
+        }
+        // Faces get merged.
+        assert!(mesh.num_triangles() < count_all_faces(&tree) * 2);
+    }
+
+    #[test]
+    fn test_surface_nets() {
+        let tree = random_tree(&mut rand::thread_rng(), 500, 1..3);
+        let mesh = tree.mesh_surface_nets();
+        assert_eq!(mesh.num_triangles(), count_all_faces(&tree) * 2);
+        for normal in mesh.normals.iter() {
+            assert!(normal.length() == 0.0 || (normal.length() - 1.0).abs() < 1e-5);
+        }
+
+        // Vertices of a sphere lie close to its surface.
+        let center = Vec3::splat(0.5);
+        let radius = 8.0;
+        let mut tree = TestTree::new();
+        for coords in Aabb::new(IVec3::splat(-10), IVec3::splat(11)).iter() {
+            if (coords.as_vec3() + 0.5).distance(center) <= radius {
+                tree.set_value(coords, Some(1));
+            }
+        }
+        let mesh = tree.mesh_surface_nets();
+        for (position, normal) in mesh.positions.iter().zip(mesh.normals.iter()) {
+            assert!((position.distance(center) - radius).abs() < 1.0);
+            let outward = (*position - center).normalize();
+            assert!(normal.dot(outward) > 0.8);
+        }
+        // Each edge is shared by two triangles.
+        let mut edges = FxHashMap::<(u32, u32), i32>::default();
+        for triangle in mesh.indices.chunks(3) {
+            for i in 0..3 {
+                let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+                *edges.entry((a.min(b), a.max(b))).or_default() += if a < b { 1 } else { -1 };
+            }
+        }
+        assert!(edges.values().all(|count| *count == 0));
+    }
+}
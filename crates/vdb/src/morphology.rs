@@ -0,0 +1,528 @@
+use std::sync::Arc;
+
+use fxhash::{FxHashMap, FxHashSet};
+use glam::IVec3;
+
+use crate::{Aabb, IsLeaf, Node, Tree, VisitedNode};
+
+/// Neighborhood of a voxel used by the morphological operations.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Connectivity {
+    /// The 6 voxels sharing a face.
+    Faces,
+    /// The 18 voxels sharing a face or an edge.
+    Edges,
+    /// The 26 voxels sharing a face, an edge or a corner.
+    Vertices,
+}
+
+impl Connectivity {
+    /// Returns the sets of axes to step along in turn. The neighborhood is the union of
+    /// the neighborhoods reached by each of the sweeps.
+    pub(crate) fn sweeps(self) -> &'static [&'static [usize]] {
+        match self {
+            Connectivity::Faces => &[&[0], &[1], &[2]],
+            Connectivity::Edges => &[&[0, 1], &[1, 2], &[0, 2]],
+            Connectivity::Vertices => &[&[0, 1, 2]],
+        }
+    }
+}
+
+/// Occupancy masks of leaf-sized blocks, in the bit order of the leaf nodes.
+type Masks = FxHashMap<IVec3, Vec<u64>>;
+
+/// Mathematical morphology.
+///
+/// These operate on the occupancy masks of whole leaf nodes. Each step shifts the masks
+/// by one voxel along an axis, carrying the bits shifted out of a leaf node into its
+/// neighbor. Larger neighborhoods are composed from steps along single axes.
+/// Tiles are expanded into leaf nodes only where their boundary changes.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Grow the occupied voxels by `iterations` voxels. New voxels are set to `value`.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Connectivity, Tree};
+    /// use glam::IVec3;
+    /// let mut tree = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+    /// tree.set_value(IVec3::new(3, 3, 3), Some(1));
+    /// tree.dilate(1, Connectivity::Faces, 2);
+    /// assert_eq!(tree.get_value(IVec3::new(3, 3, 3)), Some(1));
+    /// assert_eq!(tree.get_value(IVec3::new(4, 3, 3)), Some(2));
+    /// assert_eq!(tree.get_value(IVec3::new(3, 3, 2)), Some(2));
+    /// assert_eq!(tree.get_value(IVec3::new(4, 4, 3)), None);
+    /// assert_eq!(tree.iter().count(), 7);
+    ///
+    /// tree.erode(1, Connectivity::Faces);
+    /// assert_eq!(tree.iter().collect::<Vec<_>>(), vec![IVec3::new(3, 3, 3)]);
+    /// ```
+    pub fn dilate(&mut self, iterations: u32, connectivity: Connectivity, value: ROOT::Voxel) {
+        let mut blocks = Blocks::new(self);
+        for _ in 0..iterations {
+            blocks.step(connectivity, true);
+        }
+        let changes = blocks.into_changes(true);
+        self.apply_masks(changes, value);
+    }
+
+    /// Shrink the occupied voxels by `iterations` voxels. Voxels remain occupied only if
+    /// all their neighbors are occupied.
+    pub fn erode(&mut self, iterations: u32, connectivity: Connectivity) {
+        let mut blocks = Blocks::new(self);
+        for _ in 0..iterations {
+            blocks.step(connectivity, false);
+        }
+        let changes = blocks.into_changes(false);
+        self.apply_masks(changes, ROOT::Voxel::default());
+    }
+
+    /// Erode and then dilate the occupied voxels, removing features smaller than
+    /// `iterations` voxels. The remaining voxels keep their values.
+    pub fn open(&mut self, iterations: u32, connectivity: Connectivity) {
+        let mut blocks = Blocks::new(self);
+        for _ in 0..iterations {
+            blocks.step(connectivity, false);
+        }
+        for _ in 0..iterations {
+            blocks.step(connectivity, true);
+        }
+        let changes = blocks.into_changes(false);
+        self.apply_masks(changes, ROOT::Voxel::default());
+    }
+
+    /// Dilate and then erode the occupied voxels, filling gaps smaller than `iterations`
+    /// voxels. New voxels are set to `value`.
+    pub fn close(&mut self, iterations: u32, connectivity: Connectivity, value: ROOT::Voxel) {
+        let mut blocks = Blocks::new(self);
+        for _ in 0..iterations {
+            blocks.step(connectivity, true);
+        }
+        for _ in 0..iterations {
+            blocks.step(connectivity, false);
+        }
+        let changes = blocks.into_changes(true);
+        self.apply_masks(changes, value);
+    }
+
+    fn apply_masks(&mut self, changes: Vec<(IVec3, Vec<u64>)>, value: ROOT::Voxel) {
+        let leaf_extent = <ROOT::LeafType as Node>::EXTENT;
+        for (origin, mask) in changes {
+            if mask.iter().all(|word| *word == 0) {
+                self.clear(Aabb::from_extent(origin, leaf_extent));
+                continue;
+            }
+            self.mark_leaf(origin);
+            let leaf = unsafe {
+                &mut *Arc::make_mut(&mut self.root).touch_leaf(&mut self.pool, origin.as_uvec3())
+            };
+            leaf.set_occupancy(&mask, value);
+        }
+    }
+}
+
+/// Leaf-sized blocks taking part in an operation.
+struct Blocks<'a, ROOT: Node>
+where
+    [(); ROOT::LEVEL as usize]: Sized,
+{
+    tree: &'a Tree<ROOT>,
+    masks: Masks,
+    /// Occupancy of the leaf nodes before the operation.
+    leaves: Masks,
+    /// Blocks covered by tiles before the operation.
+    tiles: FxHashSet<IVec3>,
+    /// Bits of the voxels on the lower and upper face of a block, for each axis.
+    faces: [[Vec<u64>; 2]; 3],
+    full: Vec<u64>,
+    empty: Vec<u64>,
+}
+
+impl<'a, ROOT: Node> Blocks<'a, ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    fn new(tree: &'a Tree<ROOT>) -> Self {
+        let log2 = <ROOT::LeafType as Node>::EXTENT_LOG2;
+        let num_bits = 1_usize << (log2.x + log2.y + log2.z);
+        let num_words = num_bits / u64::BITS as usize;
+        let mut blocks = Self {
+            tree,
+            masks: Masks::default(),
+            leaves: Masks::default(),
+            tiles: FxHashSet::default(),
+            faces: face_masks::<ROOT::LeafType>(),
+            full: vec![u64::MAX; num_words],
+            empty: vec![0; num_words],
+        };
+        tree.root.visit(
+            &tree.pool,
+            IVec3::ZERO,
+            &Tree::<ROOT>::extent(),
+            &mut |node| {
+                match node {
+                    VisitedNode::Leaf(origin, leaf) => {
+                        let mut mask = vec![0; num_words];
+                        leaf.get_occupancy(&mut mask);
+                        blocks.leaves.insert(origin, mask.clone());
+                        blocks.masks.insert(origin, mask);
+                    }
+                    VisitedNode::Tile(aabb, _) => {
+                        // Only the blocks on the boundary of a tile may change.
+                        for_each_boundary_block::<ROOT::LeafType>(aabb, |origin| {
+                            blocks.tiles.insert(origin);
+                            blocks.masks.insert(origin, blocks.full.clone());
+                        });
+                    }
+                }
+            },
+        );
+        blocks
+    }
+
+    fn neighbor(origin: IVec3, axis: usize, upper: bool) -> Option<IVec3> {
+        let extent = <ROOT::LeafType as Node>::EXTENT.as_ivec3()[axis];
+        let mut neighbor = origin;
+        neighbor[axis] = if upper {
+            origin[axis].checked_add(extent)?
+        } else {
+            origin[axis].checked_sub(extent)?
+        };
+        Tree::<ROOT>::extent()
+            .contains(neighbor)
+            .then_some(neighbor)
+    }
+
+    fn is_tile(&self, origin: IVec3) -> bool {
+        if self.tiles.contains(&origin) {
+            return true;
+        }
+        let (value, extent_log2) = self.tree.root.probe(&self.tree.pool, origin.as_uvec3());
+        value.is_some() && extent_log2 != glam::UVec3::ZERO
+    }
+
+    /// Returns the mask of the neighbor of the block at `origin`.
+    fn neighbor_mask(&self, origin: IVec3, axis: usize, upper: bool) -> &[u64] {
+        let Some(neighbor) = Self::neighbor(origin, axis, upper) else {
+            return &self.empty;
+        };
+        match self.masks.get(&neighbor) {
+            Some(mask) => mask,
+            None if self.is_tile(neighbor) => &self.full,
+            None => &self.empty,
+        }
+    }
+
+    /// Add the neighbors along `axis` that a step along `axis` may change.
+    fn expand(&mut self, axis: usize, dilate: bool) {
+        let mut added = Vec::new();
+        for (origin, mask) in self.masks.iter() {
+            for (upper, face) in [(false, &self.faces[axis][0]), (true, &self.faces[axis][1])] {
+                let Some(neighbor) = Self::neighbor(*origin, axis, upper) else {
+                    continue;
+                };
+                if self.masks.contains_key(&neighbor) {
+                    continue;
+                }
+                let is_tile = self.is_tile(neighbor);
+                let changes = if dilate {
+                    // Occupied voxels on the face spread into the neighbor.
+                    !is_tile && mask.iter().zip(face).any(|(m, f)| m & f != 0)
+                } else {
+                    // Empty voxels on the face erode the neighbor.
+                    is_tile && mask.iter().zip(face).any(|(m, f)| m & f != *f)
+                };
+                if changes {
+                    added.push((neighbor, is_tile));
+                }
+            }
+        }
+        for (neighbor, is_tile) in added {
+            if is_tile {
+                self.tiles.insert(neighbor);
+                self.masks.insert(neighbor, self.full.clone());
+            } else {
+                self.masks.insert(neighbor, self.empty.clone());
+            }
+        }
+    }
+
+    /// Dilate or erode all blocks by one voxel along `axis`.
+    fn pass(&self, axis: usize, dilate: bool) -> Masks {
+        self.masks
+            .iter()
+            .map(|(origin, mask)| {
+                // The voxels above and below each voxel, taken from the neighbors
+                // on the faces of the block.
+                let lower = self.neighbor_mask(*origin, axis, false);
+                let upper = self.neighbor_mask(*origin, axis, true);
+                let above = shift_along::<ROOT::LeafType>(mask, upper, axis, true, &self.faces);
+                let below = shift_along::<ROOT::LeafType>(mask, lower, axis, false, &self.faces);
+                let result = (0..mask.len())
+                    .map(|i| {
+                        if dilate {
+                            mask[i] | above[i] | below[i]
+                        } else {
+                            mask[i] & above[i] & below[i]
+                        }
+                    })
+                    .collect();
+                (*origin, result)
+            })
+            .collect()
+    }
+
+    /// Dilate or erode all blocks by one voxel along each axis in `axes` in turn.
+    fn sweep(&mut self, axes: &[usize], dilate: bool) -> Masks {
+        for axis in axes {
+            self.expand(*axis, dilate);
+            self.masks = self.pass(*axis, dilate);
+        }
+        self.masks.clone()
+    }
+
+    fn step(&mut self, connectivity: Connectivity, dilate: bool) {
+        let initial = self.masks.clone();
+        let mut combined = Masks::default();
+        for axes in connectivity.sweeps() {
+            self.masks = initial.clone();
+            for (origin, mask) in self.sweep(axes, dilate) {
+                match combined.get_mut(&origin) {
+                    Some(combined) => {
+                        for (c, m) in combined.iter_mut().zip(mask) {
+                            // Blocks missing from a sweep are unchanged by it: empty
+                            // when dilating and tiles when eroding.
+                            if dilate {
+                                *c |= m;
+                            } else {
+                                *c &= m;
+                            }
+                        }
+                    }
+                    None => {
+                        combined.insert(origin, mask);
+                    }
+                }
+            }
+        }
+        self.masks = combined;
+    }
+
+    /// Returns the blocks with changed occupancy.
+    /// Voxels not occupied before are only added if `allow_new` is true.
+    fn into_changes(self, allow_new: bool) -> Vec<(IVec3, Vec<u64>)> {
+        let mut changes = Vec::new();
+        for (origin, mut mask) in self.masks {
+            let original = match self.leaves.get(&origin) {
+                Some(leaf) => leaf,
+                None if self.tiles.contains(&origin) => &self.full,
+                None => &self.empty,
+            };
+            if !allow_new {
+                for (m, o) in mask.iter_mut().zip(original) {
+                    *m &= o;
+                }
+            }
+            if &mask != original {
+                changes.push((origin, mask));
+            }
+        }
+        changes
+    }
+}
+
+/// Distance between neighboring voxels of a leaf node along `axis`, in bits.
+pub(crate) fn stride_log2<LEAF: Node>(axis: usize) -> u32 {
+    LEAF::EXTENT_LOG2.to_array()[axis + 1..].iter().sum()
+}
+
+/// Call `f` with the origin of each leaf-sized block on the boundary of `aabb`.
+/// The corners of `aabb` must be aligned to the leaf nodes.
+pub(crate) fn for_each_boundary_block<LEAF: Node>(aabb: Aabb, mut f: impl FnMut(IVec3)) {
+    let block_log2 = LEAF::EXTENT_LOG2.as_ivec3();
+    let min = aabb.min >> block_log2;
+    let max = (aabb.max - IVec3::ONE) >> block_log2;
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            let on_boundary = x == min.x || x == max.x || y == min.y || y == max.y;
+            let mut z = min.z;
+            while z <= max.z {
+                f(IVec3::new(x, y, z) << block_log2);
+                z = if on_boundary || z == max.z {
+                    z + 1
+                } else {
+                    max.z
+                };
+            }
+        }
+    }
+}
+
+/// Returns the bits of the voxels on the lower and upper face of a leaf node, for each
+/// axis, in the same order as the occupancy.
+pub(crate) fn face_masks<LEAF: Node>() -> [[Vec<u64>; 2]; 3] {
+    let log2 = LEAF::EXTENT_LOG2;
+    let num_bits = 1_usize << (log2.x + log2.y + log2.z);
+    let mut faces: [[Vec<u64>; 2]; 3] = Default::default();
+    for (axis, faces) in faces.iter_mut().enumerate() {
+        let shift = stride_log2::<LEAF>(axis);
+        let last = (1_usize << log2[axis]) - 1;
+        for (face, coord) in faces.iter_mut().zip([0, last]) {
+            *face = vec![0; num_bits / u64::BITS as usize];
+            for index in 0..num_bits {
+                if (index >> shift) & last == coord {
+                    face[index / 64] |= 1 << (index % 64);
+                }
+            }
+        }
+    }
+    faces
+}
+
+/// Returns the bits of the voxels next to the voxels of `mask` along `axis`, towards the
+/// upper face of the block if `upper` is true. Bits on that face are taken from
+/// `neighbor`, the mask of the adjacent block.
+pub(crate) fn shift_along<LEAF: Node>(
+    mask: &[u64],
+    neighbor: &[u64],
+    axis: usize,
+    upper: bool,
+    faces: &[[Vec<u64>; 2]; 3],
+) -> Vec<u64> {
+    let stride = 1_usize << stride_log2::<LEAF>(axis);
+    let last = (1_usize << LEAF::EXTENT_LOG2[axis]) - 1;
+    let face = &faces[axis][upper as usize];
+    let (within, across) = if upper {
+        (shift_down(mask, stride), shift_up(neighbor, stride * last))
+    } else {
+        (shift_up(mask, stride), shift_down(neighbor, stride * last))
+    };
+    (0..mask.len())
+        .map(|i| (within[i] & !face[i]) | (across[i] & face[i]))
+        .collect()
+}
+
+/// Returns the bits of `src` moved down by `k`, so that bit `i` is bit `i + k` of `src`.
+pub(crate) fn shift_down(src: &[u64], k: usize) -> Vec<u64> {
+    let (words, bits) = (k / 64, k % 64);
+    let word = |i: usize| src.get(i).copied().unwrap_or(0);
+    (0..src.len())
+        .map(|i| {
+            if bits == 0 {
+                word(i + words)
+            } else {
+                (word(i + words) >> bits) | (word(i + words + 1) << (64 - bits))
+            }
+        })
+        .collect()
+}
+
+/// Returns the bits of `src` moved up by `k`, so that bit `i` is bit `i - k` of `src`.
+pub(crate) fn shift_up(src: &[u64], k: usize) -> Vec<u64> {
+    let (words, bits) = (k / 64, k % 64);
+    let word = |i: usize, offset: usize| i.checked_sub(offset).map(|i| src[i]).unwrap_or(0);
+    (0..src.len())
+        .map(|i| {
+            if bits == 0 {
+                word(i, words)
+            } else {
+                (word(i, words) << bits) | (word(i, words + 1) >> (64 - bits))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use fxhash::FxHashSet;
+    use glam::IVec3;
+
+    use super::{shift_down, shift_up, Connectivity};
+    use crate::{
+        test_utils::{neighborhood, occupied, random_tree, TestTree},
+        Aabb,
+    };
+
+    /// Reference implementation working on individual voxels.
+    fn step(
+        voxels: &FxHashSet<IVec3>,
+        connectivity: Connectivity,
+        dilate: bool,
+    ) -> FxHashSet<IVec3> {
+        let offsets = neighborhood(connectivity);
+        if dilate {
+            let mut result = voxels.clone();
+            for v in voxels {
+                result.extend(offsets.iter().map(|d| *v + *d));
+            }
+            result
+        } else {
+            voxels
+                .iter()
+                .copied()
+                .filter(|v| offsets.iter().all(|d| voxels.contains(&(*v + *d))))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_shift() {
+        let src = [0x8000_0000_0000_0001, 0x1];
+        assert_eq!(shift_up(&src, 1), vec![0x2, 0x3]);
+        assert_eq!(
+            shift_down(&src, 1),
+            vec![0x4000_0000_0000_0000 | (1 << 63), 0]
+        );
+        assert_eq!(shift_up(&src, 64), vec![0, 0x8000_0000_0000_0001]);
+        assert_eq!(shift_down(&src, 64), vec![0x1, 0]);
+        assert_eq!(shift_down(&src, 127), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_morphology_random() {
+        let mut rng = rand::thread_rng();
+        let bounds = Aabb::new(IVec3::splat(-20), IVec3::splat(20));
+        for connectivity in [
+            Connectivity::Faces,
+            Connectivity::Edges,
+            Connectivity::Vertices,
+        ] {
+            let tree = random_tree(&mut rng, 300, 1..2);
+            let voxels = occupied(&tree, bounds);
+
+            for iterations in 1..3 {
+                let mut expected = voxels.clone();
+                let mut dilated = tree.extract(TestTree::extent());
+                for _ in 0..iterations {
+                    expected = step(&expected, connectivity, true);
+                }
+                dilated.dilate(iterations, connectivity, 2);
+                assert_eq!(occupied(&dilated, bounds), expected);
+                for v in voxels.iter() {
+                    assert_eq!(dilated.get_value(*v), tree.get_value(*v));
+                }
+
+                let mut expected = voxels.clone();
+                let mut eroded = tree.extract(TestTree::extent());
+                for _ in 0..iterations {
+                    expected = step(&expected, connectivity, false);
+                }
+                eroded.erode(iterations, connectivity);
+                assert_eq!(occupied(&eroded, bounds), expected);
+                assert_eq!(eroded.get_value(IVec3::splat(-8)), Some(2));
+            }
+
+            let mut expected = step(&step(&voxels, connectivity, false), connectivity, true);
+            expected.retain(|v| voxels.contains(v));
+            let mut opened = tree.extract(TestTree::extent());
+            opened.open(1, connectivity);
+            assert_eq!(occupied(&opened, bounds), expected);
+
+            let expected = step(&step(&voxels, connectivity, true), connectivity, false);
+            let mut closed = tree.extract(TestTree::extent());
+            closed.close(1, connectivity, 2);
+            assert_eq!(occupied(&closed, bounds), expected);
+        }
+    }
+}
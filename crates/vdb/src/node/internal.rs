@@ -1,33 +1,40 @@
-use super::{size_of_grid, NodeMeta};
-use crate::{bitmask::SetBitIterator, BitMask, ConstUVec3, Node, NodeConst, Pool};
-use glam::UVec3;
+use super::{size_of_grid, NodeMeta, TileIterator};
+use crate::{
+    bitmask::SetBitIterator, Aabb, BitMask, ConstUVec3, CsgOp, Node, NodeConst, Pool, Serializable,
+    Shape, VisitedNode,
+};
+use glam::{IVec3, UVec3};
 use std::{
     cell::UnsafeCell,
+    io::{Read, Write},
     marker::PhantomData,
     mem::{size_of, MaybeUninit},
 };
 
 #[derive(Clone, Copy)]
-pub union InternalNodeEntry {
+pub union InternalNodeEntry<V: Copy> {
     /// The corresponding bit on child_mask is set. Points to another node.
     pub occupied: u32,
     /// The corresponding bit on child_mask is not set.
-    /// Points to a value in the material array that describes all child nodes within the current node.
-    /// If this is u32::MAX, this is air.
-    pub free: u32,
+    /// If the corresponding bit on tile_mask is set, this is the value of all voxels within the tile.
+    /// Otherwise, the tile is air.
+    pub free: V,
 }
 
 /// Internal nodes are always 4x4x4 so that the child mask contains exactly 64 voxels.
-/// Size: 3 - 66 u32
+/// Size: 4 u32 + 64 entries
 #[repr(C)]
 pub struct InternalNode<CHILD: Node, const FANOUT_LOG2: ConstUVec3>
 where
     [(); size_of_grid(FANOUT_LOG2) / size_of::<usize>() / 8]: Sized,
 {
-    /// This is 0 if that tile is completely air, and 1 otherwise.
+    /// This is 1 if that tile points to a child node, and 0 otherwise.
     pub child_mask: BitMask<{ size_of_grid(FANOUT_LOG2) }>,
+    /// This is 1 if that tile is completely filled with the value in `child_ptrs`, and 0 otherwise.
+    /// Bits set on the child mask are never set on the tile mask.
+    pub tile_mask: BitMask<{ size_of_grid(FANOUT_LOG2) }>,
     /// points to self.child_mask.count_ones() LeafNodes or InternalNodes
-    pub child_ptrs: [InternalNodeEntry; size_of_grid(FANOUT_LOG2)],
+    pub child_ptrs: [InternalNodeEntry<CHILD::Voxel>; size_of_grid(FANOUT_LOG2)],
     _marker: PhantomData<CHILD>,
 }
 impl<CHILD: Node, const FANOUT_LOG2: ConstUVec3> Default for InternalNode<CHILD, FANOUT_LOG2>
@@ -37,11 +44,92 @@ where
     fn default() -> Self {
         Self {
             child_mask: Default::default(),
-            child_ptrs: [InternalNodeEntry { free: 0 }; size_of_grid(FANOUT_LOG2)],
+            tile_mask: Default::default(),
+            child_ptrs: [InternalNodeEntry {
+                free: Default::default(),
+            }; size_of_grid(FANOUT_LOG2)],
             _marker: Default::default(),
         }
     }
 }
+
+impl<CHILD: Node, const FANOUT_LOG2: ConstUVec3> InternalNode<CHILD, FANOUT_LOG2>
+where
+    [(); size_of_grid(FANOUT_LOG2) / size_of::<usize>() / 8]: Sized,
+{
+    /// Index of the child node at `offset`, in units of child nodes.
+    #[inline]
+    pub fn child_index(offset: UVec3) -> usize {
+        ((offset.x as usize) << (FANOUT_LOG2.y + FANOUT_LOG2.z))
+            | ((offset.y as usize) << FANOUT_LOG2.z)
+            | (offset.z as usize)
+    }
+    /// Offset of the child node at `index`, in units of child nodes.
+    #[inline]
+    pub fn child_offset(index: usize) -> UVec3 {
+        UVec3 {
+            x: index as u32 >> (FANOUT_LOG2.z + FANOUT_LOG2.y),
+            y: (index as u32 >> FANOUT_LOG2.z) & ((1 << FANOUT_LOG2.y) - 1),
+            z: index as u32 & ((1 << FANOUT_LOG2.z) - 1),
+        }
+    }
+    /// Returns the tile value at `index`, or None if the tile was air or points to a child node.
+    #[inline]
+    pub fn tile_value(&self, index: usize) -> Option<CHILD::Voxel> {
+        if self.tile_mask.get(index) {
+            Some(unsafe { self.child_ptrs[index].free })
+        } else {
+            None
+        }
+    }
+    /// Turn the entry at `index` into a tile. The entry must not point to a child node.
+    #[inline]
+    fn set_tile(&mut self, index: usize, value: Option<CHILD::Voxel>) {
+        debug_assert!(!self.child_mask.get(index));
+        self.tile_mask.set(index, value.is_some());
+        self.child_ptrs[index] = InternalNodeEntry {
+            free: value.unwrap_or_default(),
+        };
+    }
+    /// Allocate a child node at `index`. If the entry was a tile, the child node will be
+    /// filled with the tile value.
+    fn make_child(&mut self, pools: &mut [Pool], index: usize) -> u32 {
+        debug_assert!(!self.child_mask.get(index));
+        let child_ptr = unsafe { pools[CHILD::LEVEL].alloc::<CHILD>() };
+        if let Some(tile) = self.tile_value(index) {
+            CHILD::fill_in_pools(
+                pools,
+                child_ptr,
+                Aabb::from_extent(IVec3::ZERO, CHILD::EXTENT),
+                Some(tile),
+            );
+            self.tile_mask.set(index, false);
+        }
+        self.child_mask.set(index, true);
+        self.child_ptrs[index] = InternalNodeEntry {
+            occupied: child_ptr,
+        };
+        child_ptr
+    }
+    /// Returns the child node at `index` for writing. A child node shared with a
+    /// snapshot is copied first, and the entry is re-pointed to the copy.
+    fn child_mut(&mut self, pools: &mut [Pool], index: usize) -> u32 {
+        debug_assert!(self.child_mask.get(index));
+        let child_ptr =
+            pools[CHILD::LEVEL].make_item_unique(unsafe { self.child_ptrs[index].occupied });
+        self.child_ptrs[index] = InternalNodeEntry {
+            occupied: child_ptr,
+        };
+        child_ptr
+    }
+    /// Return the child node at `index` to the pools.
+    fn free_child(&mut self, pools: &mut [Pool], index: usize) {
+        debug_assert!(self.child_mask.get(index));
+        let child_ptr = unsafe { self.child_ptrs[index].occupied };
+        CHILD::free_in_pools(pools, child_ptr);
+        self.child_mask.set(index, false);
+    }
+}
 impl<CHILD: Node, const FANOUT_LOG2: ConstUVec3> Node for InternalNode<CHILD, FANOUT_LOG2>
 where
     [(); size_of_grid(FANOUT_LOG2) / size_of::<usize>() / 8]: Sized,
@@ -67,7 +155,10 @@ where
     fn new() -> Self {
         Self {
             child_mask: BitMask::new(),
-            child_ptrs: [InternalNodeEntry { free: 0 }; size_of_grid(FANOUT_LOG2)],
+            tile_mask: BitMask::new(),
+            child_ptrs: [InternalNodeEntry {
+                free: Default::default(),
+            }; size_of_grid(FANOUT_LOG2)],
             _marker: PhantomData,
         }
     }
@@ -81,7 +172,11 @@ where
             | (internal_offset.z as usize);
         let has_child = self.child_mask.get(index);
         if !has_child {
-            return None;
+            // Invalidate the stale path below this node.
+            if !cached_path.is_empty() {
+                cached_path[..=CHILD::LEVEL].fill(u32::MAX);
+            }
+            return self.tile_value(index);
         }
         unsafe {
             let child_ptr = self.child_ptrs[index].occupied;
@@ -105,27 +200,29 @@ where
         let index = ((internal_offset.x as usize) << (FANOUT_LOG2.y + FANOUT_LOG2.z))
             | ((internal_offset.y as usize) << FANOUT_LOG2.z)
             | (internal_offset.z as usize);
-        if value.is_some() {
-            // set
-            let has_child = self.child_mask.get(index);
-            if !has_child {
-                // ensure have children
-                self.child_mask.set(index, true);
-                unsafe {
-                    // allocate a child node
-                    let allocated_ptr = pools[CHILD::LEVEL].alloc::<CHILD>();
-                    self.child_ptrs[index].occupied = allocated_ptr;
+        if !self.child_mask.get(index) {
+            let tile = self.tile_value(index);
+            if tile == value {
+                // The tile already has the requested value.
+                if !cached_path.is_empty() {
+                    cached_path[..=CHILD::LEVEL].fill(u32::MAX);
                 }
+                return;
             }
+            // Allocate a child node, densifying the tile if needed.
+            self.make_child(pools, index);
             // TODO: propagate when filled.
-        } else {
-            // clear
-            todo!() // TODO: clear recursively, propagate if completely cleared
         }
-        unsafe {
-            let new_coords = coords & CHILD::EXTENT_MASK;
-            let child_ptr = self.child_ptrs[index].occupied;
-            <CHILD as Node>::set_in_pools(pools, new_coords, child_ptr, value, cached_path)
+        let new_coords = coords & CHILD::EXTENT_MASK;
+        let child_ptr = self.child_mut(pools, index);
+        <CHILD as Node>::set_in_pools(pools, new_coords, child_ptr, value, cached_path);
+        if value.is_none() && CHILD::is_empty_in_pools(pools, child_ptr) {
+            // The child node was completely cleared. Return it to the pool.
+            self.free_child(pools, index);
+            self.set_tile(index, None);
+            if !cached_path.is_empty() {
+                cached_path[CHILD::LEVEL] = u32::MAX;
+            }
         }
     }
     #[inline]
@@ -160,33 +257,500 @@ where
         }
     }
 
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.child_mask.is_zeroed() && self.tile_mask.is_zeroed()
+    }
+    #[inline]
+    fn is_empty_in_pools(pools: &[Pool], ptr: u32) -> bool {
+        let node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        node.is_empty()
+    }
+
+    fn uniform_value(&self) -> Option<Self::Voxel> {
+        if !self.child_mask.is_zeroed() || !self.tile_mask.is_full() {
+            return None;
+        }
+        let value = unsafe { self.child_ptrs[0].free };
+        if self
+            .child_ptrs
+            .iter()
+            .all(|entry| unsafe { entry.free } == value)
+        {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn fill(&mut self, pools: &mut [Pool], aabb: Aabb, value: Option<Self::Voxel>) {
+        let first = aabb.min.as_uvec3() >> CHILD::EXTENT_LOG2;
+        let last = (aabb.max - IVec3::ONE).as_uvec3() >> CHILD::EXTENT_LOG2;
+        for x in first.x..=last.x {
+            for y in first.y..=last.y {
+                for z in first.z..=last.z {
+                    let child_offset = UVec3::new(x, y, z);
+                    let index = Self::child_index(child_offset);
+                    let child_aabb =
+                        Aabb::from_extent((child_offset * CHILD::EXTENT).as_ivec3(), CHILD::EXTENT);
+                    let clipped = aabb.intersect(&child_aabb);
+                    if clipped == child_aabb {
+                        // The child is covered entirely. Replace it with a tile.
+                        if self.child_mask.get(index) {
+                            self.free_child(pools, index);
+                        }
+                        self.set_tile(index, value);
+                        continue;
+                    }
+                    if !self.child_mask.get(index) {
+                        let tile = self.tile_value(index);
+                        if tile == value {
+                            continue;
+                        }
+                        self.make_child(pools, index);
+                    }
+                    let child_ptr = self.child_mut(pools, index);
+                    let child_aabb =
+                        Aabb::new(clipped.min - child_aabb.min, clipped.max - child_aabb.min);
+                    CHILD::fill_in_pools(pools, child_ptr, child_aabb, value);
+                    if value.is_none() && CHILD::is_empty_in_pools(pools, child_ptr) {
+                        self.free_child(pools, index);
+                        self.set_tile(index, None);
+                    }
+                }
+            }
+        }
+    }
+    fn fill_in_pools(pools: &mut [Pool], ptr: u32, aabb: Aabb, value: Option<Self::Voxel>) {
+        // Safety: r was taken from pools[Self::LEVEL] and we know that self.fill only access pools[CHILD::LEVEL] and below.
+        unsafe {
+            let r = pools[Self::LEVEL].get_item_mut::<Self>(ptr) as *mut Self;
+            (*r).fill(pools, aabb, value)
+        }
+    }
+
+    fn free_in_pools(pools: &mut [Pool], ptr: u32) {
+        // Safety: r was taken from pools[Self::LEVEL] and we know that the children are in pools[CHILD::LEVEL] and below.
+        unsafe {
+            let r = pools[Self::LEVEL].get_item::<Self>(ptr) as *const Self;
+            for index in (*r).child_mask.iter_set_bits() {
+                CHILD::free_in_pools(pools, (*r).child_ptrs[index].occupied);
+            }
+        }
+        pools[Self::LEVEL].free(ptr);
+    }
+
+    fn clone_in_pools(src_pools: &[Pool], src_ptr: u32, dst_pools: &mut [Pool]) -> u32 {
+        let src = unsafe { src_pools[Self::LEVEL].get_item::<Self>(src_ptr) };
+        let mut child_ptrs = src.child_ptrs;
+        for index in src.child_mask.iter_set_bits() {
+            let child_ptr = unsafe { src.child_ptrs[index].occupied };
+            child_ptrs[index] = InternalNodeEntry {
+                occupied: CHILD::clone_in_pools(src_pools, child_ptr, dst_pools),
+            };
+        }
+        unsafe {
+            let dst_ptr = dst_pools[Self::LEVEL].alloc_uninitialized();
+            let dst = dst_pools[Self::LEVEL].get_mut(dst_ptr) as *mut Self;
+            dst.write(Self {
+                child_mask: src.child_mask.clone(),
+                tile_mask: src.tile_mask.clone(),
+                child_ptrs,
+                _marker: PhantomData,
+            });
+            dst_ptr
+        }
+    }
+
+    fn fill_empty_in_pools(pools: &mut [Pool], ptr: u32, value: Self::Voxel) {
+        // Safety: r was taken from pools[Self::LEVEL] and we know that the children are in pools[CHILD::LEVEL] and below.
+        unsafe {
+            let r = pools[Self::LEVEL].get_item_mut::<Self>(ptr) as *mut Self;
+            for index in 0..Self::SIZE {
+                if (*r).child_mask.get(index) {
+                    let child_ptr = (*r).child_mut(pools, index);
+                    CHILD::fill_empty_in_pools(pools, child_ptr, value);
+                } else if !(*r).tile_mask.get(index) {
+                    (*r).set_tile(index, Some(value));
+                }
+            }
+        }
+    }
+
+    fn csg(&mut self, pools: &mut [Pool], other: &Self, other_pools: &[Pool], op: CsgOp) {
+        for index in 0..Self::SIZE {
+            let other_tile = other.tile_value(index);
+            let other_child = if other.child_mask.get(index) {
+                Some(unsafe { other.child_ptrs[index].occupied })
+            } else {
+                None
+            };
+            if other_child.is_none() && other_tile.is_none() {
+                // The other node is air here.
+                if op == CsgOp::Intersection {
+                    if self.child_mask.get(index) {
+                        self.free_child(pools, index);
+                    }
+                    self.set_tile(index, None);
+                }
+                continue;
+            }
+            if !self.child_mask.get(index) {
+                match (self.tile_value(index), op) {
+                    (None, CsgOp::Union) => {
+                        if let Some(other_child) = other_child {
+                            let child_ptr = CHILD::clone_in_pools(other_pools, other_child, pools);
+                            self.child_mask.set(index, true);
+                            self.child_ptrs[index] = InternalNodeEntry {
+                                occupied: child_ptr,
+                            };
+                        } else {
+                            self.set_tile(index, other_tile);
+                        }
+                        continue;
+                    }
+                    (None, _) => continue,
+                    (Some(_), CsgOp::Union) => continue,
+                    (Some(_), CsgOp::Intersection) if other_tile.is_some() => continue,
+                    (Some(_), CsgOp::Difference) if other_tile.is_some() => {
+                        self.set_tile(index, None);
+                        continue;
+                    }
+                    // Densify the tile and combine it with the child of the other node.
+                    (Some(_), _) => {
+                        self.make_child(pools, index);
+                    }
+                }
+            }
+            match (other_child, op) {
+                (Some(other_child), _) => {
+                    let child_ptr = self.child_mut(pools, index);
+                    CHILD::csg_in_pools(pools, child_ptr, other_pools, other_child, op)
+                }
+                (None, CsgOp::Union) => {
+                    let child_ptr = self.child_mut(pools, index);
+                    CHILD::fill_empty_in_pools(pools, child_ptr, other_tile.unwrap())
+                }
+                (None, CsgOp::Intersection) => (),
+                (None, CsgOp::Difference) => {
+                    self.free_child(pools, index);
+                    self.set_tile(index, None);
+                    continue;
+                }
+            }
+            let child_ptr = unsafe { self.child_ptrs[index].occupied };
+            if CHILD::is_empty_in_pools(pools, child_ptr) {
+                self.free_child(pools, index);
+                self.set_tile(index, None);
+            }
+        }
+    }
+    fn csg_in_pools(pools: &mut [Pool], ptr: u32, other_pools: &[Pool], other_ptr: u32, op: CsgOp) {
+        let other = unsafe { other_pools[Self::LEVEL].get_item::<Self>(other_ptr) };
+        // Safety: r was taken from pools[Self::LEVEL] and we know that self.csg only access pools[CHILD::LEVEL] and below.
+        unsafe {
+            let r = pools[Self::LEVEL].get_item_mut::<Self>(ptr) as *mut Self;
+            (*r).csg(pools, other, other_pools, op)
+        }
+    }
+
+    fn relocate(&mut self, pools: &mut [Pool], offsets: &[u32]) {
+        for index in self.child_mask.iter_set_bits() {
+            let child_ptr = unsafe { self.child_ptrs[index].occupied } + offsets[CHILD::LEVEL];
+            self.child_ptrs[index] = InternalNodeEntry {
+                occupied: child_ptr,
+            };
+            CHILD::relocate_in_pools(pools, child_ptr, offsets);
+        }
+    }
+    fn relocate_in_pools(pools: &mut [Pool], ptr: u32, offsets: &[u32]) {
+        // Safety: r was taken from pools[Self::LEVEL] and we know that self.relocate only access pools[CHILD::LEVEL] and below.
+        unsafe {
+            let r = pools[Self::LEVEL].get_item_mut::<Self>(ptr) as *mut Self;
+            (*r).relocate(pools, offsets)
+        }
+    }
+
+    fn merge(&mut self, pools: &mut [Pool], other: Self) {
+        for index in 0..Self::SIZE {
+            let other_tile = other.tile_value(index);
+            let other_child = if other.child_mask.get(index) {
+                Some(unsafe { other.child_ptrs[index].occupied })
+            } else {
+                None
+            };
+            if self.child_mask.get(index) {
+                match (other_child, other_tile) {
+                    (Some(other_child), _) => {
+                        let child_ptr = self.child_mut(pools, index);
+                        CHILD::merge_in_pools(pools, child_ptr, other_child)
+                    }
+                    (None, Some(value)) => {
+                        let child_ptr = self.child_mut(pools, index);
+                        CHILD::fill_empty_in_pools(pools, child_ptr, value)
+                    }
+                    (None, None) => (),
+                }
+            } else if self.tile_mask.get(index) {
+                // The tile covers everything the other node has here.
+                if let Some(other_child) = other_child {
+                    CHILD::free_in_pools(pools, other_child);
+                }
+            } else if let Some(other_child) = other_child {
+                // Move the child node over.
+                self.child_mask.set(index, true);
+                self.child_ptrs[index] = InternalNodeEntry {
+                    occupied: other_child,
+                };
+            } else {
+                self.set_tile(index, other_tile);
+            }
+        }
+    }
+    fn merge_in_pools(pools: &mut [Pool], ptr: u32, other_ptr: u32) {
+        // Safety: r was taken from pools[Self::LEVEL] and we know that self.merge only access pools[CHILD::LEVEL] and below.
+        // The nodes were allocated from different chunks, as the chunks of the other tree were appended.
+        unsafe {
+            let r = pools[Self::LEVEL].get_item_mut::<Self>(ptr) as *mut Self;
+            let other = std::ptr::read(pools[Self::LEVEL].get_item::<Self>(other_ptr));
+            (*r).merge(pools, other);
+        }
+        pools[Self::LEVEL].free(other_ptr);
+    }
+
+    fn touch_leaf(&mut self, pools: &mut [Pool], coords: UVec3) -> *mut Self::LeafType {
+        let index = Self::child_index(coords >> CHILD::EXTENT_LOG2);
+        let child_ptr = if self.child_mask.get(index) {
+            self.child_mut(pools, index)
+        } else {
+            self.make_child(pools, index)
+        };
+        CHILD::touch_leaf_in_pools(pools, child_ptr, coords & CHILD::EXTENT_MASK)
+    }
+    fn touch_leaf_in_pools(pools: &mut [Pool], ptr: u32, coords: UVec3) -> *mut Self::LeafType {
+        // Safety: r was taken from pools[Self::LEVEL] and we know that self.touch_leaf only access pools[CHILD::LEVEL] and below.
+        unsafe {
+            let r = pools[Self::LEVEL].get_item_mut::<Self>(ptr) as *mut Self;
+            (*r).touch_leaf(pools, coords)
+        }
+    }
+
+    #[inline]
+    fn probe(&self, pools: &[Pool], coords: UVec3) -> (Option<Self::Voxel>, UVec3) {
+        let index = Self::child_index(coords >> CHILD::EXTENT_LOG2);
+        if !self.child_mask.get(index) {
+            return (self.tile_value(index), CHILD::EXTENT_LOG2);
+        }
+        let child_ptr = unsafe { self.child_ptrs[index].occupied };
+        CHILD::probe_in_pools(pools, child_ptr, coords & CHILD::EXTENT_MASK)
+    }
+    #[inline]
+    fn probe_in_pools(pools: &[Pool], ptr: u32, coords: UVec3) -> (Option<Self::Voxel>, UVec3) {
+        let node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        node.probe(pools, coords)
+    }
+
+    fn visit<'a, S: Shape + ?Sized, F: FnMut(VisitedNode<'a, Self::LeafType, Self::Voxel>)>(
+        &'a self,
+        pools: &'a [Pool],
+        offset: IVec3,
+        shape: &S,
+        f: &mut F,
+    ) {
+        let clipped = shape
+            .bounding_box()
+            .intersect(&Aabb::from_extent(offset, Self::EXTENT));
+        if clipped.is_empty() {
+            return;
+        }
+        let first = (clipped.min - offset).as_uvec3() >> CHILD::EXTENT_LOG2;
+        let last = (clipped.max - offset - IVec3::ONE).as_uvec3() >> CHILD::EXTENT_LOG2;
+        for x in first.x..=last.x {
+            for y in first.y..=last.y {
+                for z in first.z..=last.z {
+                    let child_offset = UVec3::new(x, y, z);
+                    let index = Self::child_index(child_offset);
+                    let child_origin = offset + (child_offset * CHILD::EXTENT).as_ivec3();
+                    let child_aabb = Aabb::from_extent(child_origin, CHILD::EXTENT);
+                    if !shape.intersects(&child_aabb) {
+                        continue;
+                    }
+                    if self.child_mask.get(index) {
+                        let child_ptr = unsafe { self.child_ptrs[index].occupied };
+                        CHILD::visit_in_pools(pools, child_ptr, child_origin, shape, f);
+                    } else if let Some(value) = self.tile_value(index) {
+                        f(VisitedNode::Tile(child_aabb, value));
+                    }
+                }
+            }
+        }
+    }
+    fn visit_in_pools<
+        'a,
+        S: Shape + ?Sized,
+        F: FnMut(VisitedNode<'a, Self::LeafType, Self::Voxel>),
+    >(
+        pools: &'a [Pool],
+        ptr: u32,
+        offset: IVec3,
+        shape: &S,
+        f: &mut F,
+    ) {
+        let node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        node.visit(pools, offset, shape, f)
+    }
+
+    fn prune(&mut self, pools: &mut [Pool]) -> bool {
+        for index in 0..Self::SIZE {
+            if !self.child_mask.get(index) {
+                continue;
+            }
+            let mut child_ptr = unsafe { self.child_ptrs[index].occupied };
+            let empty = CHILD::prune_in_pools(pools, &mut child_ptr);
+            self.child_ptrs[index] = InternalNodeEntry {
+                occupied: child_ptr,
+            };
+            if empty {
+                self.free_child(pools, index);
+                self.set_tile(index, None);
+                continue;
+            }
+            // Collapse uniform child nodes into tiles.
+            let child = unsafe { pools[CHILD::LEVEL].get_item::<CHILD>(child_ptr) };
+            if let Some(value) = child.uniform_value() {
+                self.free_child(pools, index);
+                self.set_tile(index, Some(value));
+            }
+        }
+        self.is_empty()
+    }
+    fn prune_in_pools(pools: &mut [Pool], ptr: &mut u32) -> bool {
+        if !pools[Self::LEVEL].is_shared(*ptr) {
+            // Safety: r was taken from pools[Self::LEVEL] and we know that self.prune only access pools[CHILD::LEVEL] and below.
+            unsafe {
+                let r = pools[Self::LEVEL].get_item_mut::<Self>(*ptr) as *mut Self;
+                return (*r).prune(pools);
+            }
+        }
+        // Prune a copy of the shared node, and keep it only if anything changed.
+        let mut pruned = unsafe { pools[Self::LEVEL].get_item::<Self>(*ptr) }.clone();
+        let empty = pruned.prune(pools);
+        let node = unsafe { pools[Self::LEVEL].get_item::<Self>(*ptr) };
+        let changed = pruned.child_mask.count_ones() != node.child_mask.count_ones()
+            || pruned.child_mask.iter_set_bits().any(|index| unsafe {
+                pruned.child_ptrs[index].occupied != node.child_ptrs[index].occupied
+            });
+        if changed {
+            *ptr = pools[Self::LEVEL].make_item_unique(*ptr);
+            unsafe {
+                *pools[Self::LEVEL].get_item_mut::<Self>(*ptr) = pruned;
+            }
+        }
+        empty
+    }
+
+    fn write_to(&self, pools: &[Pool], writer: &mut impl Write) -> std::io::Result<()>
+    where
+        Self::Voxel: Serializable,
+    {
+        self.child_mask.write_words(writer)?;
+        self.tile_mask.write_words(writer)?;
+        for index in self.tile_mask.iter_set_bits() {
+            unsafe { self.child_ptrs[index].free }.write_to(writer)?;
+        }
+        for index in self.child_mask.iter_set_bits() {
+            let child_ptr = unsafe { self.child_ptrs[index].occupied };
+            CHILD::write_in_pools(pools, child_ptr, writer)?;
+        }
+        Ok(())
+    }
+    fn write_in_pools(pools: &[Pool], ptr: u32, writer: &mut impl Write) -> std::io::Result<()>
+    where
+        Self::Voxel: Serializable,
+    {
+        let node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        node.write_to(pools, writer)
+    }
+    fn read_from(&mut self, pools: &mut [Pool], reader: &mut impl Read) -> std::io::Result<()>
+    where
+        Self::Voxel: Serializable,
+    {
+        let mut child_mask = self.child_mask.clone();
+        child_mask.read_words(reader)?;
+        self.tile_mask.read_words(reader)?;
+        let mut overlap = child_mask.clone();
+        overlap.intersect_with(&self.tile_mask);
+        if !overlap.is_zeroed() {
+            self.tile_mask.subtract(&child_mask);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "overlapping child and tile masks",
+            ));
+        }
+        for index in self.tile_mask.iter_set_bits() {
+            self.child_ptrs[index] = InternalNodeEntry {
+                free: CHILD::Voxel::read_from(reader)?,
+            };
+        }
+        // Children are added to the child mask one by one so that a partially read node
+        // can still be freed.
+        for index in child_mask.iter_set_bits() {
+            let child_ptr = CHILD::read_in_pools(pools, reader)?;
+            self.child_mask.set(index, true);
+            self.child_ptrs[index] = InternalNodeEntry {
+                occupied: child_ptr,
+            };
+        }
+        Ok(())
+    }
+    fn read_in_pools(pools: &mut [Pool], reader: &mut impl Read) -> std::io::Result<u32>
+    where
+        Self::Voxel: Serializable,
+    {
+        let ptr = unsafe { pools[Self::LEVEL].alloc::<Self>() };
+        // Safety: r was taken from pools[Self::LEVEL] and we know that self.read_from only access pools[CHILD::LEVEL] and below.
+        let result = unsafe {
+            let r = pools[Self::LEVEL].get_item_mut::<Self>(ptr) as *mut Self;
+            (*r).read_from(pools, reader)
+        };
+        if let Err(err) = result {
+            Self::free_in_pools(pools, ptr);
+            return Err(err);
+        }
+        Ok(ptr)
+    }
+
     type Iterator<'a> = InternalNodeIterator<'a, CHILD, FANOUT_LOG2>;
     #[inline]
-    fn iter<'a>(&'a self, pools: &'a [Pool], offset: UVec3) -> Self::Iterator<'a> {
+    fn iter<'a>(&'a self, pools: &'a [Pool], offset: IVec3) -> Self::Iterator<'a> {
         InternalNodeIterator {
             pools,
             location_offset: offset,
             child_mask_iterator: self.child_mask.iter_set_bits(),
+            tile_mask_iterator: self.tile_mask.iter_set_bits(),
             child_ptrs: &self.child_ptrs,
             child_iterator: None,
+            tile_iterator: None,
         }
     }
     #[inline]
-    fn iter_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: UVec3) -> Self::Iterator<'a> {
+    fn iter_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: IVec3) -> Self::Iterator<'a> {
         let node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
         InternalNodeIterator {
             pools,
             location_offset: offset,
             child_mask_iterator: node.child_mask.iter_set_bits(),
+            tile_mask_iterator: node.tile_mask.iter_set_bits(),
             child_ptrs: &node.child_ptrs,
             child_iterator: None,
+            tile_iterator: None,
         }
     }
 
     type LeafIterator<'a> = InternalNodeLeafIterator<'a, CHILD, FANOUT_LOG2>;
 
     #[inline]
-    fn iter_leaf<'a>(&'a self, pools: &'a [Pool], offset: UVec3) -> Self::LeafIterator<'a> {
+    fn iter_leaf<'a>(&'a self, pools: &'a [Pool], offset: IVec3) -> Self::LeafIterator<'a> {
         InternalNodeLeafIterator {
             pools,
             location_offset: offset,
@@ -197,7 +761,7 @@ where
     }
 
     #[inline]
-    fn iter_leaf_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: UVec3) -> Self::LeafIterator<'a> {
+    fn iter_leaf_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: IVec3) -> Self::LeafIterator<'a> {
         let node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
         InternalNodeLeafIterator {
             pools,
@@ -219,6 +783,7 @@ where
             layout: std::alloc::Layout::new::<Self>(),
             getter: Self::get_in_pools,
             setter: Self::set_in_pools,
+            is_empty: Self::is_empty_in_pools,
             extent_log2: Self::EXTENT_LOG2,
             extent_mask: Self::EXTENT_MASK,
             fanout_log2: FANOUT_LOG2.to_glam(),
@@ -227,6 +792,22 @@ where
     }
 }
 
+/// Copies the child pointers without cloning the child nodes.
+/// The clone is only valid together with a clone of the pools.
+impl<CHILD: Node, const FANOUT_LOG2: ConstUVec3> Clone for InternalNode<CHILD, FANOUT_LOG2>
+where
+    [(); size_of_grid(FANOUT_LOG2) / size_of::<usize>() / 8]: Sized,
+{
+    fn clone(&self) -> Self {
+        Self {
+            child_mask: self.child_mask.clone(),
+            tile_mask: self.tile_mask.clone(),
+            child_ptrs: self.child_ptrs,
+            _marker: PhantomData,
+        }
+    }
+}
+
 /// When the alternate flag was specified, also print the child pointers.
 impl<CHILD: Node, const FANOUT_LOG2: ConstUVec3> std::fmt::Debug
     for InternalNode<CHILD, FANOUT_LOG2>
@@ -245,17 +826,19 @@ where
     [(); size_of_grid(FANOUT_LOG2) / size_of::<usize>() / 8]: Sized,
 {
     pools: &'a [Pool],
-    location_offset: UVec3,
+    location_offset: IVec3,
     child_mask_iterator: SetBitIterator<'a, { size_of_grid(FANOUT_LOG2) }>,
+    tile_mask_iterator: SetBitIterator<'a, { size_of_grid(FANOUT_LOG2) }>,
     child_iterator: Option<CHILD::Iterator<'a>>,
-    child_ptrs: &'a [InternalNodeEntry; size_of_grid(FANOUT_LOG2)],
+    tile_iterator: Option<TileIterator>,
+    child_ptrs: &'a [InternalNodeEntry<CHILD::Voxel>; size_of_grid(FANOUT_LOG2)],
 }
 impl<'a, CHILD: Node, const FANOUT_LOG2: ConstUVec3> Iterator
     for InternalNodeIterator<'a, CHILD, FANOUT_LOG2>
 where
     [(); size_of_grid(FANOUT_LOG2) / size_of::<usize>() / 8]: Sized,
 {
-    type Item = UVec3;
+    type Item = IVec3;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
@@ -271,17 +854,33 @@ where
                     y: (next_child_index as u32 >> FANOUT_LOG2.z) & ((1 << FANOUT_LOG2.y) - 1),
                     z: next_child_index as u32 & ((1 << FANOUT_LOG2.z) - 1),
                 };
-                let offset = offset * CHILD::EXTENT;
+                let offset = (offset * CHILD::EXTENT).as_ivec3();
                 self.child_iterator = Some(CHILD::iter_in_pool(
                     self.pools,
                     child_ptr,
                     self.location_offset + offset,
                 ));
                 continue;
-            } else {
-                // Also ran out. We have nothing left.
-                return None;
             }
+            // All children were visited. Continue with the voxels of the tiles.
+            if let Some(item) = self.tile_iterator.as_mut().and_then(|a| a.next()) {
+                return Some(item);
+            }
+            if let Some(next_tile_index) = self.tile_mask_iterator.next() {
+                let offset = UVec3 {
+                    x: next_tile_index as u32 >> (FANOUT_LOG2.z + FANOUT_LOG2.y),
+                    y: (next_tile_index as u32 >> FANOUT_LOG2.z) & ((1 << FANOUT_LOG2.y) - 1),
+                    z: next_tile_index as u32 & ((1 << FANOUT_LOG2.z) - 1),
+                };
+                let offset = (offset * CHILD::EXTENT).as_ivec3();
+                self.tile_iterator = Some(TileIterator::new(
+                    self.location_offset + offset,
+                    CHILD::EXTENT_LOG2,
+                ));
+                continue;
+            }
+            // Also ran out. We have nothing left.
+            return None;
         }
     }
 }
@@ -291,17 +890,17 @@ where
     [(); size_of_grid(FANOUT_LOG2) / size_of::<usize>() / 8]: Sized,
 {
     pools: &'a [Pool],
-    location_offset: UVec3,
+    location_offset: IVec3,
     child_mask_iterator: SetBitIterator<'a, { size_of_grid(FANOUT_LOG2) }>,
     child_iterator: Option<CHILD::LeafIterator<'a>>,
-    child_ptrs: &'a [InternalNodeEntry; size_of_grid(FANOUT_LOG2)],
+    child_ptrs: &'a [InternalNodeEntry<CHILD::Voxel>; size_of_grid(FANOUT_LOG2)],
 }
 impl<'a, CHILD: Node, const FANOUT_LOG2: ConstUVec3> Iterator
     for InternalNodeLeafIterator<'a, CHILD, FANOUT_LOG2>
 where
     [(); size_of_grid(FANOUT_LOG2) / size_of::<usize>() / 8]: Sized,
 {
-    type Item = (UVec3, &'a UnsafeCell<CHILD::LeafType>);
+    type Item = (IVec3, &'a UnsafeCell<CHILD::LeafType>);
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
@@ -317,7 +916,7 @@ where
                     y: (next_child_index as u32 >> FANOUT_LOG2.z) & ((1 << FANOUT_LOG2.y) - 1),
                     z: next_child_index as u32 & ((1 << FANOUT_LOG2.z) - 1),
                 };
-                let offset = offset * CHILD::EXTENT;
+                let offset = (offset * CHILD::EXTENT).as_ivec3();
                 self.child_iterator = Some(CHILD::iter_leaf_in_pool(
                     self.pools,
                     child_ptr,
@@ -1,34 +1,85 @@
 use super::{size_of_grid, NodeMeta};
-use crate::{bitmask::SetBitIterator, BitMask, ConstUVec3, Node, NodeConst, Pool};
-use glam::UVec3;
+use crate::{
+    bitmask::SetBitIterator, Aabb, BitMask, ConstUVec3, CsgOp, Node, NodeConst, Pool, Serializable,
+    Shape, VisitedNode,
+};
+use glam::{IVec3, UVec3};
 use std::{
     cell::UnsafeCell,
+    io::{Read, Write},
     iter::Once,
     mem::{size_of, MaybeUninit},
 };
 
 /// Nodes are always 4x4x4 so that each leaf node contains exactly 64 voxels,
 /// so that the occupancy mask happens to be exactly 64 bits.
-/// Size: 3 u32
+/// `V` is the value stored for each voxel. Values of unoccupied voxels are
+/// kept at `V::default()`.
+/// Size: 4 u32 + 64 V
 #[repr(C)]
-#[derive(Default, Clone)]
-pub struct LeafNode<const LOG2: ConstUVec3>
+#[derive(Clone)]
+pub struct LeafNode<const LOG2: ConstUVec3, V: Copy + Default + PartialEq + 'static = bool>
 where
     [(); size_of_grid(LOG2) / size_of::<usize>() / 8]: Sized,
 {
     /// This is 1 for occupied voxels and 0 for unoccupied voxels
     pub occupancy: BitMask<{ size_of_grid(LOG2) }>,
-    /// This is 1 for voxels located on the surface
+    /// This is 1 for voxels located on the surface.
+    /// Computed from `occupancy` by [`crate::Tree::update_active`].
     pub active: BitMask<{ size_of_grid(LOG2) }>,
-    /// A pointer to self.occupancy.count_ones() material values
-    pub material_ptr: u32,
+    /// Dense array of voxel values, indexed in the same order as `occupancy`.
+    pub values: [V; size_of_grid(LOG2)],
+}
+
+impl<const LOG2: ConstUVec3, V: Copy + Default + PartialEq + 'static> Default for LeafNode<LOG2, V>
+where
+    [(); size_of_grid(LOG2) / size_of::<usize>() / 8]: Sized,
+{
+    fn default() -> Self {
+        Self {
+            occupancy: Default::default(),
+            active: Default::default(),
+            values: [V::default(); size_of_grid(LOG2)],
+        }
+    }
 }
 
-pub trait IsLeaf: Node {
+impl<const LOG2: ConstUVec3, V: Copy + Default + PartialEq + 'static> LeafNode<LOG2, V>
+where
+    [(); size_of_grid(LOG2) / size_of::<usize>() / 8]: Sized,
+{
+    /// Index of the voxel at `coords` into `occupancy` and `values`.
+    #[inline]
+    pub fn index_of(coords: UVec3) -> usize {
+        ((coords.x as usize) << (LOG2.y + LOG2.z))
+            | ((coords.y as usize) << LOG2.z)
+            | (coords.z as usize)
+    }
+    /// Returns the values of all occupied voxels in `occupancy` order.
+    pub fn iter_values(&self) -> impl Iterator<Item = V> + '_ {
+        self.occupancy.iter_set_bits().map(|i| self.values[i])
+    }
+}
+
+pub trait IsLeaf: Node + Clone {
     fn get_occupancy(&self, data: &mut [u64]);
+    /// Copy the surface mask of the leaf into `data`, in the same order as the occupancy.
+    fn get_active(&self, data: &mut [u64]);
+    /// Returns true if the voxel at `coords` within the leaf is located on the surface.
+    fn is_active(&self, coords: UVec3) -> bool;
+    fn set_active(&mut self, coords: UVec3, active: bool);
+    /// Replace the surface mask with `data`, in the same order as [`IsLeaf::get_active`].
+    /// Bits of voxels that are not occupied are cleared.
+    fn set_active_mask(&mut self, data: &[u64]);
+    /// Replace all voxels of the leaf with `f(coords)`, writing the occupancy mask a word
+    /// at a time. Surface bits of voxels that are no longer occupied are cleared.
+    fn write_voxels(&mut self, f: impl FnMut(UVec3) -> Option<Self::Voxel>);
+    /// Replace the occupancy mask with `data`, in the same order as [`IsLeaf::get_occupancy`].
+    /// Newly occupied voxels are set to `value`, and the other voxels keep their values.
+    fn set_occupancy(&mut self, data: &[u64], value: Self::Voxel);
 }
 
-impl<const LOG2: ConstUVec3> IsLeaf for LeafNode<LOG2>
+impl<const LOG2: ConstUVec3, V: Copy + Default + PartialEq + 'static> IsLeaf for LeafNode<LOG2, V>
 where
     [(); size_of_grid(LOG2) / size_of::<usize>() / 8]: Sized,
 {
@@ -44,9 +95,84 @@ where
             );
         }
     }
+    fn get_active(&self, data: &mut [u64]) {
+        debug_assert_eq!(std::mem::size_of::<u64>(), std::mem::size_of::<usize>());
+        let len = self.active.data.len();
+        debug_assert!(data.len() >= len);
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                self.active.data.as_ptr() as *mut u64,
+                data.as_mut_ptr(),
+                len,
+            );
+        }
+    }
+    #[inline]
+    fn is_active(&self, coords: UVec3) -> bool {
+        self.active.get(Self::index_of(coords))
+    }
+    #[inline]
+    fn set_active(&mut self, coords: UVec3, active: bool) {
+        self.active.set(Self::index_of(coords), active);
+    }
+    fn set_active_mask(&mut self, data: &[u64]) {
+        debug_assert_eq!(std::mem::size_of::<u64>(), std::mem::size_of::<usize>());
+        let len = self.active.data.len();
+        debug_assert!(data.len() >= len);
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                self.active.data.as_mut_ptr() as *mut u64,
+                len,
+            );
+        }
+        self.active.intersect_with(&self.occupancy);
+    }
+    fn write_voxels(&mut self, mut f: impl FnMut(UVec3) -> Option<Self::Voxel>) {
+        const NUM_BITS: usize = usize::BITS as usize;
+        for (i, word) in self.occupancy.data.iter_mut().enumerate() {
+            let mut bits = 0;
+            for j in 0..NUM_BITS {
+                let index = i * NUM_BITS + j;
+                let coords = UVec3 {
+                    x: (index >> (LOG2.y + LOG2.z)) as u32,
+                    y: ((index >> LOG2.z) & ((1 << LOG2.y) - 1)) as u32,
+                    z: (index & ((1 << LOG2.z) - 1)) as u32,
+                };
+                self.values[index] = match f(coords) {
+                    Some(value) => {
+                        bits |= 1 << j;
+                        value
+                    }
+                    None => V::default(),
+                };
+            }
+            *word = bits;
+        }
+        self.active.intersect_with(&self.occupancy);
+    }
+    fn set_occupancy(&mut self, data: &[u64], value: Self::Voxel) {
+        const NUM_BITS: usize = usize::BITS as usize;
+        for (i, word) in self.occupancy.data.iter_mut().enumerate() {
+            let new = data[i] as usize;
+            let changed = *word ^ new;
+            let mut bits = changed;
+            while bits != 0 {
+                let j = bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                self.values[i * NUM_BITS + j] = if new & (1 << j) != 0 {
+                    value
+                } else {
+                    V::default()
+                };
+            }
+            *word = new;
+        }
+        self.active.intersect_with(&self.occupancy);
+    }
 }
 
-impl<const LOG2: ConstUVec3> Node for LeafNode<LOG2>
+impl<const LOG2: ConstUVec3, V: Copy + Default + PartialEq + 'static> Node for LeafNode<LOG2, V>
 where
     [(); size_of_grid(LOG2) / size_of::<usize>() / 8]: Sized,
 {
@@ -67,26 +193,19 @@ where
     };
     const LEVEL: usize = 0;
     fn new() -> Self {
-        Self {
-            occupancy: BitMask::new(),
-            active: BitMask::new(),
-            material_ptr: 0,
-        }
+        Self::default()
     }
 
-    type Voxel = bool;
+    type Voxel = V;
 
     #[inline]
     fn get(&self, _: &[Pool], coords: UVec3, _cached_path: &mut [u32]) -> Option<Self::Voxel> {
-        let index = ((coords.x as usize) << (LOG2.y + LOG2.z))
-            | ((coords.y as usize) << LOG2.z)
-            | (coords.z as usize);
+        let index = Self::index_of(coords);
         let occupied = self.occupancy.get(index);
         if !occupied {
             return None;
         }
-        let active = self.active.get(index);
-        return Some(active);
+        return Some(self.values[index]);
     }
     #[inline]
     fn set(
@@ -96,14 +215,14 @@ where
         value: Option<Self::Voxel>,
         _cached_path: &mut [u32],
     ) {
-        let index = ((coords.x as usize) << (LOG2.y + LOG2.z))
-            | ((coords.y as usize) << LOG2.z)
-            | (coords.z as usize);
+        let index = Self::index_of(coords);
         if let Some(voxel) = value {
             self.occupancy.set(index, true);
-            self.active.set(index, voxel);
+            self.values[index] = voxel;
         } else {
             self.occupancy.set(index, false);
+            self.active.set(index, false);
+            self.values[index] = V::default();
         }
     }
     #[inline]
@@ -135,14 +254,222 @@ where
         leaf_node.set(&mut [], coords, value, cached_path)
     }
 
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.occupancy.is_zeroed()
+    }
+    #[inline]
+    fn is_empty_in_pools(pools: &[Pool], ptr: u32) -> bool {
+        let leaf_node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        leaf_node.is_empty()
+    }
+
+    fn uniform_value(&self) -> Option<Self::Voxel> {
+        if !self.occupancy.is_full() {
+            return None;
+        }
+        let value = self.values[0];
+        if self.values.iter().all(|v| *v == value) {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn fill(&mut self, _pools: &mut [Pool], aabb: Aabb, value: Option<Self::Voxel>) {
+        // Write one row of voxels along the z axis at a time.
+        let len = (aabb.max.z - aabb.min.z) as usize;
+        for x in aabb.min.x..aabb.max.x {
+            for y in aabb.min.y..aabb.max.y {
+                let start = Self::index_of(UVec3::new(x as u32, y as u32, aabb.min.z as u32));
+                self.occupancy.set_range(start, len, value.is_some());
+                if value.is_none() {
+                    self.active.set_range(start, len, false);
+                }
+                self.values[start..start + len].fill(value.unwrap_or_default());
+            }
+        }
+    }
+    fn fill_in_pools(pools: &mut [Pool], ptr: u32, aabb: Aabb, value: Option<Self::Voxel>) {
+        let leaf_node = unsafe { pools[Self::LEVEL].get_item_mut::<Self>(ptr) };
+        leaf_node.fill(&mut [], aabb, value)
+    }
+
+    fn free_in_pools(pools: &mut [Pool], ptr: u32) {
+        pools[Self::LEVEL].free(ptr);
+    }
+
+    fn clone_in_pools(src_pools: &[Pool], src_ptr: u32, dst_pools: &mut [Pool]) -> u32 {
+        let src = unsafe { src_pools[Self::LEVEL].get_item::<Self>(src_ptr) };
+        unsafe {
+            let dst_ptr = dst_pools[Self::LEVEL].alloc_uninitialized();
+            let dst = dst_pools[Self::LEVEL].get_mut(dst_ptr) as *mut Self;
+            dst.write(src.clone());
+            dst_ptr
+        }
+    }
+
+    fn fill_empty_in_pools(pools: &mut [Pool], ptr: u32, value: Self::Voxel) {
+        let leaf_node = unsafe { pools[Self::LEVEL].get_item_mut::<Self>(ptr) };
+        for (index, v) in leaf_node.values.iter_mut().enumerate() {
+            if !leaf_node.occupancy.get(index) {
+                *v = value;
+            }
+        }
+        leaf_node.occupancy.set_range(0, Self::SIZE, true);
+    }
+
+    fn csg(&mut self, _pools: &mut [Pool], other: &Self, _other_pools: &[Pool], op: CsgOp) {
+        let mut occupancy = self.occupancy.clone();
+        match op {
+            CsgOp::Union => {
+                let mut added = other.occupancy.clone();
+                added.subtract(&self.occupancy);
+                for index in added.iter_set_bits() {
+                    self.values[index] = other.values[index];
+                    self.active.set(index, other.active.get(index));
+                }
+                self.occupancy.union_with(&added);
+                return;
+            }
+            CsgOp::Intersection => occupancy.intersect_with(&other.occupancy),
+            CsgOp::Difference => occupancy.subtract(&other.occupancy),
+        }
+        let mut removed = self.occupancy.clone();
+        removed.subtract(&occupancy);
+        for index in removed.iter_set_bits() {
+            self.values[index] = V::default();
+        }
+        self.active.intersect_with(&occupancy);
+        self.occupancy = occupancy;
+    }
+    fn csg_in_pools(pools: &mut [Pool], ptr: u32, other_pools: &[Pool], other_ptr: u32, op: CsgOp) {
+        let other = unsafe { other_pools[Self::LEVEL].get_item::<Self>(other_ptr) };
+        let leaf_node = unsafe { pools[Self::LEVEL].get_item_mut::<Self>(ptr) };
+        leaf_node.csg(&mut [], other, &[], op)
+    }
+
+    fn relocate(&mut self, _pools: &mut [Pool], _offsets: &[u32]) {}
+    fn relocate_in_pools(_pools: &mut [Pool], _ptr: u32, _offsets: &[u32]) {}
+
+    fn merge(&mut self, _pools: &mut [Pool], other: Self) {
+        self.csg(&mut [], &other, &[], CsgOp::Union)
+    }
+    fn merge_in_pools(pools: &mut [Pool], ptr: u32, other_ptr: u32) {
+        // The nodes were allocated from different chunks, as the chunks of the other tree
+        // were appended, so both can be borrowed at once.
+        unsafe {
+            let leaf_node = pools[Self::LEVEL].get_item_mut::<Self>(ptr) as *mut Self;
+            let other = pools[Self::LEVEL].get_item::<Self>(other_ptr);
+            (*leaf_node).csg(&mut [], other, &[], CsgOp::Union);
+        }
+        pools[Self::LEVEL].free(other_ptr);
+    }
+
+    #[inline]
+    fn touch_leaf(&mut self, _pools: &mut [Pool], _coords: UVec3) -> *mut Self::LeafType {
+        self
+    }
+    #[inline]
+    fn touch_leaf_in_pools(pools: &mut [Pool], ptr: u32, _coords: UVec3) -> *mut Self::LeafType {
+        unsafe { pools[Self::LEVEL].get_item_mut::<Self>(ptr) }
+    }
+
+    #[inline]
+    fn probe(&self, pools: &[Pool], coords: UVec3) -> (Option<Self::Voxel>, UVec3) {
+        (self.get(pools, coords, &mut []), UVec3::ZERO)
+    }
+    #[inline]
+    fn probe_in_pools(pools: &[Pool], ptr: u32, coords: UVec3) -> (Option<Self::Voxel>, UVec3) {
+        let node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        node.probe(pools, coords)
+    }
+
+    #[inline]
+    fn visit<'a, S: Shape + ?Sized, F: FnMut(VisitedNode<'a, Self::LeafType, Self::Voxel>)>(
+        &'a self,
+        _pools: &'a [Pool],
+        offset: IVec3,
+        _shape: &S,
+        f: &mut F,
+    ) {
+        f(VisitedNode::Leaf(offset, self))
+    }
+    #[inline]
+    fn visit_in_pools<
+        'a,
+        S: Shape + ?Sized,
+        F: FnMut(VisitedNode<'a, Self::LeafType, Self::Voxel>),
+    >(
+        pools: &'a [Pool],
+        ptr: u32,
+        offset: IVec3,
+        shape: &S,
+        f: &mut F,
+    ) {
+        let leaf_node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        leaf_node.visit(pools, offset, shape, f)
+    }
+
+    #[inline]
+    fn prune(&mut self, _pools: &mut [Pool]) -> bool {
+        self.is_empty()
+    }
+    #[inline]
+    fn prune_in_pools(pools: &mut [Pool], ptr: &mut u32) -> bool {
+        Self::is_empty_in_pools(pools, *ptr)
+    }
+
+    fn write_to(&self, _pools: &[Pool], writer: &mut impl Write) -> std::io::Result<()>
+    where
+        Self::Voxel: Serializable,
+    {
+        self.occupancy.write_words(writer)?;
+        self.active.write_words(writer)?;
+        for value in self.iter_values() {
+            value.write_to(writer)?;
+        }
+        Ok(())
+    }
+    fn write_in_pools(pools: &[Pool], ptr: u32, writer: &mut impl Write) -> std::io::Result<()>
+    where
+        Self::Voxel: Serializable,
+    {
+        let leaf_node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        leaf_node.write_to(pools, writer)
+    }
+    fn read_from(&mut self, _pools: &mut [Pool], reader: &mut impl Read) -> std::io::Result<()>
+    where
+        Self::Voxel: Serializable,
+    {
+        self.occupancy.read_words(reader)?;
+        self.active.read_words(reader)?;
+        for index in self.occupancy.iter_set_bits() {
+            self.values[index] = V::read_from(reader)?;
+        }
+        Ok(())
+    }
+    fn read_in_pools(pools: &mut [Pool], reader: &mut impl Read) -> std::io::Result<u32>
+    where
+        Self::Voxel: Serializable,
+    {
+        let ptr = unsafe { pools[Self::LEVEL].alloc::<Self>() };
+        let leaf_node = unsafe { pools[Self::LEVEL].get_item_mut::<Self>(ptr) };
+        if let Err(err) = leaf_node.read_from(&mut [], reader) {
+            pools[Self::LEVEL].free(ptr);
+            return Err(err);
+        }
+        Ok(ptr)
+    }
+
     type Iterator<'a> = LeafNodeIterator<'a, LOG2>;
-    fn iter<'a>(&'a self, _pool: &'a [Pool], offset: UVec3) -> Self::Iterator<'a> {
+    fn iter<'a>(&'a self, _pool: &'a [Pool], offset: IVec3) -> Self::Iterator<'a> {
         LeafNodeIterator {
             location_offset: offset,
             bits_iterator: self.occupancy.iter_set_bits(),
         }
     }
-    fn iter_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: UVec3) -> Self::Iterator<'a> {
+    fn iter_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: IVec3) -> Self::Iterator<'a> {
         let node = unsafe { pools[0].get_item::<Self>(ptr) };
         LeafNodeIterator {
             location_offset: offset,
@@ -150,21 +477,22 @@ where
         }
     }
 
-    type LeafIterator<'a> = Once<(UVec3, &'a UnsafeCell<Self>)>;
+    type LeafIterator<'a> = Once<(IVec3, &'a UnsafeCell<Self>)>;
 
     #[inline]
-    fn iter_leaf<'a>(&'a self, _pools: &'a [Pool], offset: UVec3) -> Self::LeafIterator<'a> {
+    fn iter_leaf<'a>(&'a self, _pools: &'a [Pool], offset: IVec3) -> Self::LeafIterator<'a> {
         std::iter::once((offset, unsafe { std::mem::transmute(self) }))
     }
 
     #[inline]
-    fn iter_leaf_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: UVec3) -> Self::LeafIterator<'a> {
+    fn iter_leaf_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: IVec3) -> Self::LeafIterator<'a> {
         let node = unsafe { pools[0].get_item::<Self>(ptr) };
         std::iter::once((offset, unsafe { std::mem::transmute(node) }))
     }
 }
 
-impl<const LOG2: ConstUVec3> const NodeConst for LeafNode<LOG2>
+impl<const LOG2: ConstUVec3, V: Copy + Default + PartialEq + 'static> const NodeConst
+    for LeafNode<LOG2, V>
 where
     [(); size_of_grid(LOG2) / size_of::<usize>() / 8]: Sized,
 {
@@ -176,11 +504,13 @@ where
             fanout_log2: LOG2.to_glam(),
             extent_mask: Self::EXTENT_MASK,
             setter: Self::set_in_pools,
+            is_empty: Self::is_empty_in_pools,
         });
     }
 }
 
-impl<const LOG2: ConstUVec3> std::fmt::Debug for LeafNode<LOG2>
+impl<const LOG2: ConstUVec3, V: Copy + Default + PartialEq + 'static> std::fmt::Debug
+    for LeafNode<LOG2, V>
 where
     [(); size_of_grid(LOG2) / size_of::<usize>() / 8]: Sized,
 {
@@ -195,14 +525,14 @@ pub struct LeafNodeIterator<'a, const LOG2: ConstUVec3>
 where
     [(); size_of_grid(LOG2) / size_of::<usize>() / 8]: Sized,
 {
-    location_offset: UVec3,
+    location_offset: IVec3,
     bits_iterator: SetBitIterator<'a, { size_of_grid(LOG2) }>,
 }
 impl<'a, const LOG2: ConstUVec3> Iterator for LeafNodeIterator<'a, LOG2>
 where
     [(); size_of_grid(LOG2) / size_of::<usize>() / 8]: Sized,
 {
-    type Item = UVec3;
+    type Item = IVec3;
 
     fn next(&mut self) -> Option<Self::Item> {
         let index = self.bits_iterator.next()?;
@@ -210,7 +540,7 @@ where
         let z = index & ((1 << LOG2.z) - 1);
         let y = (index >> LOG2.z) & ((1 << LOG2.y) - 1);
         let x = index >> (LOG2.z + LOG2.y);
-        let location = UVec3::new(x as u32, y as u32, z as u32);
+        let location = IVec3::new(x as i32, y as i32, z as i32);
         Some(location + self.location_offset)
     }
 }
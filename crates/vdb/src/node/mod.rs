@@ -5,14 +5,15 @@ mod root;
 use std::alloc::Layout;
 use std::cell::UnsafeCell;
 use std::fmt::Debug;
+use std::io::{Read, Write};
 use std::mem::MaybeUninit;
 
-use glam::UVec3;
+use glam::{IVec3, UVec3};
 pub use internal::*;
 pub use leaf::*;
 pub use root::*;
 
-use crate::{ConstUVec3, Pool};
+use crate::{Aabb, ConstUVec3, CsgOp, Pool, Serializable, Shape};
 
 pub struct NodeMeta<V> {
     pub(crate) layout: Layout,
@@ -20,15 +21,16 @@ pub struct NodeMeta<V> {
         fn(pools: &[Pool], coords: UVec3, ptr: u32, cached_path: &mut [u32]) -> Option<V>,
     pub(crate) setter:
         fn(pools: &mut [Pool], coords: UVec3, ptr: u32, value: Option<V>, cached_path: &mut [u32]),
+    pub(crate) is_empty: fn(pools: &[Pool], ptr: u32) -> bool,
     pub(crate) extent_log2: UVec3,
     pub(crate) fanout_log2: UVec3,
 
     pub(crate) extent_mask: UVec3, // = (1 << extent_log2) - 1
 }
 
-pub trait Node: 'static + Default + Debug {
+pub trait Node: 'static + Default + Debug + Clone {
     /// span of the node.
-    type LeafType: IsLeaf;
+    type LeafType: IsLeaf<Voxel = Self::Voxel>;
     const EXTENT_LOG2: UVec3;
     const EXTENT: UVec3;
     const EXTENT_MASK: UVec3; // = (1 << extent_log2) - 1
@@ -39,9 +41,12 @@ pub trait Node: 'static + Default + Debug {
     const LEVEL: usize;
     fn new() -> Self;
 
-    type Voxel;
+    /// Value stored for each occupied voxel.
+    type Voxel: Copy + Default + PartialEq + 'static;
 
     /// Get the value of a voxel at the specified coordinates within the node space.
+    /// The node space of the root node is the whole signed coordinate space, so its
+    /// coordinates are the two's complement bit patterns of signed coordinates.
     /// This is called when the node was owned.
     /// Implementation will write to cached_path for all levels below the current level.
     fn get(&self, pools: &[Pool], coords: UVec3, cached_path: &mut [u32]) -> Option<Self::Voxel>;
@@ -76,17 +81,203 @@ pub trait Node: 'static + Default + Debug {
         cached_path: &mut [u32],
     );
 
-    type Iterator<'a>: Iterator<Item = UVec3>;
+    /// Returns true if the node contains no occupied voxels.
+    fn is_empty(&self) -> bool;
+    /// Returns true if the node located in a node pool contains no occupied voxels.
+    fn is_empty_in_pools(pools: &[Pool], ptr: u32) -> bool;
+
+    /// Returns the value of all voxels within the node if the node is completely
+    /// filled with the same value.
+    fn uniform_value(&self) -> Option<Self::Voxel>;
+
+    /// Set all voxels within `aabb` to `value`. `aabb` is in node space and must be
+    /// contained within the node. Regions covering entire child nodes are stored as tiles.
+    /// This is called when the node was owned.
+    fn fill(&mut self, pools: &mut [Pool], aabb: Aabb, value: Option<Self::Voxel>);
+    /// Set all voxels within `aabb` to `value`. `aabb` is in node space and must be
+    /// contained within the node. Regions covering entire child nodes are stored as tiles.
+    /// This is called when the node was located in a node pool.
+    fn fill_in_pools(pools: &mut [Pool], ptr: u32, aabb: Aabb, value: Option<Self::Voxel>);
+
+    /// Return the node located in a node pool and all of its descendants to the pools.
+    fn free_in_pools(pools: &mut [Pool], ptr: u32);
+
+    /// Copy the node located in `src_pools` and all of its descendants into `dst_pools`.
+    /// Returns the pointer to the new node in `dst_pools`.
+    fn clone_in_pools(src_pools: &[Pool], src_ptr: u32, dst_pools: &mut [Pool]) -> u32;
+
+    /// Set all unoccupied voxels within the node to `value`.
+    /// This is called when the node was located in a node pool.
+    fn fill_empty_in_pools(pools: &mut [Pool], ptr: u32, value: Self::Voxel);
+
+    /// Combine `other` into the current node. When both nodes contain a voxel, the value
+    /// of the current node is kept. Empty child nodes are freed, but the node itself
+    /// may become empty.
+    /// This is called when the node was owned.
+    fn csg(&mut self, pools: &mut [Pool], other: &Self, other_pools: &[Pool], op: CsgOp);
+    /// Combine `other` into the current node. When both nodes contain a voxel, the value
+    /// of the current node is kept. Empty child nodes are freed, but the node itself
+    /// may become empty.
+    /// This is called when the node was located in a node pool.
+    fn csg_in_pools(pools: &mut [Pool], ptr: u32, other_pools: &[Pool], other_ptr: u32, op: CsgOp);
+
+    /// Add `offsets[level]` to the pointers to child nodes at each level, after the pools
+    /// containing them were appended to other pools with [`Pool::append`].
+    /// This is called when the node was owned.
+    fn relocate(&mut self, pools: &mut [Pool], offsets: &[u32]);
+    /// Add `offsets[level]` to the pointers to child nodes at each level, after the pools
+    /// containing them were appended to other pools with [`Pool::append`].
+    /// This is called when the node was located in a node pool.
+    fn relocate_in_pools(pools: &mut [Pool], ptr: u32, offsets: &[u32]);
+
+    /// Move all voxels of `other`, whose descendants are located in the same pools, into
+    /// the current node. When both nodes contain a voxel, the value of the current node is
+    /// kept. Child nodes of `other` are either moved over or freed.
+    /// This is called when the node was owned.
+    fn merge(&mut self, pools: &mut [Pool], other: Self);
+    /// Move all voxels of the node at `other_ptr` into the node at `ptr`. When both nodes
+    /// contain a voxel, the value of the current node is kept. The node at `other_ptr` is
+    /// freed, and its child nodes are either moved over or freed.
+    /// This is called when the node was located in a node pool.
+    fn merge_in_pools(pools: &mut [Pool], ptr: u32, other_ptr: u32);
+
+    /// Returns the leaf node containing `coords`, allocating nodes along the path and
+    /// densifying tiles as needed. The returned leaf stays valid until it gets freed.
+    /// This is called when the node was owned.
+    fn touch_leaf(&mut self, pools: &mut [Pool], coords: UVec3) -> *mut Self::LeafType;
+    /// Returns the leaf node containing `coords`, allocating nodes along the path and
+    /// densifying tiles as needed. The returned leaf stays valid until it gets freed.
+    /// This is called when the node was located in a node pool.
+    fn touch_leaf_in_pools(pools: &mut [Pool], ptr: u32, coords: UVec3) -> *mut Self::LeafType;
+
+    /// Get the value of a voxel at the specified coordinates within the node space, and the
+    /// log2 extent of the largest node or tile containing it in which all voxels share the
+    /// same value. This is zero when the voxel is located in a leaf node.
+    /// This is called when the node was owned.
+    fn probe(&self, pools: &[Pool], coords: UVec3) -> (Option<Self::Voxel>, UVec3);
+    /// Get the value of a voxel at the specified coordinates within the node space, and the
+    /// log2 extent of the largest node or tile containing it in which all voxels share the
+    /// same value. This is zero when the voxel is located in a leaf node.
+    /// This is called when the node was located in a node pool.
+    fn probe_in_pools(pools: &[Pool], ptr: u32, coords: UVec3) -> (Option<Self::Voxel>, UVec3);
+
+    /// Call `f` on all leaf nodes and tiles intersecting with `shape`.
+    /// `offset` is the location of the node, and `shape` is in the same space as `offset`.
+    /// This is called when the node was owned.
+    fn visit<'a, S: Shape + ?Sized, F: FnMut(VisitedNode<'a, Self::LeafType, Self::Voxel>)>(
+        &'a self,
+        pools: &'a [Pool],
+        offset: IVec3,
+        shape: &S,
+        f: &mut F,
+    );
+    /// Call `f` on all leaf nodes and tiles intersecting with `shape`.
+    /// `offset` is the location of the node, and `shape` is in the same space as `offset`.
+    /// This is called when the node was located in a node pool.
+    fn visit_in_pools<
+        'a,
+        S: Shape + ?Sized,
+        F: FnMut(VisitedNode<'a, Self::LeafType, Self::Voxel>),
+    >(
+        pools: &'a [Pool],
+        ptr: u32,
+        offset: IVec3,
+        shape: &S,
+        f: &mut F,
+    );
+
+    /// Free all empty child nodes recursively.
+    /// This is called when the node was owned.
+    /// Returns true if the node itself became empty and may be freed by its parent.
+    fn prune(&mut self, pools: &mut [Pool]) -> bool;
+    /// Free all empty child nodes recursively.
+    /// This is called when the node was located in a node pool.
+    /// Returns true if the node itself became empty and may be freed by its parent.
+    /// A node shared with a snapshot is only copied if pruning changes it, in which case
+    /// `ptr` is updated to point to the copy.
+    fn prune_in_pools(pools: &mut [Pool], ptr: &mut u32) -> bool;
+
+    /// Write the node and all of its descendants.
+    /// This is called when the node was owned.
+    fn write_to(&self, pools: &[Pool], writer: &mut impl Write) -> std::io::Result<()>
+    where
+        Self::Voxel: Serializable;
+    /// Write the node and all of its descendants.
+    /// This is called when the node was located in a node pool.
+    fn write_in_pools(pools: &[Pool], ptr: u32, writer: &mut impl Write) -> std::io::Result<()>
+    where
+        Self::Voxel: Serializable;
+    /// Read the node and all of its descendants, allocating child nodes from the pools.
+    /// This is called when the node was owned.
+    fn read_from(&mut self, pools: &mut [Pool], reader: &mut impl Read) -> std::io::Result<()>
+    where
+        Self::Voxel: Serializable;
+    /// Read the node and all of its descendants into a node newly allocated from the pools.
+    /// Returns the pointer to the new node.
+    fn read_in_pools(pools: &mut [Pool], reader: &mut impl Read) -> std::io::Result<u32>
+    where
+        Self::Voxel: Serializable;
+
+    /// Iterates over all occupied voxels, including the voxels covered by tiles.
+    type Iterator<'a>: Iterator<Item = IVec3>;
     /// This is called when the node was owned as the root node in the tree.
-    fn iter<'a>(&'a self, pools: &'a [Pool], offset: UVec3) -> Self::Iterator<'a>;
+    fn iter<'a>(&'a self, pools: &'a [Pool], offset: IVec3) -> Self::Iterator<'a>;
     /// This is called when the node was located in a node pool.
-    fn iter_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: UVec3) -> Self::Iterator<'a>;
+    fn iter_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: IVec3) -> Self::Iterator<'a>;
 
-    type LeafIterator<'a>: Iterator<Item = (UVec3, &'a UnsafeCell<Self::LeafType>)>;
+    type LeafIterator<'a>: Iterator<Item = (IVec3, &'a UnsafeCell<Self::LeafType>)>;
     /// This is called when the node was owned as the root node in the tree.
-    fn iter_leaf<'a>(&'a self, pools: &'a [Pool], offset: UVec3) -> Self::LeafIterator<'a>;
+    fn iter_leaf<'a>(&'a self, pools: &'a [Pool], offset: IVec3) -> Self::LeafIterator<'a>;
     /// This is called when the node was located in a node pool.
-    fn iter_leaf_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: UVec3) -> Self::LeafIterator<'a>;
+    fn iter_leaf_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: IVec3) -> Self::LeafIterator<'a>;
+}
+
+/// Leaf nodes and tiles visited by [`Node::visit`].
+pub enum VisitedNode<'a, L, V> {
+    /// A leaf node and the location of its first voxel.
+    Leaf(IVec3, &'a L),
+    /// A tile in which all voxels within the box have the same value.
+    Tile(Aabb, V),
+}
+
+/// Coordinates of all voxels within a tile, with z varying fastest like the voxels
+/// within leaf nodes.
+pub(crate) struct TileIterator {
+    origin: IVec3,
+    extent_log2: UVec3,
+    next: u64,
+    len: u64,
+}
+
+impl TileIterator {
+    pub(crate) fn new(origin: IVec3, extent_log2: UVec3) -> Self {
+        let len_log2 = extent_log2.x + extent_log2.y + extent_log2.z;
+        Self {
+            origin,
+            extent_log2,
+            next: 0,
+            len: 1_u64.checked_shl(len_log2).unwrap_or(u64::MAX),
+        }
+    }
+}
+
+impl Iterator for TileIterator {
+    type Item = IVec3;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.len {
+            return None;
+        }
+        let index = self.next;
+        self.next += 1;
+        let z = index & ((1 << self.extent_log2.z) - 1);
+        let y = (index >> self.extent_log2.z) & ((1 << self.extent_log2.y) - 1);
+        let x = index >> (self.extent_log2.z + self.extent_log2.y);
+        Some(
+            self.origin
+                .wrapping_add(IVec3::new(x as i32, y as i32, z as i32)),
+        )
+    }
 }
 
 /// Trait that contains const methods for the node.
@@ -108,17 +299,53 @@ pub trait NodeConst: Node {
 /// let hierarchy = <hierarchy!(2, 2, 1)>::new();
 /// // Create a three-level tree with infinite size (implemented with a HashMap), 4x4x4 intermediate nodes and 2x2x2 leafs.
 /// let hierarchy = <hierarchy!(#, 2, 1)>::new();
+/// // Leaf nodes store a `bool` per voxel by default. Specify another value type after a semicolon.
+/// let hierarchy = <hierarchy!(3, 2; u8)>::new();
+/// // Levels may be anisotropic. Write the log2 of the fanout on each axis as `(x, y, z)`.
+/// // This creates 16x4x16 leafs in 8x2x8 intermediate nodes, suitable for flat terrain.
+/// let hierarchy = <hierarchy!(#, (3, 1, 3), (4, 2, 4))>::new();
+/// // Isotropic and anisotropic levels can be mixed freely.
+/// let hierarchy = <hierarchy!(2, (1, 4, 1), 2; u8)>::new();
+/// ```
+///
+/// Every level must subdivide each axis, so all components of a level need to be at least 1.
+/// Like isotropic levels, each node also needs at least 64 cells.
+/// ```compile_fail
+/// use dust_vdb::hierarchy;
+/// // Error: the middle level doesn't subdivide the y axis.
+/// type Flat = hierarchy!(#, (3, 0, 3), 2);
 /// ```
 #[macro_export]
 macro_rules! hierarchy {
+    (@log2 (0, $y: tt, $z: tt)) => {
+        compile_error!("each level of a hierarchy must subdivide the x axis")
+    };
+    (@log2 ($x: tt, 0, $z: tt)) => {
+        compile_error!("each level of a hierarchy must subdivide the y axis")
+    };
+    (@log2 ($x: tt, $y: tt, 0)) => {
+        compile_error!("each level of a hierarchy must subdivide the z axis")
+    };
+    (@log2 0) => {
+        compile_error!("each level of a hierarchy must subdivide every axis")
+    };
+    (@log2 ($x: tt, $y: tt, $z: tt)) => {
+        dust_vdb::ConstUVec3{x:$x,y:$y,z:$z}
+    };
+    (@log2 $e: tt) => {
+        dust_vdb::ConstUVec3{x:$e,y:$e,z:$e}
+    };
     ($e: tt) => {
-        $crate::LeafNode<{dust_vdb::ConstUVec3{x:$e,y:$e,z:$e}}>
+        $crate::LeafNode<{hierarchy!(@log2 $e)}>
+    };
+    ($e: tt; $t: ty) => {
+        $crate::LeafNode<{hierarchy!(@log2 $e)}, $t>
     };
-    (#, $($n:tt),+) => {
-        $crate::RootNode<hierarchy!($($n),*)>
+    (#, $($n:tt),+ $(; $t: ty)?) => {
+        $crate::RootNode<hierarchy!($($n),* $(; $t)?)>
     };
-    ($e: tt, $($n:tt),+) => {
-        $crate::InternalNode::<hierarchy!($($n),*), {dust_vdb::ConstUVec3{x:$e,y:$e,z:$e}}>
+    ($e: tt, $($n:tt),+ $(; $t: ty)?) => {
+        $crate::InternalNode::<hierarchy!($($n),* $(; $t)?), {hierarchy!(@log2 $e)}>
     };
 }
 
@@ -1,18 +1,26 @@
-use std::{cell::UnsafeCell, marker::PhantomData, mem::MaybeUninit};
+use std::{
+    cell::UnsafeCell,
+    io::{Read, Write},
+    marker::PhantomData,
+    mem::MaybeUninit,
+};
 
-use glam::UVec3;
+use glam::{IVec3, UVec3};
 
-use crate::{Node, NodeConst, Pool};
+use crate::{Aabb, CsgOp, Node, NodeConst, Pool, Serializable, Shape, VisitedNode};
 
-use super::NodeMeta;
+use super::{NodeMeta, TileIterator};
 
-pub enum RootNodeEntry {
+#[derive(Clone)]
+pub enum RootNodeEntry<V> {
+    /// Points to a child node.
     Occupied(u32),
-    Free(u32),
+    /// All voxels within the tile have the same value.
+    Free(V),
 }
 
 #[derive(PartialEq, Eq, Clone)]
-pub struct RootKey(UVec3);
+pub struct RootKey(IVec3);
 impl std::hash::Hash for RootKey {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         let root_hash = (self.0.x as u64).wrapping_mul(73856093_u64)
@@ -27,10 +35,84 @@ impl std::hash::Hash for RootKey {
 #[derive(Default)]
 pub struct RootNode<CHILD: Node> {
     /// Map from [`RootKey`] to tiles.
-    map: std::collections::HashMap<RootKey, RootNodeEntry, nohash::BuildNoHashHasher<u64>>,
+    map: std::collections::HashMap<
+        RootKey,
+        RootNodeEntry<CHILD::Voxel>,
+        nohash::BuildNoHashHasher<u64>,
+    >,
     _marker: PhantomData<CHILD>,
 }
 
+impl<CHILD: Node> RootNode<CHILD> {
+    /// Returns the key of the child node containing `coords`. The coordinates are
+    /// interpreted as signed, so that the tree extends in all directions.
+    #[inline]
+    fn key_of(coords: UVec3) -> RootKey {
+        RootKey(coords.as_ivec3() >> CHILD::EXTENT_LOG2.as_ivec3())
+    }
+    /// Returns the location of the first voxel of the child node at `key`.
+    #[inline]
+    fn origin_of(key: &RootKey) -> IVec3 {
+        key.0 << CHILD::EXTENT_LOG2.as_ivec3()
+    }
+
+    /// Set all voxels within the intersection of `aabb` and the child at `key` to `value`.
+    fn fill_child(
+        &mut self,
+        pools: &mut [Pool],
+        key: RootKey,
+        aabb: &Aabb,
+        value: Option<CHILD::Voxel>,
+    ) {
+        let child_aabb = Aabb::from_extent(Self::origin_of(&key), CHILD::EXTENT);
+        let clipped = aabb.intersect(&child_aabb);
+        if clipped == child_aabb {
+            // The child is covered entirely. Replace it with a tile.
+            if let Some(RootNodeEntry::Occupied(child_ptr)) = self.map.get(&key) {
+                CHILD::free_in_pools(pools, *child_ptr);
+            }
+            match value {
+                Some(value) => self.map.insert(key, RootNodeEntry::Free(value)),
+                None => self.map.remove(&key),
+            };
+            return;
+        }
+        let child_ptr = match self.map.get_mut(&key) {
+            Some(RootNodeEntry::Occupied(child_ptr)) => Self::child_mut(pools, child_ptr),
+            Some(RootNodeEntry::Free(tile)) if Some(*tile) == value => return,
+            None if value.is_none() => return,
+            _ => self.make_child(pools, key.clone()),
+        };
+        let child_aabb = Aabb::new(clipped.min - child_aabb.min, clipped.max - child_aabb.min);
+        CHILD::fill_in_pools(pools, child_ptr, child_aabb, value);
+        if value.is_none() && CHILD::is_empty_in_pools(pools, child_ptr) {
+            pools[CHILD::LEVEL].free(child_ptr);
+            self.map.remove(&key);
+        }
+    }
+    /// Returns the child node at `key` for writing. A child node shared with a snapshot
+    /// is copied first, and the entry is re-pointed to the copy.
+    fn child_mut(pools: &mut [Pool], child_ptr: &mut u32) -> u32 {
+        *child_ptr = pools[CHILD::LEVEL].make_item_unique(*child_ptr);
+        *child_ptr
+    }
+    /// Allocate a child node at `key`. If the entry was a tile, the child node will be
+    /// filled with the tile value.
+    fn make_child(&mut self, pools: &mut [Pool], key: RootKey) -> u32 {
+        let child_ptr = unsafe { pools[CHILD::LEVEL].alloc::<CHILD>() };
+        if let Some(RootNodeEntry::Free(tile)) = self.map.get(&key) {
+            CHILD::fill_in_pools(
+                pools,
+                child_ptr,
+                Aabb::from_extent(IVec3::ZERO, CHILD::EXTENT),
+                Some(*tile),
+            );
+        }
+        self.map.insert(key, RootNodeEntry::Occupied(child_ptr));
+        child_ptr
+    }
+}
+
 impl<CHILD: Node> Node for RootNode<CHILD> {
     type LeafType = CHILD::LeafType;
     const EXTENT_LOG2: UVec3 = UVec3 {
@@ -59,11 +141,16 @@ impl<CHILD: Node> Node for RootNode<CHILD> {
     type Voxel = CHILD::Voxel;
     #[inline]
     fn get(&self, pools: &[Pool], coords: UVec3, cached_path: &mut [u32]) -> Option<Self::Voxel> {
-        let root_offset = coords >> CHILD::EXTENT_LOG2;
-        let entry = self.map.get(&RootKey(root_offset));
+        let entry = self.map.get(&Self::key_of(coords));
         if let Some(entry) = entry {
             match entry {
-                RootNodeEntry::Free(_material_id) => None,
+                RootNodeEntry::Free(value) => {
+                    // Invalidate the stale path below this node.
+                    if !cached_path.is_empty() {
+                        cached_path[..=CHILD::LEVEL].fill(u32::MAX);
+                    }
+                    Some(*value)
+                }
                 RootNodeEntry::Occupied(ptr) => unsafe {
                     let _child_node = pools[CHILD::LEVEL].get_item::<CHILD>(*ptr);
                     let new_coords = UVec3 {
@@ -75,6 +162,10 @@ impl<CHILD: Node> Node for RootNode<CHILD> {
                 },
             }
         } else {
+            // Invalidate the stale path below this node.
+            if !cached_path.is_empty() {
+                cached_path[..=CHILD::LEVEL].fill(u32::MAX);
+            }
             None
         }
     }
@@ -87,27 +178,40 @@ impl<CHILD: Node> Node for RootNode<CHILD> {
         cached_path: &mut [u32],
     ) {
         // ptr is meaningless and always 0 for root nodes.
-        let root_offset = coords >> CHILD::EXTENT_LOG2;
-        let key = RootKey(root_offset);
-
-        if value.is_some() {
-            // Ensure that the node contains stuff on ptr
-            if !self.map.contains_key(&key) {
-                let new_node_ptr = unsafe { pools[CHILD::LEVEL].alloc::<CHILD>() };
-                self.map
-                    .insert(key.clone(), RootNodeEntry::Occupied(new_node_ptr));
-            }
+        let key = Self::key_of(coords);
 
-            let child_ptr = match self.map.get(&key).unwrap() {
-                RootNodeEntry::Occupied(ptr) => *ptr,
-                RootNodeEntry::Free(_) => todo!(),
-            };
-            let new_coords = UVec3 {
-                x: coords.x & ((1_u32 << CHILD::EXTENT_LOG2.x) - 1),
-                y: coords.y & ((1_u32 << CHILD::EXTENT_LOG2.y) - 1),
-                z: coords.z & ((1_u32 << CHILD::EXTENT_LOG2.z) - 1),
-            };
-            CHILD::set_in_pools(pools, new_coords, child_ptr, value, cached_path)
+        let child_ptr = match self.map.get_mut(&key) {
+            Some(RootNodeEntry::Occupied(child_ptr)) => Self::child_mut(pools, child_ptr),
+            Some(RootNodeEntry::Free(tile)) if Some(*tile) == value => {
+                // The tile already has the requested value.
+                if !cached_path.is_empty() {
+                    cached_path[..=CHILD::LEVEL].fill(u32::MAX);
+                }
+                return;
+            }
+            None if value.is_none() => {
+                // Clearing a voxel that was never set.
+                if !cached_path.is_empty() {
+                    cached_path[..=CHILD::LEVEL].fill(u32::MAX);
+                }
+                return;
+            }
+            // Allocate a child node, densifying the tile if needed.
+            _ => self.make_child(pools, key.clone()),
+        };
+        let new_coords = UVec3 {
+            x: coords.x & ((1_u32 << CHILD::EXTENT_LOG2.x) - 1),
+            y: coords.y & ((1_u32 << CHILD::EXTENT_LOG2.y) - 1),
+            z: coords.z & ((1_u32 << CHILD::EXTENT_LOG2.z) - 1),
+        };
+        CHILD::set_in_pools(pools, new_coords, child_ptr, value, cached_path);
+        if value.is_none() && CHILD::is_empty_in_pools(pools, child_ptr) {
+            // The child node was completely cleared. Return it to the pool.
+            pools[CHILD::LEVEL].free(child_ptr);
+            self.map.remove(&key);
+            if !cached_path.is_empty() {
+                cached_path[CHILD::LEVEL] = u32::MAX;
+            }
         }
     }
 
@@ -129,22 +233,338 @@ impl<CHILD: Node> Node for RootNode<CHILD> {
     ) {
         unreachable!("Root Node is never kept in a pool!")
     }
+
+    fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+    fn is_empty_in_pools(_pools: &[Pool], _ptr: u32) -> bool {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn uniform_value(&self) -> Option<Self::Voxel> {
+        // The root node is infinitely large and can never be completely filled.
+        None
+    }
+
+    fn fill(&mut self, pools: &mut [Pool], aabb: Aabb, value: Option<Self::Voxel>) {
+        let first = aabb.min >> CHILD::EXTENT_LOG2.as_ivec3();
+        let last = (aabb.max - IVec3::ONE) >> CHILD::EXTENT_LOG2.as_ivec3();
+        if value.is_none() {
+            // Only existing entries need to be cleared.
+            let keys: Vec<RootKey> = self
+                .map
+                .keys()
+                .filter(|key| key.0.cmpge(first).all() && key.0.cmple(last).all())
+                .cloned()
+                .collect();
+            for key in keys {
+                self.fill_child(pools, key, &aabb, value);
+            }
+            return;
+        }
+        for x in first.x..=last.x {
+            for y in first.y..=last.y {
+                for z in first.z..=last.z {
+                    self.fill_child(pools, RootKey(IVec3::new(x, y, z)), &aabb, value);
+                }
+            }
+        }
+    }
+    fn fill_in_pools(_pools: &mut [Pool], _ptr: u32, _aabb: Aabb, _value: Option<Self::Voxel>) {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn free_in_pools(_pools: &mut [Pool], _ptr: u32) {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn clone_in_pools(_src_pools: &[Pool], _src_ptr: u32, _dst_pools: &mut [Pool]) -> u32 {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn fill_empty_in_pools(_pools: &mut [Pool], _ptr: u32, _value: Self::Voxel) {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn csg(&mut self, pools: &mut [Pool], other: &Self, other_pools: &[Pool], op: CsgOp) {
+        if op == CsgOp::Intersection {
+            // Remove everything not present in the other node.
+            self.map.retain(|key, entry| {
+                if other.map.contains_key(key) {
+                    return true;
+                }
+                if let RootNodeEntry::Occupied(child_ptr) = entry {
+                    CHILD::free_in_pools(pools, *child_ptr);
+                }
+                false
+            });
+        }
+        for (key, other_entry) in other.map.iter() {
+            let child_ptr = match (self.map.get_mut(key), other_entry, op) {
+                (
+                    Some(RootNodeEntry::Occupied(child_ptr)),
+                    RootNodeEntry::Free(_),
+                    CsgOp::Intersection | CsgOp::Difference,
+                ) => *child_ptr,
+                (Some(RootNodeEntry::Occupied(child_ptr)), _, _) => {
+                    Self::child_mut(pools, child_ptr)
+                }
+                (None, RootNodeEntry::Occupied(other_child), CsgOp::Union) => {
+                    let child_ptr = CHILD::clone_in_pools(other_pools, *other_child, pools);
+                    self.map
+                        .insert(key.clone(), RootNodeEntry::Occupied(child_ptr));
+                    continue;
+                }
+                (None, RootNodeEntry::Free(value), CsgOp::Union) => {
+                    self.map.insert(key.clone(), RootNodeEntry::Free(*value));
+                    continue;
+                }
+                (None, _, _) => continue,
+                (Some(RootNodeEntry::Free(_)), _, CsgOp::Union) => continue,
+                (Some(RootNodeEntry::Free(_)), RootNodeEntry::Free(_), CsgOp::Intersection) => {
+                    continue
+                }
+                (Some(RootNodeEntry::Free(_)), RootNodeEntry::Free(_), CsgOp::Difference) => {
+                    self.map.remove(key);
+                    continue;
+                }
+                // Densify the tile and combine it with the child of the other node.
+                (Some(RootNodeEntry::Free(_)), RootNodeEntry::Occupied(_), _) => {
+                    self.make_child(pools, key.clone())
+                }
+            };
+            match (other_entry, op) {
+                (RootNodeEntry::Occupied(other_child), _) => {
+                    CHILD::csg_in_pools(pools, child_ptr, other_pools, *other_child, op)
+                }
+                (RootNodeEntry::Free(value), CsgOp::Union) => {
+                    CHILD::fill_empty_in_pools(pools, child_ptr, *value)
+                }
+                (RootNodeEntry::Free(_), CsgOp::Intersection) => (),
+                (RootNodeEntry::Free(_), CsgOp::Difference) => {
+                    CHILD::free_in_pools(pools, child_ptr);
+                    self.map.remove(key);
+                    continue;
+                }
+            }
+            if CHILD::is_empty_in_pools(pools, child_ptr) {
+                pools[CHILD::LEVEL].free(child_ptr);
+                self.map.remove(key);
+            }
+        }
+    }
+    fn csg_in_pools(
+        _pools: &mut [Pool],
+        _ptr: u32,
+        _other_pools: &[Pool],
+        _other_ptr: u32,
+        _op: CsgOp,
+    ) {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn relocate(&mut self, pools: &mut [Pool], offsets: &[u32]) {
+        for entry in self.map.values_mut() {
+            if let RootNodeEntry::Occupied(child_ptr) = entry {
+                *child_ptr += offsets[CHILD::LEVEL];
+                CHILD::relocate_in_pools(pools, *child_ptr, offsets);
+            }
+        }
+    }
+    fn relocate_in_pools(_pools: &mut [Pool], _ptr: u32, _offsets: &[u32]) {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn merge(&mut self, pools: &mut [Pool], other: Self) {
+        for (key, other_entry) in other.map {
+            match (self.map.get_mut(&key), other_entry) {
+                (None, other_entry) => {
+                    self.map.insert(key, other_entry);
+                }
+                (
+                    Some(RootNodeEntry::Occupied(child_ptr)),
+                    RootNodeEntry::Occupied(other_child),
+                ) => {
+                    let child_ptr = Self::child_mut(pools, child_ptr);
+                    CHILD::merge_in_pools(pools, child_ptr, other_child)
+                }
+                (Some(RootNodeEntry::Occupied(child_ptr)), RootNodeEntry::Free(value)) => {
+                    let child_ptr = Self::child_mut(pools, child_ptr);
+                    CHILD::fill_empty_in_pools(pools, child_ptr, value)
+                }
+                (Some(RootNodeEntry::Free(_)), RootNodeEntry::Occupied(other_child)) => {
+                    CHILD::free_in_pools(pools, other_child)
+                }
+                (Some(RootNodeEntry::Free(_)), RootNodeEntry::Free(_)) => (),
+            }
+        }
+    }
+    fn merge_in_pools(_pools: &mut [Pool], _ptr: u32, _other_ptr: u32) {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn touch_leaf(&mut self, pools: &mut [Pool], coords: UVec3) -> *mut Self::LeafType {
+        let key = Self::key_of(coords);
+        let child_ptr = match self.map.get_mut(&key) {
+            Some(RootNodeEntry::Occupied(child_ptr)) => Self::child_mut(pools, child_ptr),
+            _ => self.make_child(pools, key),
+        };
+        CHILD::touch_leaf_in_pools(pools, child_ptr, coords & CHILD::EXTENT_MASK)
+    }
+    fn touch_leaf_in_pools(_pools: &mut [Pool], _ptr: u32, _coords: UVec3) -> *mut Self::LeafType {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn probe(&self, pools: &[Pool], coords: UVec3) -> (Option<Self::Voxel>, UVec3) {
+        match self.map.get(&Self::key_of(coords)) {
+            Some(RootNodeEntry::Occupied(ptr)) => {
+                CHILD::probe_in_pools(pools, *ptr, coords & CHILD::EXTENT_MASK)
+            }
+            Some(RootNodeEntry::Free(value)) => (Some(*value), CHILD::EXTENT_LOG2),
+            None => (None, CHILD::EXTENT_LOG2),
+        }
+    }
+    fn probe_in_pools(_pools: &[Pool], _ptr: u32, _coords: UVec3) -> (Option<Self::Voxel>, UVec3) {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn visit<'a, S: Shape + ?Sized, F: FnMut(VisitedNode<'a, Self::LeafType, Self::Voxel>)>(
+        &'a self,
+        pools: &'a [Pool],
+        offset: IVec3,
+        shape: &S,
+        f: &mut F,
+    ) {
+        for (key, entry) in self.map.iter() {
+            let child_origin = offset + Self::origin_of(key);
+            let child_aabb = Aabb::from_extent(child_origin, CHILD::EXTENT);
+            if !shape.intersects(&child_aabb) {
+                continue;
+            }
+            match entry {
+                RootNodeEntry::Occupied(child_ptr) => {
+                    CHILD::visit_in_pools(pools, *child_ptr, child_origin, shape, f)
+                }
+                RootNodeEntry::Free(value) => f(VisitedNode::Tile(child_aabb, *value)),
+            }
+        }
+    }
+    fn visit_in_pools<
+        'a,
+        S: Shape + ?Sized,
+        F: FnMut(VisitedNode<'a, Self::LeafType, Self::Voxel>),
+    >(
+        _pools: &'a [Pool],
+        _ptr: u32,
+        _offset: IVec3,
+        _shape: &S,
+        _f: &mut F,
+    ) {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn prune(&mut self, pools: &mut [Pool]) -> bool {
+        self.map.retain(|_, entry| match entry {
+            RootNodeEntry::Occupied(ptr) => {
+                if CHILD::prune_in_pools(pools, ptr) {
+                    pools[CHILD::LEVEL].free(*ptr);
+                    return false;
+                }
+                // Collapse uniform child nodes into tiles.
+                let child = unsafe { pools[CHILD::LEVEL].get_item::<CHILD>(*ptr) };
+                if let Some(value) = child.uniform_value() {
+                    pools[CHILD::LEVEL].free(*ptr);
+                    *entry = RootNodeEntry::Free(value);
+                }
+                true
+            }
+            RootNodeEntry::Free(_) => true,
+        });
+        self.is_empty()
+    }
+    fn prune_in_pools(_pools: &mut [Pool], _ptr: &mut u32) -> bool {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn write_to(&self, pools: &[Pool], writer: &mut impl Write) -> std::io::Result<()>
+    where
+        Self::Voxel: Serializable,
+    {
+        (self.map.len() as u64).write_to(writer)?;
+        for (key, entry) in self.map.iter() {
+            key.0.x.write_to(writer)?;
+            key.0.y.write_to(writer)?;
+            key.0.z.write_to(writer)?;
+            match entry {
+                RootNodeEntry::Occupied(child_ptr) => {
+                    0_u8.write_to(writer)?;
+                    CHILD::write_in_pools(pools, *child_ptr, writer)?;
+                }
+                RootNodeEntry::Free(value) => {
+                    1_u8.write_to(writer)?;
+                    value.write_to(writer)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn write_in_pools(_pools: &[Pool], _ptr: u32, _writer: &mut impl Write) -> std::io::Result<()>
+    where
+        Self::Voxel: Serializable,
+    {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+    fn read_from(&mut self, pools: &mut [Pool], reader: &mut impl Read) -> std::io::Result<()>
+    where
+        Self::Voxel: Serializable,
+    {
+        let len = u64::read_from(reader)?;
+        for _ in 0..len {
+            let key = RootKey(IVec3 {
+                x: i32::read_from(reader)?,
+                y: i32::read_from(reader)?,
+                z: i32::read_from(reader)?,
+            });
+            let entry = match u8::read_from(reader)? {
+                0 => RootNodeEntry::Occupied(CHILD::read_in_pools(pools, reader)?),
+                1 => RootNodeEntry::Free(CHILD::Voxel::read_from(reader)?),
+                _ => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "invalid root entry",
+                    ))
+                }
+            };
+            if let Some(RootNodeEntry::Occupied(child_ptr)) = self.map.insert(key, entry) {
+                CHILD::free_in_pools(pools, child_ptr);
+            }
+        }
+        Ok(())
+    }
+    fn read_in_pools(_pools: &mut [Pool], _reader: &mut impl Read) -> std::io::Result<u32>
+    where
+        Self::Voxel: Serializable,
+    {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
     type Iterator<'a> = RootIterator<'a, CHILD>;
-    fn iter<'a>(&'a self, pools: &'a [Pool], offset: UVec3) -> Self::Iterator<'a> {
+    fn iter<'a>(&'a self, pools: &'a [Pool], offset: IVec3) -> Self::Iterator<'a> {
         RootIterator {
             pools,
             map_iterator: self.map.iter(),
             child_iterator: None,
+            tile_iterator: None,
             location_offset: offset,
         }
     }
-    fn iter_in_pool<'a>(_pools: &'a [Pool], _ptr: u32, _offset: UVec3) -> Self::Iterator<'a> {
+    fn iter_in_pool<'a>(_pools: &'a [Pool], _ptr: u32, _offset: IVec3) -> Self::Iterator<'a> {
         unreachable!("Root Node is never kept in a pool!")
     }
 
     type LeafIterator<'a> = RootLeafIterator<'a, CHILD>;
 
-    fn iter_leaf<'a>(&'a self, pools: &'a [Pool], offset: UVec3) -> Self::LeafIterator<'a> {
+    fn iter_leaf<'a>(&'a self, pools: &'a [Pool], offset: IVec3) -> Self::LeafIterator<'a> {
         RootLeafIterator {
             pools,
             map_iterator: self.map.iter(),
@@ -156,7 +576,7 @@ impl<CHILD: Node> Node for RootNode<CHILD> {
     fn iter_leaf_in_pool<'a>(
         _pools: &'a [Pool],
         _ptr: u32,
-        _offset: UVec3,
+        _offset: IVec3,
     ) -> Self::LeafIterator<'a> {
         unreachable!("Root Node is never kept in a pool!")
     }
@@ -168,6 +588,7 @@ impl<CHILD: ~const NodeConst> const NodeConst for RootNode<CHILD> {
             layout: std::alloc::Layout::new::<Self>(),
             getter: Self::get_in_pools,
             setter: Self::set_in_pools,
+            is_empty: Self::is_empty_in_pools,
             extent_log2: Self::EXTENT_LOG2,
             fanout_log2: Self::EXTENT_LOG2,
             extent_mask: Self::EXTENT_MASK,
@@ -176,6 +597,17 @@ impl<CHILD: ~const NodeConst> const NodeConst for RootNode<CHILD> {
     }
 }
 
+/// Copies the child pointers without cloning the child nodes.
+/// The clone is only valid together with a clone of the pools.
+impl<CHILD: Node> Clone for RootNode<CHILD> {
+    fn clone(&self) -> Self {
+        Self {
+            map: self.map.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
 impl<CHILD: Node> std::fmt::Debug for RootNode<CHILD> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str("RootNode")
@@ -184,13 +616,14 @@ impl<CHILD: Node> std::fmt::Debug for RootNode<CHILD> {
 
 pub struct RootIterator<'a, CHILD: Node> {
     pools: &'a [Pool],
-    map_iterator: std::collections::hash_map::Iter<'a, RootKey, RootNodeEntry>,
+    map_iterator: std::collections::hash_map::Iter<'a, RootKey, RootNodeEntry<CHILD::Voxel>>,
     child_iterator: Option<CHILD::Iterator<'a>>,
-    location_offset: UVec3,
+    tile_iterator: Option<TileIterator>,
+    location_offset: IVec3,
 }
 
 impl<'a, CHILD: Node> Iterator for RootIterator<'a, CHILD> {
-    type Item = UVec3;
+    type Item = IVec3;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
@@ -198,18 +631,23 @@ impl<'a, CHILD: Node> Iterator for RootIterator<'a, CHILD> {
             if let Some(item) = self.child_iterator.as_mut().and_then(|a| a.next()) {
                 return Some(item);
             }
-            // self.child_iterator is None or ran out. Grab the next child.
-            if let Some((_, root_node)) = self.map_iterator.next() {
+            if let Some(item) = self.tile_iterator.as_mut().and_then(|a| a.next()) {
+                return Some(item);
+            }
+            // Both iterators are None or ran out. Grab the next child.
+            if let Some((key, root_node)) = self.map_iterator.next() {
+                let origin = self
+                    .location_offset
+                    .wrapping_add(RootNode::<CHILD>::origin_of(key));
                 match root_node {
                     RootNodeEntry::Occupied(ptr) => {
-                        self.child_iterator =
-                            Some(CHILD::iter_in_pool(self.pools, *ptr, self.location_offset));
-                        continue;
+                        self.child_iterator = Some(CHILD::iter_in_pool(self.pools, *ptr, origin));
                     }
-                    _ => {
-                        return None;
+                    RootNodeEntry::Free(_) => {
+                        self.tile_iterator = Some(TileIterator::new(origin, CHILD::EXTENT_LOG2));
                     }
                 }
+                continue;
             } else {
                 // Also ran out. We have nothing left.
                 return None;
@@ -219,13 +657,13 @@ impl<'a, CHILD: Node> Iterator for RootIterator<'a, CHILD> {
 }
 pub struct RootLeafIterator<'a, CHILD: Node> {
     pools: &'a [Pool],
-    map_iterator: std::collections::hash_map::Iter<'a, RootKey, RootNodeEntry>,
+    map_iterator: std::collections::hash_map::Iter<'a, RootKey, RootNodeEntry<CHILD::Voxel>>,
     child_iterator: Option<CHILD::LeafIterator<'a>>,
-    location_offset: UVec3,
+    location_offset: IVec3,
 }
 
 impl<'a, CHILD: Node> Iterator for RootLeafIterator<'a, CHILD> {
-    type Item = (UVec3, &'a UnsafeCell<CHILD::LeafType>);
+    type Item = (IVec3, &'a UnsafeCell<CHILD::LeafType>);
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
@@ -234,19 +672,18 @@ impl<'a, CHILD: Node> Iterator for RootLeafIterator<'a, CHILD> {
                 return Some(item);
             }
             // self.child_iterator is None or ran out. Grab the next child.
-            if let Some((_, root_node)) = self.map_iterator.next() {
+            if let Some((key, root_node)) = self.map_iterator.next() {
                 match root_node {
                     RootNodeEntry::Occupied(ptr) => {
                         self.child_iterator = Some(CHILD::iter_leaf_in_pool(
                             self.pools,
                             *ptr,
-                            self.location_offset,
+                            self.location_offset + RootNode::<CHILD>::origin_of(key),
                         ));
                         continue;
                     }
-                    _ => {
-                        return None;
-                    }
+                    // Tiles are not visited by the iterator.
+                    RootNodeEntry::Free(_) => continue,
                 }
             } else {
                 // Also ran out. We have nothing left.
@@ -1,4 +1,6 @@
-use std::{alloc::Layout, marker::PhantomData, mem::MaybeUninit};
+use std::{alloc::Layout, marker::PhantomData, mem::MaybeUninit, sync::Arc};
+
+use fxhash::FxHashMap;
 
 pub struct Pool {
     /// Size of one individual allocation
@@ -11,7 +13,12 @@ pub struct Pool {
     /// Number of items to request when we run out of space.
     /// When running out of space, request (1 << chunk_size_log2) * size bytes.
     chunk_size_log2: usize,
-    chunks: Vec<*mut u8>,
+    /// Chunks are shared between clones of the pool. Items in shared chunks are never
+    /// written in place, see [`Pool::make_item_unique`].
+    chunks: Arc<Chunks>,
+    /// Items freed while their chunk was shared, by chunk index. Clones may still use
+    /// them, so they are put on the freelist once the chunk is no longer shared.
+    retired: FxHashMap<usize, Vec<u32>>,
 
     count: u32,
 }
@@ -19,6 +26,32 @@ pub struct Pool {
 unsafe impl Send for Pool {}
 unsafe impl Sync for Pool {}
 
+#[derive(Clone)]
+struct Chunks {
+    ptrs: Vec<*mut u8>,
+    /// Owners of `ptrs`.
+    owners: Vec<Arc<Chunk>>,
+}
+
+unsafe impl Send for Chunks {}
+unsafe impl Sync for Chunks {}
+
+struct Chunk {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+unsafe impl Send for Chunk {}
+unsafe impl Sync for Chunk {}
+
+impl Drop for Chunk {
+    fn drop(&mut self) {
+        unsafe {
+            std::alloc::dealloc(self.ptr, self.layout);
+        }
+    }
+}
+
 /// A memory pool for objects of the same layout.
 /// ```
 /// use std::alloc::Layout;
@@ -35,9 +68,36 @@ unsafe impl Sync for Pool {}
 ///
 ///   pool.free(1);
 ///   pool.free(2);
+///   assert_eq!(pool.count(), 2);
+///   assert_eq!(pool.num_free(), 2);
+///   assert_eq!(pool.capacity(), 4);
 ///   assert_eq!(pool.alloc::<u64>(), 2);
 ///   assert_eq!(pool.alloc::<u64>(), 1);
 ///   assert_eq!(pool.alloc::<u64>(), 4);
+///
+///   // Appending moves the chunks of another pool over, starting at a chunk boundary.
+///   let mut other = Pool::new(Layout::for_value(&item), 1);
+///   let index = other.alloc::<u64>();
+///   *other.get_item_mut::<u64>(index) = 7;
+///   let offset = pool.append(other);
+///   assert_eq!(offset, 6);
+///   assert_eq!(*pool.get_item::<u64>(offset), 7);
+///   assert_eq!(pool.count(), 6);
+///   assert_eq!(pool.alloc::<u64>(), 5);
+///   assert_eq!(pool.alloc::<u64>(), 7);
+///
+///   // Clones share their chunks. Shared items are copied to a new index before
+///   // writing, and the old index is reused once no clone shares it anymore.
+///   *pool.get_item_mut::<u64>(0) = 5;
+///   let clone = pool.clone();
+///   assert_eq!(pool.num_shared_chunks(), 4);
+///   let copy = pool.make_item_unique(0);
+///   assert_eq!(copy, 8);
+///   *pool.get_item_mut::<u64>(copy) = 6;
+///   assert_eq!(*clone.get_item::<u64>(0), 5);
+///   drop(clone);
+///   assert_eq!(pool.num_shared_chunks(), 0);
+///   assert_eq!(pool.num_free(), 1);
 /// }
 /// ```
 impl Pool {
@@ -47,7 +107,11 @@ impl Pool {
             head: u32::MAX,
             top: 0,
             chunk_size_log2,
-            chunks: Vec::new(),
+            chunks: Arc::new(Chunks {
+                ptrs: Vec::new(),
+                owners: Vec::new(),
+            }),
+            retired: Default::default(),
             count: 0,
         }
     }
@@ -61,33 +125,60 @@ impl Pool {
         *item = T::default();
         ptr
     }
+    /// The returned item is never shared with clones of the pool.
     pub unsafe fn alloc_uninitialized(&mut self) -> u32 {
         self.count += 1;
-        if self.head == u32::MAX {
+        loop {
+            if self.head != u32::MAX {
+                // take from freelist
+                let head = self.head;
+                self.head = *(self.get(head) as *const u32);
+                if self.is_shared(head) {
+                    self.retire(head);
+                    continue;
+                }
+                return head;
+            }
             // allocate new
             let top = self.top;
             let chunk_index = top as usize >> self.chunk_size_log2;
-            if chunk_index >= self.chunks.len() {
-                // allocate new block
-                let (layout, _) = self.layout.repeat(1 << self.chunk_size_log2).unwrap();
-                let block = std::alloc::alloc_zeroed(layout);
-                self.chunks.push(block);
+            if chunk_index < self.chunks.ptrs.len() {
+                if !self.is_chunk_shared(chunk_index) {
+                    self.top += 1;
+                    return top;
+                }
+                // The rest of the chunk is unused, but it is shared with clones.
+                let end = ((chunk_index + 1) << self.chunk_size_log2) as u32;
+                self.retired
+                    .entry(chunk_index)
+                    .or_default()
+                    .extend(top..end);
+                self.top = end;
+                continue;
             }
-            self.top += 1;
-            top
-        } else {
-            // take from freelist
-            let item_location = self.get_mut(self.head);
-            let next_available_location = *(item_location as *const u32);
-            let head = self.head;
-            self.head = next_available_location;
-            return head;
+            if self.reclaim() {
+                continue;
+            }
+            // allocate new block
+            let layout = self.chunk_layout();
+            let block = std::alloc::alloc_zeroed(layout);
+            let chunks = Arc::make_mut(&mut self.chunks);
+            chunks.ptrs.push(block);
+            chunks.owners.push(Arc::new(Chunk { ptr: block, layout }));
         }
     }
     pub fn free(&mut self, index: u32) {
         self.count -= 1;
+        self.push_free(index);
+    }
+    /// Put the item at `index` on the freelist without changing the count.
+    fn push_free(&mut self, index: u32) {
+        if self.is_shared(index) {
+            self.retire(index);
+            return;
+        }
         unsafe {
-            let current_free_location = self.get_mut(index);
+            let current_free_location = self.get(index) as *mut u8;
 
             // The first 4 bytes of the entry is populated with self.head
             *(current_free_location as *mut u32) = self.head;
@@ -100,9 +191,118 @@ impl Pool {
             self.head = index;
         }
     }
+    /// Keep a free item shared with clones of the pool off the freelist, which is
+    /// stored in the items themselves.
+    fn retire(&mut self, index: u32) {
+        let chunk_index = index as usize >> self.chunk_size_log2;
+        self.retired.entry(chunk_index).or_default().push(index);
+    }
+    /// Put the retired items of chunks no longer shared on the freelist.
+    /// Returns whether there were any.
+    fn reclaim(&mut self) -> bool {
+        let head = self.head;
+        for (chunk_index, items) in std::mem::take(&mut self.retired) {
+            if self.is_chunk_shared(chunk_index) {
+                self.retired.insert(chunk_index, items);
+                continue;
+            }
+            for index in items {
+                self.push_free(index);
+            }
+        }
+        self.head != head
+    }
+
+    /// Move all items of `other` into the pool, keeping the chunks of `other` as they are.
+    /// Returns the offset to add to the indices of items from `other`.
+    /// The two pools must have the same item layout and chunk size.
+    pub fn append(&mut self, mut other: Pool) -> u32 {
+        debug_assert_eq!(self.layout, other.layout);
+        debug_assert_eq!(self.chunk_size_log2, other.chunk_size_log2);
+        // The caller relocates the items of `other`, so they are written anyway.
+        other.make_unique();
+        other.reclaim();
+        let offset = self.capacity();
+        // The chunks of `other` start at a chunk boundary. Unused items before it are freed.
+        for index in self.top..offset {
+            self.push_free(index);
+        }
+        if other.head != u32::MAX {
+            let mut index = other.head;
+            loop {
+                let next = unsafe { &mut *(other.get(index) as *mut u32) };
+                if *next == u32::MAX {
+                    *next = self.head;
+                    break;
+                }
+                index = *next;
+                *next += offset;
+            }
+            self.head = other.head + offset;
+        }
+        let chunks = Arc::make_mut(&mut self.chunks);
+        let other_chunks = Arc::make_mut(&mut other.chunks);
+        chunks.ptrs.append(&mut other_chunks.ptrs);
+        chunks.owners.append(&mut other_chunks.owners);
+        self.top = offset + other.top;
+        self.count += other.count;
+        offset
+    }
 
     pub fn num_chunks(&self) -> usize {
-        self.chunks.len()
+        self.chunks.ptrs.len()
+    }
+    /// Size of one item in bytes, including padding.
+    pub fn item_size(&self) -> usize {
+        self.layout.size()
+    }
+    /// Number of items that fit into the allocated chunks.
+    pub fn capacity(&self) -> u32 {
+        (self.chunks.ptrs.len() << self.chunk_size_log2) as u32
+    }
+    /// Number of freed items waiting to be reused.
+    pub fn num_free(&self) -> u32 {
+        self.top - self.count
+    }
+    /// Number of chunks shared with clones of the pool.
+    pub fn num_shared_chunks(&self) -> usize {
+        (0..self.chunks.ptrs.len())
+            .filter(|chunk_index| self.is_chunk_shared(*chunk_index))
+            .count()
+    }
+    /// Whether the item at `index` is shared with clones of the pool.
+    /// Shared items must not be written.
+    #[inline]
+    pub fn is_shared(&self, index: u32) -> bool {
+        self.is_chunk_shared(index as usize >> self.chunk_size_log2)
+    }
+    #[inline]
+    fn is_chunk_shared(&self, chunk_index: usize) -> bool {
+        Arc::strong_count(&self.chunks) > 1
+            || Arc::strong_count(&self.chunks.owners[chunk_index]) > 1
+    }
+    /// Returns the index of an item with the same content as the item at `index`
+    /// that may be written. An item shared with clones of the pool is copied to a new
+    /// index, and `index` is freed. References to the item must be updated.
+    pub fn make_item_unique(&mut self, index: u32) -> u32 {
+        if !self.is_shared(index) {
+            return index;
+        }
+        unsafe {
+            let copy = self.alloc_uninitialized();
+            std::ptr::copy_nonoverlapping(
+                self.get(index),
+                self.get(copy) as *mut u8,
+                self.layout.size(),
+            );
+            self.free(index);
+            copy
+        }
+    }
+    /// Number of bytes allocated for the chunks.
+    /// This includes chunks shared with clones of the pool.
+    pub fn allocated_bytes(&self) -> usize {
+        self.chunks.ptrs.len() * (self.layout.size() << self.chunk_size_log2)
     }
 
     #[inline]
@@ -111,15 +311,49 @@ impl Pool {
         let item_index = (ptr as usize) & ((1 << self.chunk_size_log2) - 1);
         return self
             .chunks
+            .ptrs
             .get_unchecked(chunk_index)
             .add(item_index * self.layout.size());
     }
+    /// Returns a pointer for writing to the item at `ptr`.
+    /// The chunk containing the item is copied first if it is shared with a clone of the
+    /// pool. Use [`Pool::make_item_unique`] to copy only the item instead.
     #[inline]
     pub unsafe fn get_mut(&mut self, ptr: u32) -> *mut u8 {
+        self.make_chunk_unique((ptr as usize) >> self.chunk_size_log2);
         let ptr = self.get(ptr);
         ptr as *mut u8
     }
 
+    /// Copy all chunks shared with clones of the pool, so that items may be written
+    /// through pointers obtained with [`Pool::get`].
+    pub fn make_unique(&mut self) {
+        for chunk_index in 0..self.chunks.ptrs.len() {
+            self.make_chunk_unique(chunk_index);
+        }
+    }
+
+    #[inline]
+    fn make_chunk_unique(&mut self, chunk_index: usize) {
+        if !self.is_chunk_shared(chunk_index) {
+            return;
+        }
+        let chunks = Arc::make_mut(&mut self.chunks);
+        let owner = &mut chunks.owners[chunk_index];
+        unsafe {
+            let layout = owner.layout;
+            let copy = std::alloc::alloc(layout);
+            std::ptr::copy_nonoverlapping(owner.ptr, copy, layout.size());
+            *owner = Arc::new(Chunk { ptr: copy, layout });
+            chunks.ptrs[chunk_index] = copy;
+        }
+    }
+
+    fn chunk_layout(&self) -> Layout {
+        let (layout, _) = self.layout.repeat(1 << self.chunk_size_log2).unwrap();
+        layout
+    }
+
     #[inline]
     pub unsafe fn get_item<T>(&self, ptr: u32) -> &T {
         debug_assert_eq!(Layout::new::<T>().pad_to_align(), self.layout);
@@ -163,14 +397,20 @@ impl<'a, T: 'a> Iterator for PoolIterator<'a, T> {
     }
 }
 
-impl Drop for Pool {
-    fn drop(&mut self) {
-        unsafe {
-            let (layout, _) = self.layout.repeat(1 << self.chunk_size_log2).unwrap();
-            for chunk in self.chunks.iter() {
-                let chunk = *chunk;
-                std::alloc::dealloc(chunk, layout);
-            }
+/// Clones share all chunks with the original pool, so cloning takes constant time.
+/// Afterwards, items are copied individually with [`Pool::make_item_unique`] before
+/// either pool writes them, and new items are allocated from chunks of their own.
+impl Clone for Pool {
+    fn clone(&self) -> Self {
+        Self {
+            layout: self.layout,
+            head: self.head,
+            top: self.top,
+            chunk_size_log2: self.chunk_size_log2,
+            chunks: self.chunks.clone(),
+            // The retired items of the original pool stay unused in the clone.
+            retired: Default::default(),
+            count: self.count,
         }
     }
 }
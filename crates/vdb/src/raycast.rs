@@ -0,0 +1,323 @@
+use glam::{I64Vec3, IVec3, UVec3, Vec3};
+
+use crate::{Aabb, Node, Tree, VisitedNode};
+
+/// The first occupied voxel hit by a ray. See [`Tree::raycast`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit<V> {
+    /// Coordinates of the voxel.
+    pub coords: IVec3,
+    /// Normal of the face through which the ray entered the voxel.
+    /// This is zero if the ray started inside the voxel.
+    pub normal: IVec3,
+    /// Distance from the origin of the ray to the hit, in units of the ray direction.
+    pub t: f32,
+    /// Value of the voxel.
+    pub value: V,
+}
+
+/// Ray queries.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Returns the first occupied voxel along the ray `origin + dir * t` for `t` in `0..=max_t`.
+    ///
+    /// The ray is traced with a hierarchical DDA. Empty child nodes and tiles are crossed
+    /// in a single step, and only leaf nodes are traversed voxel by voxel. The tree is only
+    /// descended again once the ray leaves a leaf node.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Tree};
+    /// use glam::{IVec3, Vec3};
+    /// let mut tree = Tree::<hierarchy!(#, 3, 2; u8)>::new();
+    /// tree.set_value(IVec3::new(10, 0, 0), Some(5));
+    /// let hit = tree.raycast(Vec3::new(-100.0, 0.5, 0.5), Vec3::X, 1000.0).unwrap();
+    /// assert_eq!(hit.coords, IVec3::new(10, 0, 0));
+    /// assert_eq!(hit.normal, IVec3::new(-1, 0, 0));
+    /// assert_eq!(hit.t, 110.0);
+    /// assert_eq!(hit.value, 5);
+    /// assert!(tree.raycast(Vec3::new(-100.0, 0.5, 0.5), Vec3::X, 100.0).is_none());
+    /// ```
+    pub fn raycast(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Option<RayHit<ROOT::Voxel>> {
+        let extent = Self::extent();
+        let step = IVec3::new(sign(dir.x), sign(dir.y), sign(dir.z));
+        let inv_dir = dir.recip();
+
+        // Clip the ray to the extent of the tree.
+        let mut t = 0.0_f32;
+        let mut entry_axis = None;
+        for axis in 0..3 {
+            let (min, max) = (extent.min[axis] as f32, extent.max[axis] as f32);
+            let t_entry = match step[axis] {
+                0 if origin[axis] < min || origin[axis] >= max => return None,
+                0 => continue,
+                1 => (min - origin[axis]) * inv_dir[axis],
+                _ => (max - origin[axis]) * inv_dir[axis],
+            };
+            if t_entry > t {
+                t = t_entry;
+                entry_axis = Some(axis);
+            }
+        }
+        if t > max_t {
+            return None;
+        }
+        let mut coords = (origin + dir * t).floor().as_ivec3();
+        let mut normal = IVec3::ZERO;
+        if let Some(axis) = entry_axis {
+            coords[axis] = if step[axis] > 0 {
+                extent.min[axis]
+            } else {
+                extent.max[axis] - 1
+            };
+            normal[axis] = -step[axis];
+        }
+        if !extent.contains(coords) {
+            return None;
+        }
+
+        // Returns the first voxel after the box of size `1 << extent_log2` at `node_min`,
+        // the normal of the face crossed and the distance to it.
+        let exit = |t: f32, node_min: IVec3, extent_log2: UVec3| {
+            let node_max = node_min.as_i64vec3() + (I64Vec3::ONE << extent_log2);
+            let mut t_exit = f32::INFINITY;
+            let mut exit_axis = 0;
+            for axis in 0..3 {
+                let t_axis = match step[axis] {
+                    0 => continue,
+                    1 => (node_max[axis] as f32 - origin[axis]) * inv_dir[axis],
+                    _ => (node_min[axis] as f32 - origin[axis]) * inv_dir[axis],
+                };
+                if t_axis < t_exit {
+                    t_exit = t_axis;
+                    exit_axis = axis;
+                }
+            }
+            if t_exit > max_t {
+                return None;
+            }
+            let t = t.max(t_exit);
+
+            let next = if step[exit_axis] > 0 {
+                node_max[exit_axis]
+            } else {
+                node_min[exit_axis] as i64 - 1
+            };
+            if next < extent.min[exit_axis] as i64 || next >= extent.max[exit_axis] as i64 {
+                return None;
+            }
+            // Stay within the node on the other axes despite rounding errors.
+            let position = (origin + dir * t).floor().as_ivec3();
+            let mut coords = position.clamp(node_min, (node_max - I64Vec3::ONE).as_ivec3());
+            coords[exit_axis] = next as i32;
+            let mut normal = IVec3::ZERO;
+            normal[exit_axis] = -step[exit_axis];
+            Some((coords, normal, t))
+        };
+
+        let leaf_extent = <ROOT::LeafType as Node>::EXTENT;
+        let leaf_mask = <ROOT::LeafType as Node>::EXTENT_MASK.as_ivec3();
+        loop {
+            let (value, extent_log2) = self.root.probe(&self.pool, coords.as_uvec3());
+            if let Some(value) = value {
+                return Some(RayHit {
+                    coords,
+                    normal,
+                    t,
+                    value,
+                });
+            }
+            if extent_log2 != UVec3::ZERO {
+                // Skip over the node or tile containing `coords`.
+                let node_min = (coords >> extent_log2.as_ivec3()) << extent_log2.as_ivec3();
+                (coords, normal, t) = exit(t, node_min, extent_log2)?;
+                continue;
+            }
+
+            // Step through the leaf node voxel by voxel, and only go back to the root
+            // once the ray leaves it.
+            let leaf_aabb = Aabb::from_extent(coords & !leaf_mask, leaf_extent);
+            let leaf = self.leaf_at(leaf_aabb);
+            loop {
+                (coords, normal, t) = exit(t, coords, UVec3::ZERO)?;
+                if !leaf_aabb.contains(coords) {
+                    break;
+                }
+                let local = (coords - leaf_aabb.min).as_uvec3();
+                if let Some(value) = leaf.get(&[], local, &mut []) {
+                    return Some(RayHit {
+                        coords,
+                        normal,
+                        t,
+                        value,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Returns the leaf node at `aabb`, which must exist.
+    fn leaf_at(&self, aabb: Aabb) -> &ROOT::LeafType {
+        let mut leaf = None;
+        self.root
+            .visit(&self.pool, IVec3::ZERO, &aabb, &mut |node| {
+                if let VisitedNode::Leaf(origin, node) = node {
+                    if origin == aabb.min {
+                        leaf = Some(node);
+                    }
+                }
+            });
+        leaf.unwrap()
+    }
+}
+
+fn sign(x: f32) -> i32 {
+    if x > 0.0 {
+        1
+    } else if x < 0.0 {
+        -1
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, Vec3};
+    use rand::prelude::*;
+
+    use super::sign;
+    use crate::{hierarchy, Aabb, Tree};
+
+    type MyTree = Tree<hierarchy!(#, 3, 2; u8)>;
+
+    /// Reference implementation stepping through every voxel along the ray.
+    fn raycast_voxels(tree: &MyTree, origin: Vec3, dir: Vec3, max_t: f32) -> Option<IVec3> {
+        let step = IVec3::new(sign(dir.x), sign(dir.y), sign(dir.z));
+        let mut coords = origin.floor().as_ivec3();
+        let mut t = 0.0;
+        while t <= max_t {
+            if tree.get_value(coords).is_some() {
+                return Some(coords);
+            }
+            let mut t_exit = f32::INFINITY;
+            let mut exit_axis = 0;
+            for axis in 0..3 {
+                let boundary = match step[axis] {
+                    0 => continue,
+                    1 => coords[axis] + 1,
+                    _ => coords[axis],
+                };
+                let t_axis = (boundary as f32 - origin[axis]) / dir[axis];
+                if t_axis < t_exit {
+                    t_exit = t_axis;
+                    exit_axis = axis;
+                }
+            }
+            t = t_exit;
+            coords[exit_axis] += step[exit_axis];
+        }
+        None
+    }
+
+    #[test]
+    fn test_raycast() {
+        let mut tree = MyTree::new();
+        tree.fill(
+            Aabb::new(IVec3::new(-64, -64, -64), IVec3::new(-32, 0, 0)),
+            1,
+        );
+        tree.set_value(IVec3::new(3, 4, 5), Some(2));
+
+        // Starting inside an occupied voxel.
+        let hit = tree
+            .raycast(Vec3::new(3.5, 4.5, 5.5), Vec3::Y, 0.0)
+            .unwrap();
+        assert_eq!(hit.coords, IVec3::new(3, 4, 5));
+        assert_eq!(hit.normal, IVec3::ZERO);
+        assert_eq!(hit.t, 0.0);
+
+        // Hitting a tile from the positive side.
+        let hit = tree
+            .raycast(Vec3::new(100.0, -10.5, -0.5), Vec3::NEG_X, 1000.0)
+            .unwrap();
+        assert_eq!(hit.coords, IVec3::new(-33, -11, -1));
+        assert_eq!(hit.normal, IVec3::X);
+        assert_eq!(hit.t, 132.0);
+        assert_eq!(hit.value, 1);
+
+        // Hitting a voxel from below.
+        let hit = tree
+            .raycast(Vec3::new(3.25, -1000.0, 5.75), Vec3::Y * 2.0, 1000.0)
+            .unwrap();
+        assert_eq!(hit.coords, IVec3::new(3, 4, 5));
+        assert_eq!(hit.normal, IVec3::NEG_Y);
+        assert_eq!(hit.t, 502.0);
+
+        assert!(tree
+            .raycast(Vec3::new(3.25, -1000.0, 5.75), Vec3::NEG_Y, 1000.0)
+            .is_none());
+        assert!(tree
+            .raycast(Vec3::new(0.5, 0.5, 0.5), Vec3::ZERO, 10.0)
+            .is_none());
+    }
+
+    #[test]
+    fn test_raycast_bounded() {
+        let mut tree = Tree::<hierarchy!(3, 2; u8)>::new();
+        tree.set_value(IVec3::new(31, 0, 0), Some(1));
+        let hit = tree
+            .raycast(Vec3::new(40.0, 0.5, 0.5), Vec3::NEG_X, 100.0)
+            .unwrap();
+        assert_eq!(hit.coords, IVec3::new(31, 0, 0));
+        assert_eq!(hit.normal, IVec3::X);
+        assert_eq!(hit.t, 8.0);
+        assert!(tree
+            .raycast(Vec3::new(30.5, 0.5, 0.5), Vec3::X, 100.0)
+            .is_some());
+        assert!(tree
+            .raycast(Vec3::new(30.5, 1.5, 0.5), Vec3::X, 100.0)
+            .is_none());
+    }
+
+    #[test]
+    fn test_raycast_random() {
+        let mut rng = rand::thread_rng();
+        let mut tree = MyTree::new();
+        for _ in 0..200 {
+            let coords = IVec3::new(
+                rng.gen_range(-64..64),
+                rng.gen_range(-64..64),
+                rng.gen_range(-64..64),
+            );
+            tree.set_value(coords, Some(1));
+        }
+        for _ in 0..500 {
+            let origin = Vec3::new(
+                rng.gen_range(-80.0..80.0),
+                rng.gen_range(-80.0..80.0),
+                rng.gen_range(-80.0..80.0),
+            );
+            let dir = Vec3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            )
+            .normalize();
+            let hit = tree.raycast(origin, dir, 300.0);
+            assert_eq!(
+                hit.map(|hit| hit.coords),
+                raycast_voxels(&tree, origin, dir, 300.0)
+            );
+            if let Some(hit) = hit.filter(|hit| hit.t > 0.0) {
+                let position = origin + dir * hit.t;
+                let min = hit.coords.as_vec3();
+                assert!(position.cmpge(min - 1e-3).all());
+                assert!(position.cmple(min + 1.0 + 1e-3).all());
+                assert_eq!(hit.normal.abs().dot(IVec3::ONE), 1);
+                assert!(dir.dot(hit.normal.as_vec3()) < 0.0);
+            }
+        }
+    }
+}
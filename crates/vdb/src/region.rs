@@ -0,0 +1,236 @@
+use std::sync::Arc;
+
+use glam::{IVec3, UVec3};
+
+use crate::{Aabb, Node, NodeConst, Tree, VisitedNode};
+
+/// Operations on axis-aligned regions of the tree.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Clear all voxels within `aabb`. Nodes that become empty are freed.
+    pub fn clear(&mut self, aabb: Aabb) {
+        let aabb = aabb.intersect(&Self::extent());
+        if aabb.is_empty() {
+            return;
+        }
+        self.track_region(aabb, |tree| {
+            Arc::make_mut(&mut tree.root).fill(&mut tree.pool, aabb, None);
+        });
+    }
+
+    /// Replace the voxels in the box of size `src_aabb.extent()` located at `dst_min`
+    /// with the voxels of `src` within `src_aabb`.
+    ///
+    /// Leaf nodes are copied wholesale when `src_aabb.min` and `dst_min` are aligned to
+    /// the leaf extent, and tiles are pasted as tiles whenever possible. Otherwise, each
+    /// destination leaf is looked up once per source leaf overlapping it.
+    /// Voxels that would land outside of [`Tree::extent`] are skipped.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Aabb, Tree};
+    /// use glam::IVec3;
+    /// let mut src = Tree::<hierarchy!(3, 2; u8)>::new();
+    /// src.set_value(IVec3::new(1, 2, 3), Some(7));
+    /// src.set_value(IVec3::new(9, 2, 3), Some(8));
+    ///
+    /// let mut dst = Tree::<hierarchy!(3, 2; u8)>::new();
+    /// dst.set_value(IVec3::new(20, 20, 20), Some(1));
+    /// dst.paste(&src, Aabb::new(IVec3::ZERO, IVec3::splat(8)), IVec3::new(17, 18, 19));
+    /// assert_eq!(dst.get_value(IVec3::new(18, 20, 22)), Some(7));
+    /// assert_eq!(dst.get_value(IVec3::new(20, 20, 20)), None);
+    /// assert_eq!(dst.get_value(IVec3::new(26, 20, 22)), None);
+    /// ```
+    pub fn paste(&mut self, src: &Tree<ROOT>, src_aabb: Aabb, dst_min: IVec3)
+    where
+        ROOT: ~const NodeConst,
+    {
+        let src_aabb = src_aabb.intersect(&Self::extent());
+        if src_aabb.is_empty() {
+            return;
+        }
+        let offset = dst_min.wrapping_sub(src_aabb.min);
+        let translate = |coords: IVec3| coords.wrapping_add(offset);
+        // Clip the destination to the tree, and shrink the source region to match.
+        let dst_aabb = Aabb::from_extent(dst_min, src_aabb.extent()).intersect(&Self::extent());
+        if dst_aabb.is_empty() {
+            return;
+        }
+        let src_aabb = Aabb::new(
+            dst_aabb.min.wrapping_sub(offset),
+            dst_aabb.max.wrapping_sub(offset),
+        );
+        self.clear(dst_aabb);
+
+        let leaf_extent = <ROOT::LeafType as Node>::EXTENT;
+        let leaf_mask = <ROOT::LeafType as Node>::EXTENT_MASK.as_ivec3();
+        let aligned = (src_aabb.min & leaf_mask) == (dst_min & leaf_mask);
+        // Voxels of an unaligned source leaf land in up to 8 destination leaves.
+        // They are grouped by destination leaf, indexed by the axes they overflow on.
+        let mut groups: [Vec<(UVec3, ROOT::Voxel)>; 8] = Default::default();
+        self.track_region(dst_aabb, |tree| {
+            let pool = &mut tree.pool;
+            let root = Arc::make_mut(&mut tree.root);
+            src.root
+                .visit(&src.pool, IVec3::ZERO, &src_aabb, &mut |node| match node {
+                    VisitedNode::Tile(aabb, value) => {
+                        let aabb = aabb.intersect(&src_aabb);
+                        root.fill(
+                            pool,
+                            Aabb::new(translate(aabb.min), translate(aabb.max)),
+                            Some(value),
+                        );
+                    }
+                    VisitedNode::Leaf(origin, leaf) => {
+                        let leaf_aabb = Aabb::from_extent(origin, leaf_extent);
+                        if aligned && src_aabb.contains_aabb(&leaf_aabb) {
+                            let dst_leaf = unsafe {
+                                &mut *root.touch_leaf(pool, translate(origin).as_uvec3())
+                            };
+                            dst_leaf.clone_from(leaf);
+                            return;
+                        }
+                        let dst_base = translate(origin) & !leaf_mask;
+                        for group in groups.iter_mut() {
+                            group.clear();
+                        }
+                        for local in leaf.iter(&[], IVec3::ZERO) {
+                            let coords = origin + local;
+                            if !src_aabb.contains(coords) {
+                                continue;
+                            }
+                            let value = leaf.get(&[], local.as_uvec3(), &mut []).unwrap();
+                            let dst_coords = translate(coords);
+                            let overflow = dst_coords
+                                .wrapping_sub(dst_base)
+                                .cmpge(leaf_extent.as_ivec3())
+                                .bitmask();
+                            groups[overflow as usize]
+                                .push(((dst_coords & leaf_mask).as_uvec3(), value));
+                        }
+                        for (overflow, group) in groups.iter().enumerate() {
+                            if group.is_empty() {
+                                continue;
+                            }
+                            let step = UVec3::new(
+                                overflow as u32 & 1,
+                                (overflow as u32 >> 1) & 1,
+                                (overflow as u32 >> 2) & 1,
+                            ) * leaf_extent;
+                            let dst_origin = dst_base.wrapping_add(step.as_ivec3());
+                            let dst_leaf =
+                                unsafe { &mut *root.touch_leaf(pool, dst_origin.as_uvec3()) };
+                            for (local, value) in group.iter() {
+                                dst_leaf.set(&mut [], *local, Some(*value), &mut []);
+                            }
+                        }
+                    }
+                });
+        });
+    }
+
+    /// Returns a new tree containing only the voxels within `aabb`.
+    /// Voxels keep their coordinates in the new tree.
+    pub fn extract(&self, aabb: Aabb) -> Self
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut tree = Self::new();
+        tree.paste(self, aabb, aabb.min);
+        tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use crate::{hierarchy, Aabb, Tree};
+
+    #[test]
+    fn test_clear() {
+        let mut tree = Tree::<hierarchy!(#, 2, 2)>::new();
+        tree.fill(Aabb::new(IVec3::ZERO, IVec3::splat(32)), true);
+        tree.clear(Aabb::new(IVec3::new(1, 0, 0), IVec3::splat(32)));
+        assert_eq!(tree.iter().count(), 32 * 32);
+        tree.clear(Aabb::new(IVec3::ZERO, IVec3::splat(32)));
+        assert_eq!(tree.pool[0].count(), 0);
+        assert_eq!(tree.pool[1].count(), 0);
+        assert!(tree.get_value(IVec3::ZERO).is_none());
+    }
+
+    #[test]
+    fn test_paste() {
+        let mut src = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+        for i in 0..40_i32 {
+            src.set_value(IVec3::new(i, i / 2, i / 3), Some(i as u8));
+        }
+        src.fill(Aabb::new(IVec3::new(32, 0, 0), IVec3::new(64, 16, 16)), 100);
+        let src_aabb = Aabb::new(IVec3::new(2, 0, 0), IVec3::new(50, 20, 20));
+        for dst_min in [IVec3::new(6, 4, 8), IVec3::new(3, 5, 7)] {
+            let mut dst = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+            dst.paste(&src, src_aabb, dst_min);
+            for x in src_aabb.min.x..src_aabb.max.x {
+                for y in src_aabb.min.y..src_aabb.max.y {
+                    for z in src_aabb.min.z..src_aabb.max.z {
+                        let coords = IVec3::new(x, y, z);
+                        assert_eq!(
+                            dst.get_value(coords - src_aabb.min + dst_min),
+                            src.get_value(coords),
+                        );
+                    }
+                }
+            }
+            assert_eq!(dst.get_value(dst_min - IVec3::ONE), None);
+        }
+        let extracted = src.extract(src_aabb);
+        assert_eq!(extracted.get_value(IVec3::new(3, 1, 1)), Some(3));
+        assert_eq!(extracted.get_value(IVec3::new(1, 0, 0)), None);
+    }
+
+    #[test]
+    fn test_paste_clipped() {
+        let mut src = Tree::<hierarchy!(3, 2; u8)>::new();
+        src.fill(Aabb::new(IVec3::ZERO, IVec3::splat(12)), 5);
+        let mut dst = Tree::<hierarchy!(3, 2; u8)>::new();
+        dst.paste(
+            &src,
+            Aabb::new(IVec3::ZERO, IVec3::splat(12)),
+            IVec3::new(27, -3, 1),
+        );
+        assert_eq!(dst.iter().count(), 5 * 9 * 12);
+        assert_eq!(dst.get_value(IVec3::new(27, 0, 1)), Some(5));
+        assert_eq!(dst.get_value(IVec3::new(31, 8, 12)), Some(5));
+        assert_eq!(dst.get_value(IVec3::new(31, 9, 12)), None);
+
+        // Nothing of the source lands within the tree.
+        dst.paste(
+            &src,
+            Aabb::new(IVec3::ZERO, IVec3::splat(12)),
+            IVec3::splat(40),
+        );
+        assert_eq!(dst.iter().count(), 5 * 9 * 12);
+    }
+
+    #[test]
+    fn test_paste_negative() {
+        let mut src = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+        src.fill(Aabb::new(IVec3::splat(-8), IVec3::splat(8)), 1);
+        src.set_value(IVec3::new(-3, -2, -1), Some(2));
+        let mut dst = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+        dst.paste(
+            &src,
+            Aabb::new(IVec3::splat(-4), IVec3::splat(4)),
+            IVec3::splat(-30),
+        );
+        assert_eq!(dst.iter().count(), 8 * 8 * 8);
+        assert_eq!(dst.get_value(IVec3::new(-29, -28, -27)), Some(2));
+        assert_eq!(dst.get_value(IVec3::splat(-30)), Some(1));
+        assert_eq!(dst.get_value(IVec3::splat(-23)), Some(1));
+        assert_eq!(dst.get_value(IVec3::splat(-22)), None);
+
+        dst.clear(Aabb::new(IVec3::splat(-100), IVec3::splat(-26)));
+        assert_eq!(dst.iter().count(), 8 * 8 * 8 - 4 * 4 * 4);
+    }
+}
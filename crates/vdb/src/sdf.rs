@@ -0,0 +1,345 @@
+use fxhash::FxHashMap;
+use glam::IVec3;
+
+use crate::{dense::dense_strides, Aabb, Node, NodeConst, Tree, VisitedNode};
+
+const NEIGHBORS: [IVec3; 6] = [
+    IVec3::NEG_X,
+    IVec3::X,
+    IVec3::NEG_Y,
+    IVec3::Y,
+    IVec3::NEG_Z,
+    IVec3::Z,
+];
+
+/// Voxels on which the distances are solved.
+struct Domain {
+    voxels: Vec<IVec3>,
+    /// Whether each voxel is occupied in the source tree.
+    occupied: Vec<bool>,
+    /// Unsigned distance of each voxel.
+    distance: Vec<f32>,
+    /// Index of the neighbor of each voxel in the order of [`NEIGHBORS`], or u32::MAX if
+    /// the neighbor is not part of the domain.
+    neighbors: Vec<[u32; 6]>,
+}
+
+impl Domain {
+    fn new(voxels: Vec<IVec3>, mut is_occupied: impl FnMut(IVec3) -> bool) -> Self {
+        let indices: FxHashMap<IVec3, u32> = voxels
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (*v, i as u32))
+            .collect();
+        let occupied: Vec<bool> = voxels.iter().map(|v| is_occupied(*v)).collect();
+        let mut distance = vec![f32::INFINITY; voxels.len()];
+        let mut neighbors = vec![[u32::MAX; 6]; voxels.len()];
+        for (i, voxel) in voxels.iter().enumerate() {
+            for (n, offset) in NEIGHBORS.iter().enumerate() {
+                let neighbor = voxel.wrapping_add(*offset);
+                let neighbor_occupied = match indices.get(&neighbor) {
+                    Some(index) => {
+                        neighbors[i][n] = *index;
+                        occupied[*index as usize]
+                    }
+                    None => is_occupied(neighbor),
+                };
+                // The surface lies halfway between the voxel and its neighbor.
+                if neighbor_occupied != occupied[i] {
+                    distance[i] = 0.5;
+                }
+            }
+        }
+        Self {
+            voxels,
+            occupied,
+            distance,
+            neighbors,
+        }
+    }
+
+    /// Solve the distances of the voxels away from the surface with the fast sweeping
+    /// method, until they no longer change.
+    fn solve(&mut self) {
+        let orders: Vec<Vec<u32>> = Aabb::new(IVec3::ZERO, IVec3::splat(2))
+            .iter()
+            .map(|signs| {
+                let signs = signs * 2 - IVec3::ONE;
+                let mut order: Vec<u32> = (0..self.voxels.len() as u32).collect();
+                order.sort_unstable_by_key(|i| (self.voxels[*i as usize] * signs).to_array());
+                order
+            })
+            .collect();
+        loop {
+            let mut changed = false;
+            for order in orders.iter() {
+                for i in order.iter() {
+                    let i = *i as usize;
+                    let [a, b, c] = [0, 1, 2].map(|axis| {
+                        let [lower, upper] =
+                            [2 * axis, 2 * axis + 1].map(|n| match self.neighbors[i][n] {
+                                u32::MAX => f32::INFINITY,
+                                index => self.distance[index as usize],
+                            });
+                        lower.min(upper)
+                    });
+                    let distance = solve_eikonal(a, b, c);
+                    if distance < self.distance[i] {
+                        self.distance[i] = distance;
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    fn signed_distance(&self, index: usize) -> f32 {
+        if self.occupied[index] {
+            -self.distance[index]
+        } else {
+            self.distance[index]
+        }
+    }
+}
+
+/// Returns the distance of a voxel given the smallest distance of its neighbors along
+/// each axis, for voxels one unit apart.
+fn solve_eikonal(a: f32, b: f32, c: f32) -> f32 {
+    let mut d = [a, b, c];
+    d.sort_unstable_by(|x, y| x.total_cmp(y));
+    let [a, b, c] = d;
+    let x = a + 1.0;
+    if x <= b {
+        return x;
+    }
+    let x = (a + b + (2.0 - (a - b) * (a - b)).sqrt()) / 2.0;
+    if x <= c {
+        return x;
+    }
+    let sum = a + b + c;
+    (sum + (sum * sum - 3.0 * (a * a + b * b + c * c - 1.0)).sqrt()) / 3.0
+}
+
+/// Signed distance fields.
+///
+/// Distances are measured between voxel centers in units of voxels, and are negative
+/// within occupied voxels. The surface lies halfway between occupied and empty voxels,
+/// so voxels next to it are half a voxel away. The remaining distances are solved with
+/// the fast sweeping method for the eikonal equation, which visits the voxels in each of
+/// the 8 diagonal orders in turn.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Returns the signed distance field of the occupied voxels within a narrow band of
+    /// `band` voxels around the surface. Distances are clamped to `band`. Occupied voxels
+    /// further away from the surface are set to `-band`, while empty voxels further away
+    /// from the surface are left empty.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Aabb, Tree};
+    /// use glam::IVec3;
+    /// let mut tree = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+    /// tree.fill(Aabb::new(IVec3::new(-16, -16, -16), IVec3::new(16, 16, 0)), 1);
+    /// let sdf: Tree<hierarchy!(#, 2, 2; f32)> = tree.to_sdf(4.0);
+    /// assert_eq!(sdf.get_value(IVec3::new(0, 0, -1)), Some(-0.5));
+    /// assert_eq!(sdf.get_value(IVec3::new(0, 0, -3)), Some(-2.5));
+    /// assert_eq!(sdf.get_value(IVec3::new(0, 0, 2)), Some(2.5));
+    /// assert_eq!(sdf.get_value(IVec3::new(0, 0, -10)), Some(-4.0));
+    /// assert_eq!(sdf.get_value(IVec3::new(0, 0, 20)), None);
+    /// ```
+    pub fn to_sdf<DST: Node<Voxel = f32>>(&self, band: f32) -> Tree<DST>
+    where
+        ROOT: ~const NodeConst,
+        DST: ~const NodeConst,
+        [(); DST::LEVEL as usize + 1]: Sized,
+    {
+        let mut sdf = Tree::<DST>::new();
+        self.root.visit(
+            &self.pool,
+            IVec3::ZERO,
+            &Self::extent(),
+            &mut |node| match node {
+                VisitedNode::Tile(tile, _) => sdf.fill(tile, -band),
+                VisitedNode::Leaf(origin, leaf) => {
+                    for coords in leaf.iter(&[], origin) {
+                        sdf.set_value(coords, Some(-band));
+                    }
+                }
+            },
+        );
+
+        // Voxels within the band of the surface, by Chebyshev distance.
+        let radius = band.ceil().max(0.0) as i32;
+        let mut accessor = self.accessor();
+        let mut band_voxels = FxHashMap::default();
+        let mut queue = Vec::new();
+        for coords in self.surface_candidates() {
+            let occupied = accessor.get(coords).is_some();
+            for offset in NEIGHBORS {
+                let neighbor = coords.wrapping_add(offset);
+                if accessor.get(neighbor).is_some() != occupied {
+                    for voxel in [coords, neighbor] {
+                        if band_voxels.insert(voxel, 0).is_none() {
+                            queue.push(voxel);
+                        }
+                    }
+                }
+            }
+        }
+        let offsets = Aabb::new(IVec3::NEG_ONE, IVec3::splat(2));
+        while let Some(voxel) = queue.pop() {
+            let depth = band_voxels[&voxel];
+            if depth >= radius {
+                continue;
+            }
+            for offset in offsets.iter() {
+                let neighbor = voxel.wrapping_add(offset);
+                match band_voxels.get(&neighbor) {
+                    Some(d) if *d <= depth + 1 => continue,
+                    _ => {
+                        band_voxels.insert(neighbor, depth + 1);
+                        queue.push(neighbor);
+                    }
+                }
+            }
+        }
+
+        let mut domain = Domain::new(band_voxels.into_keys().collect(), |coords| {
+            accessor.get(coords).is_some()
+        });
+        domain.solve();
+        for (i, voxel) in domain.voxels.iter().enumerate() {
+            let distance = domain.signed_distance(i).clamp(-band, band);
+            sdf.set_value(*voxel, Some(distance));
+        }
+        sdf
+    }
+
+    /// Returns the signed distance field of the occupied voxels within `aabb` as a dense
+    /// array, in the same layout as [`Tree::to_dense`]. Only the surface within `aabb` is
+    /// taken into account. Distances are infinite if there is no such surface.
+    pub fn to_sdf_dense(&self, aabb: Aabb) -> Vec<f32>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut accessor = self.accessor();
+        let mut domain = Domain::new(aabb.iter().collect(), |coords| {
+            accessor.get(coords).is_some()
+        });
+        domain.solve();
+        let strides = dense_strides(aabb.extent());
+        let mut data = vec![0.0; domain.voxels.len()];
+        for (i, voxel) in domain.voxels.iter().enumerate() {
+            let local = (*voxel - aabb.min).as_uvec3();
+            let index = local.x as usize * strides[0]
+                + local.y as usize * strides[1]
+                + local.z as usize * strides[2];
+            data[index] = domain.signed_distance(i);
+        }
+        data
+    }
+
+    /// Returns the voxels that may be located on either side of the surface: all
+    /// occupied voxels of the leaf nodes, and the voxels on the boundary of tiles.
+    fn surface_candidates(&self) -> Vec<IVec3> {
+        let mut candidates = Vec::new();
+        self.root.visit(
+            &self.pool,
+            IVec3::ZERO,
+            &Self::extent(),
+            &mut |node| match node {
+                VisitedNode::Leaf(origin, leaf) => candidates.extend(leaf.iter(&[], origin)),
+                VisitedNode::Tile(tile, _) => {
+                    // Only the voxels on the six faces of the tile may border empty space.
+                    // Voxels shared with the faces along previous axes are skipped.
+                    let mut rest = tile;
+                    for axis in 0..3 {
+                        for side in [rest.min[axis], rest.max[axis] - 1] {
+                            let mut face = rest;
+                            face.min[axis] = side;
+                            face.max[axis] = side + 1;
+                            candidates.extend(face.iter());
+                            if rest.max[axis] - rest.min[axis] == 1 {
+                                break;
+                            }
+                        }
+                        rest.min[axis] += 1;
+                        rest.max[axis] -= 1;
+                    }
+                }
+            },
+        );
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, Vec3};
+
+    use super::solve_eikonal;
+    use crate::{hierarchy, Aabb, Tree};
+
+    type MyTree = Tree<hierarchy!(#, 2, 2; u8)>;
+    type SdfTree = Tree<hierarchy!(#, 2, 2; f32)>;
+
+    #[test]
+    fn test_solve_eikonal() {
+        let inf = f32::INFINITY;
+        assert_eq!(solve_eikonal(0.5, inf, inf), 1.5);
+        assert_eq!(solve_eikonal(inf, 2.0, 0.5), 1.5);
+        // Diagonal neighbors at the same distance.
+        let d = solve_eikonal(1.0, 1.0, inf);
+        assert!((d - (1.0 + 0.5f32.sqrt())).abs() < 1e-6);
+        let d = solve_eikonal(1.0, 1.0, 1.0);
+        assert!((d - (1.0 + 1.0 / 3.0f32.sqrt())).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_sphere() {
+        let center = Vec3::splat(0.5);
+        let radius = 10.0;
+        let mut tree = MyTree::new();
+        let bounds = Aabb::new(IVec3::splat(-16), IVec3::splat(17));
+        for coords in bounds.iter() {
+            if (coords.as_vec3() + 0.5).distance(center) <= radius {
+                tree.set_value(coords, Some(1));
+            }
+        }
+        // A tile in the middle of the sphere.
+        tree.fill(Aabb::new(IVec3::splat(-4), IVec3::splat(4)), 1);
+
+        let dense = tree.to_sdf_dense(bounds);
+        assert_eq!(dense.len(), 33 * 33 * 33);
+        for (coords, distance) in bounds.iter().zip(dense.iter()) {
+            let occupied = tree.get_value(coords).is_some();
+            assert_eq!(*distance < 0.0, occupied);
+            // The voxels approximate the sphere by a staircase, which adds about a voxel
+            // of error on top of the error of the first order scheme.
+            let exact = (coords.as_vec3() + 0.5).distance(center) - radius;
+            assert!(
+                (distance - exact).abs() < 1.5,
+                "{coords}: {distance} {exact}"
+            );
+        }
+
+        let band = 3.0;
+        let sdf: SdfTree = tree.to_sdf(band);
+        for (coords, distance) in bounds.iter().zip(dense.iter()) {
+            let value = sdf.get_value(coords);
+            if distance.abs() < band {
+                // The band contains all voxels the distances within the band depend on.
+                assert!((value.unwrap() - distance).abs() < 1e-4);
+            } else if *distance < 0.0 {
+                assert_eq!(value, Some(-band));
+            } else if let Some(value) = value {
+                assert_eq!(value, band);
+            }
+        }
+        assert_eq!(sdf.get_value(IVec3::ZERO), Some(-band));
+    }
+}
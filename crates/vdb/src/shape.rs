@@ -0,0 +1,153 @@
+use glam::{IVec3, Mat4, Vec3, Vec4, Vec4Swizzles};
+
+use crate::Aabb;
+
+/// A region of voxel space used to restrict queries such as [`crate::Tree::iter_in`].
+///
+/// A voxel at `coords` covers the unit cube from `coords` to `coords + 1`, and is
+/// considered part of the shape if that cube intersects the shape.
+pub trait Shape {
+    /// Returns a box containing the shape.
+    fn bounding_box(&self) -> Aabb;
+    /// Returns whether `aabb` intersects the shape. This may conservatively return true
+    /// for boxes close to the shape, but never returns false for intersecting boxes.
+    fn intersects(&self, aabb: &Aabb) -> bool;
+}
+
+impl Shape for Aabb {
+    fn bounding_box(&self) -> Aabb {
+        *self
+    }
+    fn intersects(&self, aabb: &Aabb) -> bool {
+        !self.intersect(aabb).is_empty()
+    }
+}
+
+/// A sphere in voxel space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl Sphere {
+    pub const fn new(center: Vec3, radius: f32) -> Self {
+        Self { center, radius }
+    }
+}
+
+impl Shape for Sphere {
+    fn bounding_box(&self) -> Aabb {
+        let clamp = |x: f64| x.clamp(i32::MIN as f64, i32::MAX as f64) as i32;
+        let min = self.center.as_dvec3() - self.radius as f64;
+        let max = self.center.as_dvec3() + self.radius as f64;
+        Aabb::new(
+            IVec3::new(
+                clamp(min.x.floor()),
+                clamp(min.y.floor()),
+                clamp(min.z.floor()),
+            ),
+            IVec3::new(
+                clamp(max.x.floor() + 1.0),
+                clamp(max.y.floor() + 1.0),
+                clamp(max.z.floor() + 1.0),
+            ),
+        )
+    }
+    fn intersects(&self, aabb: &Aabb) -> bool {
+        if aabb.is_empty() {
+            return false;
+        }
+        let closest = self.center.clamp(aabb.min.as_vec3(), aabb.max.as_vec3());
+        closest.distance_squared(self.center) <= self.radius * self.radius
+    }
+}
+
+/// A convex region bounded by six planes, usually the view frustum of a camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    /// Planes of the frustum. A point `p` is inside of a plane if
+    /// `plane.xyz().dot(p) + plane.w >= 0`.
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extract the frustum from a matrix transforming voxel space into clip space with
+    /// depth ranging from 0 to 1. Both regular and reversed depth are supported, and the
+    /// far plane may be at infinity.
+    pub fn from_matrix(voxel_to_clip: Mat4) -> Self {
+        let row = |i| voxel_to_clip.row(i);
+        Self {
+            planes: [
+                row(3) + row(0),
+                row(3) - row(0),
+                row(3) + row(1),
+                row(3) - row(1),
+                row(2),
+                row(3) - row(2),
+            ],
+        }
+    }
+}
+
+impl Shape for Frustum {
+    /// The frustum is not bounded. Nodes are culled against the planes instead.
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(IVec3::MIN, IVec3::MAX)
+    }
+    fn intersects(&self, aabb: &Aabb) -> bool {
+        if aabb.is_empty() {
+            return false;
+        }
+        let (min, max) = (aabb.min.as_vec3(), aabb.max.as_vec3());
+        self.planes.iter().all(|plane| {
+            // The corner of the box furthest along the normal of the plane.
+            let normal = plane.xyz();
+            let corner = Vec3::select(normal.cmpge(Vec3::ZERO), max, min);
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, Mat4, Vec3};
+
+    use super::*;
+
+    #[test]
+    fn test_sphere() {
+        let sphere = Sphere::new(Vec3::new(0.5, 0.5, 0.5), 2.0);
+        assert_eq!(
+            sphere.bounding_box(),
+            Aabb::new(IVec3::splat(-2), IVec3::splat(3))
+        );
+        let voxel = |x, y, z| Aabb::new(IVec3::new(x, y, z), IVec3::new(x + 1, y + 1, z + 1));
+        assert!(sphere.intersects(&voxel(0, 0, 0)));
+        assert!(sphere.intersects(&voxel(2, 0, 0)));
+        assert!(sphere.intersects(&voxel(-2, 0, 0)));
+        assert!(!sphere.intersects(&voxel(2, 2, 0)));
+        assert!(!sphere.intersects(&voxel(-2, -2, -2)));
+        assert!(!sphere.intersects(&Aabb::new(IVec3::ZERO, IVec3::ZERO)));
+    }
+
+    #[test]
+    fn test_frustum() {
+        let projection = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, 1.0, 100.0);
+        let view = Mat4::look_at_rh(Vec3::ZERO, Vec3::X, Vec3::Y);
+        let frustum = Frustum::from_matrix(projection * view);
+        let voxel = |x, y, z| Aabb::new(IVec3::new(x, y, z), IVec3::new(x + 1, y + 1, z + 1));
+        assert!(frustum.intersects(&voxel(10, 0, 0)));
+        assert!(frustum.intersects(&voxel(10, 9, -10)));
+        assert!(!frustum.intersects(&voxel(10, 12, 0)));
+        assert!(!frustum.intersects(&voxel(-10, 0, 0)));
+        assert!(!frustum.intersects(&voxel(200, 0, 0)));
+        assert!(frustum.intersects(&Aabb::new(IVec3::splat(-1000), IVec3::splat(1000))));
+
+        let reversed = Mat4::perspective_infinite_reverse_rh(std::f32::consts::FRAC_PI_2, 1.0, 1.0);
+        let frustum = Frustum::from_matrix(reversed * view);
+        assert!(frustum.intersects(&voxel(10, 0, 0)));
+        assert!(frustum.intersects(&voxel(100000, 0, 0)));
+        assert!(!frustum.intersects(&voxel(-10, 0, 0)));
+    }
+}
@@ -0,0 +1,182 @@
+use crate::{Node, Tree};
+
+/// A read-only copy of a tree taken with [`Tree::snapshot`].
+pub struct Snapshot<ROOT: Node>
+where
+    [(); ROOT::LEVEL as usize]: Sized,
+{
+    tree: Tree<ROOT>,
+}
+
+impl<ROOT: Node> Snapshot<ROOT>
+where
+    [(); ROOT::LEVEL as usize]: Sized,
+{
+    /// Returns the tree as it was when the snapshot was taken.
+    pub fn tree(&self) -> &Tree<ROOT> {
+        &self.tree
+    }
+}
+
+/// Copy-on-write snapshots.
+///
+/// Snapshots share the root node and the chunks of the node pools with the tree.
+/// Nodes shared with a snapshot are never written. Instead, the first write to a node
+/// copies it to a new slot and re-points its parent to the copy, so editing a single
+/// voxel copies the nodes on its path and nothing else. Slots freed while shared are
+/// reused once no snapshot holds them anymore.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Take a snapshot of the tree, for example to undo later edits.
+    /// This takes constant time, regardless of the size of the tree.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Tree};
+    /// use glam::IVec3;
+    /// let mut tree = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+    /// tree.set_value(IVec3::new(1, 2, 3), Some(1));
+    /// let snapshot = tree.snapshot();
+    /// tree.set_value(IVec3::new(1, 2, 3), Some(2));
+    /// tree.set_value(IVec3::new(-100, 0, 0), Some(2));
+    /// assert_eq!(snapshot.tree().get_value(IVec3::new(1, 2, 3)), Some(1));
+    /// assert_eq!(snapshot.tree().get_value(IVec3::new(-100, 0, 0)), None);
+    ///
+    /// tree.restore(&snapshot);
+    /// assert_eq!(tree.get_value(IVec3::new(1, 2, 3)), Some(1));
+    /// assert_eq!(tree.get_value(IVec3::new(-100, 0, 0)), None);
+    /// ```
+    pub fn snapshot(&self) -> Snapshot<ROOT> {
+        Snapshot {
+            tree: Tree {
+                root: self.root.clone(),
+                pool: self.pool.clone(),
+                journal: None,
+            },
+        }
+    }
+
+    /// Replace the content of the tree with a snapshot. The snapshot stays valid and
+    /// may be restored again.
+    pub fn restore(&mut self, snapshot: &Snapshot<ROOT>) {
+        self.mark_replaced(&snapshot.tree);
+        self.root = snapshot.tree.root.clone();
+        self.pool = snapshot.tree.pool.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+    use rand::prelude::*;
+
+    use crate::{hierarchy, Aabb, Tree};
+
+    type MyTree = Tree<hierarchy!(#, 2, 2; u8)>;
+
+    fn voxels(tree: &MyTree) -> Vec<([i32; 3], Option<u8>)> {
+        let mut voxels: Vec<_> = tree
+            .iter()
+            .map(|c| (c.to_array(), tree.get_value(c)))
+            .collect();
+        voxels.sort_by_key(|(c, _)| *c);
+        voxels
+    }
+
+    #[test]
+    fn test_undo_stack() {
+        let mut rng = rand::thread_rng();
+        let mut tree = MyTree::new();
+        let mut history = Vec::new();
+        let mut expected = Vec::new();
+        for _ in 0..10 {
+            history.push(tree.snapshot());
+            expected.push(voxels(&tree));
+            let mut accessor = tree.accessor_mut();
+            for _ in 0..100 {
+                let coords = IVec3::new(
+                    rng.gen_range(-40..40),
+                    rng.gen_range(-40..40),
+                    rng.gen_range(-40..40),
+                );
+                let value = rng.gen_bool(0.8).then(|| rng.gen_range(1..4));
+                accessor.set(coords, value);
+            }
+            let min = IVec3::new(
+                rng.gen_range(-40..40),
+                rng.gen_range(-40..40),
+                rng.gen_range(-40..40),
+            );
+            tree.fill(Aabb::new(min, min + IVec3::splat(6)), 9);
+            let mut other = MyTree::new();
+            other.fill(Aabb::new(-min, IVec3::splat(5) - min), 7);
+            match rng.gen_range(0..3) {
+                0 => tree.union(&other),
+                1 => tree.difference(&other),
+                _ => tree.merge(other),
+            }
+            tree.prune();
+        }
+        for (snapshot, expected) in history.iter().zip(expected.iter()) {
+            assert_eq!(&voxels(snapshot.tree()), expected);
+        }
+        while let Some(snapshot) = history.pop() {
+            let expected = expected.pop().unwrap();
+            tree.restore(&snapshot);
+            assert_eq!(voxels(&tree), expected);
+            // Editing the restored tree leaves the snapshot intact.
+            tree.set_value(IVec3::new(1000, 0, 0), Some(1));
+            assert_eq!(voxels(snapshot.tree()), expected);
+        }
+    }
+
+    #[test]
+    fn test_copy_on_write() {
+        let mut tree = MyTree::new();
+        for x in 0..3000 {
+            tree.set_value(IVec3::new(x * 4, 0, 0), Some(1));
+        }
+        let num_chunks = tree.pool[0].num_chunks();
+        assert_eq!(num_chunks, 3);
+        let count = tree.pool[0].count();
+        let snapshot = tree.snapshot();
+        assert_eq!(tree.pool[0].num_shared_chunks(), num_chunks);
+
+        // Leaf nodes in different chunks are copied individually into a new chunk.
+        tree.set_value(IVec3::new(1, 0, 0), Some(2));
+        tree.set_value(IVec3::new(2000 * 4 + 1, 0, 0), Some(2));
+        assert_eq!(tree.pool[0].num_chunks(), num_chunks + 1);
+        assert_eq!(tree.pool[0].num_shared_chunks(), num_chunks);
+        assert_eq!(tree.pool[0].count(), count);
+        assert_eq!(snapshot.tree().get_value(IVec3::new(1, 0, 0)), None);
+        assert_eq!(snapshot.tree().get_value(IVec3::new(8001, 0, 0)), None);
+        assert_eq!(tree.get_value(IVec3::new(8001, 0, 0)), Some(2));
+
+        // Cached paths of accessors may point to shared nodes.
+        let mut accessor = tree.accessor_mut();
+        assert_eq!(accessor.get(IVec3::new(400, 0, 0)), Some(1));
+        accessor.set(IVec3::new(401, 0, 0), Some(3));
+        accessor.set(IVec3::new(402, 0, 0), Some(3));
+        assert_eq!(tree.get_value(IVec3::new(402, 0, 0)), Some(3));
+        assert_eq!(snapshot.tree().get_value(IVec3::new(401, 0, 0)), None);
+        assert_eq!(tree.pool[0].num_chunks(), num_chunks + 1);
+
+        // Pruning copies only the nodes it changes.
+        let num_internal_chunks = tree.pool[1].num_chunks();
+        tree.prune();
+        assert_eq!(tree.pool[0].num_chunks(), num_chunks + 1);
+        assert_eq!(tree.pool[1].num_chunks(), num_internal_chunks);
+        assert_eq!(tree.pool[1].num_shared_chunks(), num_internal_chunks - 1);
+
+        for (_, leaf) in tree.iter_leaf_mut() {
+            leaf.occupancy.set(0, false);
+        }
+        assert_eq!(tree.pool[0].num_shared_chunks(), 0);
+        assert_eq!(tree.get_value(IVec3::new(4, 0, 0)), None);
+        assert_eq!(snapshot.tree().get_value(IVec3::new(4, 0, 0)), Some(1));
+
+        drop(snapshot);
+        assert_eq!(tree.pool[1].num_shared_chunks(), 0);
+    }
+}
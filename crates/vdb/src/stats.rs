@@ -0,0 +1,234 @@
+use glam::{I64Vec3, IVec3};
+
+use crate::{Aabb, Node, Pool, Tree, VisitedNode};
+
+/// Memory usage of the nodes of one level. See [`TreeStats`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Number of nodes in use.
+    pub count: u32,
+    /// Number of freed nodes on the freelist.
+    pub free: u32,
+    /// Number of nodes that fit into the allocated chunks.
+    pub capacity: u32,
+    /// Number of allocated chunks.
+    pub num_chunks: usize,
+    /// Size of one node in bytes.
+    pub node_size: usize,
+    /// Number of bytes allocated for the chunks.
+    pub bytes: usize,
+}
+
+impl PoolStats {
+    pub fn new(pool: &Pool) -> Self {
+        Self {
+            count: pool.count(),
+            free: pool.num_free(),
+            capacity: pool.capacity(),
+            num_chunks: pool.num_chunks(),
+            node_size: pool.item_size(),
+            bytes: pool.allocated_bytes(),
+        }
+    }
+    /// Fraction of the allocated chunks holding nodes in use.
+    pub fn usage(&self) -> f32 {
+        if self.capacity == 0 {
+            return 0.0;
+        }
+        self.count as f32 / self.capacity as f32
+    }
+    /// Fraction of the touched part of the chunks that sits on the freelist.
+    /// A fragmentation that keeps growing while the number of nodes stays the same
+    /// hints at nodes being leaked or freed without being reused.
+    pub fn fragmentation(&self) -> f32 {
+        if self.count + self.free == 0 {
+            return 0.0;
+        }
+        self.free as f32 / (self.count + self.free) as f32
+    }
+}
+
+/// Memory usage of a tree. See [`Tree::stats`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreeStats {
+    /// Statistics of the node pools, indexed by level. The root node is not kept in a pool.
+    pub levels: Vec<PoolStats>,
+    /// Size of the root node in bytes, excluding heap allocations.
+    pub root_size: usize,
+}
+
+impl TreeStats {
+    /// Number of nodes in use, excluding the root node.
+    pub fn node_count(&self) -> u64 {
+        self.levels.iter().map(|level| level.count as u64).sum()
+    }
+    /// Number of bytes allocated for the tree.
+    pub fn bytes(&self) -> usize {
+        self.root_size + self.levels.iter().map(|level| level.bytes).sum::<usize>()
+    }
+}
+
+/// Bounds and statistics.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Returns the smallest box containing all occupied voxels, or None if the tree is empty.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Aabb, Tree};
+    /// use glam::IVec3;
+    /// let mut tree = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+    /// assert_eq!(tree.bounds(), None);
+    /// tree.set_value(IVec3::new(-5, 2, 3), Some(1));
+    /// tree.fill(Aabb::new(IVec3::new(0, 0, 0), IVec3::new(16, 4, 4)), 2);
+    /// assert_eq!(tree.bounds(), Some(Aabb::new(IVec3::new(-5, 0, 0), IVec3::new(16, 4, 4))));
+    /// assert_eq!(tree.voxel_count(), 16 * 4 * 4 + 1);
+    /// ```
+    pub fn bounds(&self) -> Option<Aabb> {
+        let mut min = I64Vec3::MAX;
+        let mut max = I64Vec3::MIN;
+        let mut include = |aabb: Aabb| {
+            min = min.min(aabb.min.as_i64vec3());
+            max = max.max(aabb.max.as_i64vec3());
+        };
+        self.root.visit(
+            &self.pool,
+            IVec3::ZERO,
+            &Self::extent(),
+            &mut |node| match node {
+                VisitedNode::Tile(tile, _) => include(tile),
+                VisitedNode::Leaf(origin, leaf) => {
+                    for coords in leaf.iter(&[], origin) {
+                        include(Aabb::new(coords, coords + IVec3::ONE));
+                    }
+                }
+            },
+        );
+        if min.cmpgt(max).any() {
+            return None;
+        }
+        Some(Aabb::new(min.as_ivec3(), max.as_ivec3()))
+    }
+
+    /// Returns the number of occupied voxels, including the voxels covered by tiles.
+    pub fn voxel_count(&self) -> u64 {
+        let mut count = 0;
+        self.root
+            .visit(&self.pool, IVec3::ZERO, &Self::extent(), &mut |node| {
+                count += match node {
+                    VisitedNode::Tile(tile, _) => {
+                        let extent = tile.extent().as_u64vec3();
+                        extent.x * extent.y * extent.z
+                    }
+                    VisitedNode::Leaf(origin, leaf) => leaf.iter(&[], origin).count() as u64,
+                };
+            });
+        count
+    }
+
+    /// Returns the number of nodes and the memory allocated on each level.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Tree};
+    /// use glam::IVec3;
+    /// let mut tree = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+    /// tree.set_value(IVec3::new(0, 0, 0), Some(1));
+    /// tree.set_value(IVec3::new(4, 0, 0), Some(1));
+    /// tree.set_value(IVec3::new(4, 0, 0), None);
+    /// let stats = tree.stats();
+    /// assert_eq!(stats.levels[0].count, 1);
+    /// assert_eq!(stats.levels[0].free, 1);
+    /// assert_eq!(stats.levels[0].fragmentation(), 0.5);
+    /// assert_eq!(stats.levels[1].count, 1);
+    /// assert_eq!(stats.node_count(), 2);
+    /// ```
+    pub fn stats(&self) -> TreeStats {
+        TreeStats {
+            levels: self.pool.iter().map(PoolStats::new).collect(),
+            root_size: std::mem::size_of::<ROOT>(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+    use rand::prelude::*;
+
+    use crate::{hierarchy, Aabb, Tree};
+
+    type MyTree = Tree<hierarchy!(#, 2, 2; u8)>;
+
+    #[test]
+    fn test_bounds_and_count() {
+        let mut rng = rand::thread_rng();
+        let mut tree = MyTree::new();
+        let mut min = IVec3::MAX;
+        let mut max = IVec3::MIN;
+        for _ in 0..100 {
+            let coords = IVec3::new(
+                rng.gen_range(-100..100),
+                rng.gen_range(-100..100),
+                rng.gen_range(-100..100),
+            );
+            tree.set_value(coords, Some(1));
+            min = min.min(coords);
+            max = max.max(coords + IVec3::ONE);
+        }
+        assert_eq!(tree.bounds(), Some(Aabb::new(min, max)));
+        assert_eq!(tree.voxel_count(), tree.iter().count() as u64);
+
+        tree.fill(Aabb::new(IVec3::splat(200), IVec3::splat(232)), 2);
+        assert_eq!(tree.bounds().unwrap().max, IVec3::splat(232));
+        assert_eq!(tree.voxel_count(), tree.iter().count() as u64);
+        assert_eq!(
+            tree.voxel_count(),
+            tree.iter_in(MyTree::extent()).count() as u64
+        );
+
+        let mut tree = MyTree::new();
+        tree.set_value(IVec3::splat(i32::MAX - 1), Some(1));
+        tree.set_value(IVec3::splat(i32::MIN), Some(1));
+        assert_eq!(
+            tree.bounds(),
+            Some(Aabb::new(IVec3::splat(i32::MIN), IVec3::splat(i32::MAX)))
+        );
+        assert_eq!(tree.voxel_count(), 2);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut tree = MyTree::new();
+        let stats = tree.stats();
+        assert_eq!(stats.node_count(), 0);
+        assert_eq!(stats.levels.len(), 2);
+        assert_eq!(stats.levels[0].usage(), 0.0);
+        assert_eq!(stats.levels[0].fragmentation(), 0.0);
+
+        for x in 0..64 {
+            tree.set_value(IVec3::new(x * 4, 0, 0), Some(1));
+        }
+        let stats = tree.stats();
+        assert_eq!(stats.levels[0].count, 64);
+        assert_eq!(stats.levels[1].count, 16);
+        assert_eq!(stats.levels[0].num_chunks, 1);
+        assert_eq!(stats.levels[0].capacity, 1024);
+        assert_eq!(stats.levels[0].usage(), 64.0 / 1024.0);
+        assert_eq!(
+            stats.levels[0].bytes,
+            stats.levels[0].node_size * stats.levels[0].capacity as usize
+        );
+        assert!(stats.bytes() > stats.levels[0].bytes + stats.levels[1].bytes);
+
+        for x in 0..32 {
+            tree.set_value(IVec3::new(x * 4, 0, 0), None);
+        }
+        let stats = tree.stats();
+        assert_eq!(stats.levels[0].count, 32);
+        assert_eq!(stats.levels[0].free, 32);
+        assert_eq!(stats.levels[1].count, 8);
+        assert_eq!(stats.levels[1].free, 8);
+        assert_eq!(stats.levels[0].fragmentation(), 0.5);
+    }
+}
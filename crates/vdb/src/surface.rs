@@ -0,0 +1,222 @@
+use std::sync::Arc;
+
+use fxhash::FxHashMap;
+use glam::{IVec3, UVec3};
+
+use crate::{
+    morphology::{face_masks, shift_along},
+    Aabb, IsLeaf, Node, NodeConst, Tree, VisitedNode,
+};
+
+/// Surface extraction.
+///
+/// A voxel is located on the surface if it is occupied and at least one of its six
+/// neighbors is not. The result is stored in the `active` mask of the leaf nodes.
+/// Voxels within tiles have no such mask, so tiles are never part of the surface
+/// themselves, but they do hide the faces of the leaf voxels next to them.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Recompute the surface mask of all leaf nodes.
+    pub fn update_active(&mut self)
+    where
+        ROOT: ~const NodeConst,
+    {
+        self.update_active_in(Self::extent())
+    }
+
+    /// Recompute the surface mask after editing the voxels within `aabb`.
+    /// Voxels next to `aabb` are updated as well, since their neighbors may have changed.
+    /// Leaf nodes touched by the edits are updated entirely, since writing into a tile
+    /// densifies it into new leaf nodes.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Aabb, IsLeaf, Tree};
+    /// use glam::{IVec3, UVec3};
+    /// let mut tree = Tree::<hierarchy!(#, 2, 2)>::new();
+    /// for x in 0..3 {
+    ///     tree.set_value(IVec3::new(x, 0, 0), Some(true));
+    /// }
+    /// tree.update_active();
+    /// let (_, leaf) = tree.iter_leaf().next().unwrap();
+    /// assert!(leaf.is_active(UVec3::new(1, 0, 0)));
+    ///
+    /// tree.fill(Aabb::new(IVec3::new(0, 1, 0), IVec3::new(3, 2, 1)), true);
+    /// tree.fill(Aabb::new(IVec3::new(0, -1, 0), IVec3::new(3, 0, 1)), true);
+    /// tree.fill(Aabb::new(IVec3::new(0, 0, 1), IVec3::new(3, 1, 2)), true);
+    /// tree.fill(Aabb::new(IVec3::new(0, 0, -1), IVec3::new(3, 1, 0)), true);
+    /// tree.update_active_in(Aabb::new(IVec3::new(0, -1, -1), IVec3::new(3, 2, 2)));
+    /// let leaf = tree
+    ///     .iter_leaf()
+    ///     .find(|(origin, _)| *origin == IVec3::ZERO)
+    ///     .unwrap()
+    ///     .1;
+    /// assert!(!leaf.is_active(UVec3::new(1, 0, 0)));
+    /// assert!(leaf.is_active(UVec3::new(0, 0, 0)));
+    /// ```
+    pub fn update_active_in(&mut self, aabb: Aabb)
+    where
+        ROOT: ~const NodeConst,
+    {
+        let extent = Self::extent();
+        let aabb = Aabb::new(
+            aabb.min.max(IVec3::splat(i32::MIN + 1)) - IVec3::ONE,
+            aabb.max.min(IVec3::splat(i32::MAX - 1)) + IVec3::ONE,
+        )
+        .intersect(&extent);
+        if aabb.is_empty() {
+            return;
+        }
+        let log2 = <ROOT::LeafType as Node>::EXTENT_LOG2;
+        let leaf_extent = <ROOT::LeafType as Node>::EXTENT.as_ivec3();
+        let num_words = (1_usize << (log2.x + log2.y + log2.z)) / u64::BITS as usize;
+
+        // Leaf nodes next to the updated ones provide the voxels across their faces.
+        let neighborhood = Aabb::new(
+            aabb.min.max(IVec3::splat(i32::MIN) + leaf_extent) - leaf_extent,
+            aabb.max.min(IVec3::splat(i32::MAX) - leaf_extent) + leaf_extent,
+        )
+        .intersect(&extent);
+        let mut blocks = FxHashMap::default();
+        self.root
+            .visit(&self.pool, IVec3::ZERO, &neighborhood, &mut |node| {
+                if let VisitedNode::Leaf(origin, leaf) = node {
+                    let mut occupancy = vec![0; num_words];
+                    leaf.get_occupancy(&mut occupancy);
+                    let mut active = vec![0; num_words];
+                    leaf.get_active(&mut active);
+                    blocks.insert(origin, (occupancy, active));
+                }
+            });
+        let full = vec![u64::MAX; num_words];
+        let empty = vec![0; num_words];
+        let occupancy_of = |origin: IVec3, axis: usize, upper: bool| -> &[u64] {
+            let coord = if upper {
+                origin[axis].checked_add(leaf_extent[axis])
+            } else {
+                origin[axis].checked_sub(leaf_extent[axis])
+            };
+            let mut neighbor = origin;
+            match coord {
+                Some(coord) => neighbor[axis] = coord,
+                // Voxels outside of the tree are empty.
+                None => return &empty,
+            }
+            if !extent.contains(neighbor) {
+                return &empty;
+            }
+            if let Some((occupancy, _)) = blocks.get(&neighbor) {
+                return occupancy;
+            }
+            match self.root.probe(&self.pool, neighbor.as_uvec3()) {
+                (Some(_), extent_log2) if extent_log2 != UVec3::ZERO => &full,
+                _ => &empty,
+            }
+        };
+
+        // A voxel is enclosed if the next voxels in all six directions are occupied.
+        let faces = face_masks::<ROOT::LeafType>();
+        let mut updates = Vec::new();
+        for (origin, (occupancy, active)) in blocks.iter() {
+            if aabb
+                .intersect(&Aabb::from_extent(*origin, leaf_extent.as_uvec3()))
+                .is_empty()
+            {
+                continue;
+            }
+            let mut enclosed = full.clone();
+            for axis in 0..3 {
+                for upper in [false, true] {
+                    let neighbor = occupancy_of(*origin, axis, upper);
+                    let next =
+                        shift_along::<ROOT::LeafType>(occupancy, neighbor, axis, upper, &faces);
+                    for (enclosed, next) in enclosed.iter_mut().zip(next) {
+                        *enclosed &= next;
+                    }
+                }
+            }
+            let surface: Vec<u64> = occupancy
+                .iter()
+                .zip(enclosed)
+                .map(|(occupied, enclosed)| occupied & !enclosed)
+                .collect();
+            if &surface != active {
+                updates.push((*origin, surface));
+            }
+        }
+
+        for (origin, surface) in updates {
+            // The leaf exists, so this does not allocate.
+            let leaf = unsafe {
+                &mut *Arc::make_mut(&mut self.root).touch_leaf(&mut self.pool, origin.as_uvec3())
+            };
+            leaf.set_active_mask(&surface);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+    use rand::prelude::*;
+
+    use crate::{hierarchy, Aabb, IsLeaf, Node, Tree};
+
+    const NEIGHBORS: [IVec3; 6] = [
+        IVec3::X,
+        IVec3::NEG_X,
+        IVec3::Y,
+        IVec3::NEG_Y,
+        IVec3::Z,
+        IVec3::NEG_Z,
+    ];
+
+    type MyTree = Tree<hierarchy!(#, 2, 2; u8)>;
+
+    fn assert_active(tree: &MyTree) {
+        for (origin, leaf) in tree.iter_leaf() {
+            for local in leaf.iter(&[], IVec3::ZERO) {
+                let coords = origin + local;
+                let expected = NEIGHBORS
+                    .iter()
+                    .any(|offset| tree.get_value(coords + *offset).is_none());
+                assert_eq!(leaf.is_active(local.as_uvec3()), expected, "{:?}", coords);
+            }
+        }
+    }
+
+    #[test]
+    fn test_update_active() {
+        let mut rng = rand::thread_rng();
+        let mut tree = MyTree::new();
+        tree.fill(Aabb::new(IVec3::splat(-16), IVec3::splat(0)), 1);
+        for _ in 0..2000 {
+            let coords = IVec3::new(
+                rng.gen_range(-20..20),
+                rng.gen_range(-20..20),
+                rng.gen_range(-20..20),
+            );
+            tree.set_value(coords, Some(2));
+        }
+        tree.update_active();
+        assert_active(&tree);
+
+        // Incremental updates after edits.
+        for _ in 0..200 {
+            let coords = IVec3::new(
+                rng.gen_range(-20..20),
+                rng.gen_range(-20..20),
+                rng.gen_range(-20..20),
+            );
+            let value = if rng.gen() { Some(3) } else { None };
+            tree.set_value(coords, value);
+            tree.update_active_in(Aabb::from_extent(coords, glam::UVec3::ONE));
+        }
+        assert_active(&tree);
+
+        tree.clear(Aabb::new(IVec3::splat(-8), IVec3::splat(8)));
+        tree.update_active_in(Aabb::new(IVec3::splat(-8), IVec3::splat(8)));
+        assert_active(&tree);
+    }
+}
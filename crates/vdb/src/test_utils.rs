@@ -0,0 +1,51 @@
+//! Scaffolding shared by the tests of the voxel operations.
+use std::ops::Range;
+
+use fxhash::FxHashSet;
+use glam::IVec3;
+use rand::prelude::*;
+
+use crate::{hierarchy, Aabb, Connectivity, Tree};
+
+pub type TestTree = Tree<hierarchy!(#, 2, 2; u8)>;
+
+/// Offsets to the neighbors of a voxel.
+pub fn neighborhood(connectivity: Connectivity) -> Vec<IVec3> {
+    let max_nonzero = match connectivity {
+        Connectivity::Faces => 1,
+        Connectivity::Edges => 2,
+        Connectivity::Vertices => 3,
+    };
+    Aabb::new(IVec3::NEG_ONE, IVec3::splat(2))
+        .iter()
+        .filter(|d| *d != IVec3::ZERO && d.abs().dot(IVec3::ONE) <= max_nonzero)
+        .collect()
+}
+
+/// Returns a tree with `count` random voxels around the origin valued within `values`,
+/// a root tile valued `values.end` and a tile within an internal node valued
+/// `values.end + 1`.
+pub fn random_tree(rng: &mut impl Rng, count: usize, values: Range<u8>) -> TestTree {
+    let mut tree = TestTree::new();
+    for _ in 0..count {
+        let coords = IVec3::new(
+            rng.gen_range(-12..12),
+            rng.gen_range(-12..12),
+            rng.gen_range(-12..12),
+        );
+        tree.set_value(coords, Some(rng.gen_range(values.clone())));
+    }
+    tree.fill(Aabb::new(IVec3::splat(-16), IVec3::splat(0)), values.end);
+    tree.fill(
+        Aabb::new(IVec3::new(4, 0, 0), IVec3::new(8, 4, 4)),
+        values.end + 1,
+    );
+    tree
+}
+
+/// Returns the occupied voxels within `aabb`.
+pub fn occupied(tree: &TestTree, aabb: Aabb) -> FxHashSet<IVec3> {
+    aabb.iter()
+        .filter(|v| tree.get_value(*v).is_some())
+        .collect()
+}
@@ -1,27 +1,43 @@
-use std::mem::MaybeUninit;
+use std::{mem::MaybeUninit, sync::Arc};
 
-use glam::UVec3;
+use glam::{IVec3, UVec3};
 
-use crate::{Node, NodeConst, NodeMeta, Pool};
+use crate::{journal::Journal, Aabb, Node, NodeConst, NodeMeta, Pool, Shape, VisitedNode};
 
 pub struct Tree<ROOT: Node>
 where
     [(); ROOT::LEVEL as usize]: Sized,
 {
-    pub(crate) root: ROOT,
+    /// Shared with snapshots until the tree writes to it.
+    pub(crate) root: Arc<ROOT>,
     pub(crate) pool: [Pool; ROOT::LEVEL as usize],
+    pub(crate) journal: Option<Journal>,
 }
 
 /// ```
 /// #![feature(generic_const_exprs)]
 /// use dust_vdb::{hierarchy, Node, Tree};
-/// use glam::UVec3;
+/// use glam::IVec3;
 /// let mut tree = Tree::<hierarchy!(2, 2)>::new();
-/// tree.set_value(UVec3{x: 0, y: 4, z: 0}, Some(true));
-/// tree.set_value(UVec3{x: 0, y: 2, z: 2}, Some(false));
-/// assert_eq!(tree.get_value(UVec3::new(0, 4, 0)), Some(true));
-/// assert_eq!(tree.get_value(UVec3::new(0, 3, 0)), None);
-/// assert_eq!(tree.get_value(UVec3::new(0, 2, 2)), Some(false));
+/// tree.set_value(IVec3{x: 0, y: 4, z: 0}, Some(true));
+/// tree.set_value(IVec3{x: 0, y: 2, z: 2}, Some(false));
+/// assert_eq!(tree.get_value(IVec3::new(0, 4, 0)), Some(true));
+/// assert_eq!(tree.get_value(IVec3::new(0, 3, 0)), None);
+/// assert_eq!(tree.get_value(IVec3::new(0, 2, 2)), Some(false));
+///
+/// // Trees with a root node extend in all directions.
+/// let mut tree = Tree::<hierarchy!(#, 2, 2)>::new();
+/// tree.set_value(IVec3::new(-1, -20, 300), Some(true));
+/// assert_eq!(tree.get_value(IVec3::new(-1, -20, 300)), Some(true));
+/// assert_eq!(tree.get_value(IVec3::new(15, -20, 300)), None);
+/// ```
+///
+/// Each level has to subdivide every axis. Trees of other hierarchies fail to compile:
+/// ```compile_fail
+/// #![feature(generic_const_exprs)]
+/// use dust_vdb::{ConstUVec3, InternalNode, LeafNode, Tree};
+/// type Leaf = LeafNode<{ ConstUVec3 { x: 2, y: 2, z: 2 } }>;
+/// let tree = Tree::<InternalNode<Leaf, { ConstUVec3 { x: 3, y: 0, z: 3 } }>>::new();
 /// ```
 impl<ROOT: Node> Tree<ROOT>
 where
@@ -31,6 +47,8 @@ where
     where
         ROOT: ~const NodeConst,
     {
+        // Evaluating the mask rejects hierarchies the accessors can't handle at compile time.
+        let _ = <Self as TreeMeta<ROOT>>::META_MASK;
         let mut pools: [MaybeUninit<Pool>; ROOT::LEVEL as usize] = MaybeUninit::uninit_array();
         for (i, meta) in Self::METAS.iter().take(ROOT::LEVEL).enumerate() {
             let pool = Pool::new(meta.layout, 10);
@@ -42,8 +60,9 @@ where
             (&*(&MaybeUninit::new(pools) as *const _ as *const MaybeUninit<_>)).assume_init_read()
         };
         Self {
-            root: ROOT::default(),
+            root: Arc::new(ROOT::default()),
             pool: pools,
+            journal: None,
         }
     }
     pub unsafe fn alloc_node<CHILD: Node>(&mut self) -> u32 {
@@ -59,7 +78,7 @@ where
     pub unsafe fn get_node<CHILD: Node>(&self, ptr: u32) -> &CHILD {
         if CHILD::LEVEL == ROOT::LEVEL {
             // specialization for root
-            return &*(&self.root as *const ROOT as *const CHILD);
+            return &*(&*self.root as *const ROOT as *const CHILD);
         }
         &*(self.pool[CHILD::LEVEL as usize].get(ptr) as *const CHILD)
     }
@@ -69,55 +88,200 @@ where
     pub unsafe fn get_node_mut<CHILD: Node>(&mut self, ptr: u32) -> &mut CHILD {
         if CHILD::LEVEL == ROOT::LEVEL {
             // specialization for root
-            return &mut *(&mut self.root as *mut ROOT as *mut CHILD);
+            return &mut *(Arc::make_mut(&mut self.root) as *mut ROOT as *mut CHILD);
         }
         &mut *(self.pool[CHILD::LEVEL as usize].get_mut(ptr) as *mut CHILD)
     }
 
+    /// The box of all coordinates addressable by the tree.
+    /// Trees with a root node span the whole signed coordinate space.
+    pub fn extent() -> Aabb {
+        if ROOT::SIZE == usize::MAX {
+            Aabb::new(IVec3::splat(i32::MIN), IVec3::splat(i32::MAX))
+        } else {
+            Aabb::from_extent(IVec3::ZERO, ROOT::EXTENT)
+        }
+    }
+
+    /// Whether `coords` lies within [`Tree::extent`].
     #[inline]
-    pub fn get_value(&self, coords: UVec3) -> Option<ROOT::Voxel> {
-        self.root.get(&self.pool, coords, &mut [])
+    pub(crate) fn in_extent(coords: IVec3) -> bool {
+        ROOT::SIZE == usize::MAX || Self::extent().contains(coords)
+    }
+
+    /// Returns `None` for coordinates outside of [`Tree::extent`].
+    #[inline]
+    pub fn get_value(&self, coords: IVec3) -> Option<ROOT::Voxel> {
+        if !Self::in_extent(coords) {
+            return None;
+        }
+        self.root.get(&self.pool, coords.as_uvec3(), &mut [])
     }
 
+    /// Coordinates outside of [`Tree::extent`] are ignored.
     #[inline]
-    pub fn set_value(&mut self, coords: UVec3, value: Option<ROOT::Voxel>) {
-        self.root.set(&mut self.pool, coords, value, &mut [])
+    pub fn set_value(&mut self, coords: IVec3, value: Option<ROOT::Voxel>) {
+        if !Self::in_extent(coords) {
+            return;
+        }
+        self.mark_leaf(coords);
+        Arc::make_mut(&mut self.root).set(&mut self.pool, coords.as_uvec3(), value, &mut [])
+    }
+
+    /// Set all voxels within `aabb` to `value`.
+    /// Regions covering entire nodes are stored as a single tile on the highest possible level.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Aabb, Tree};
+    /// use glam::IVec3;
+    /// let mut tree = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+    /// tree.fill(Aabb::new(IVec3::new(-4, 0, 0), IVec3::new(20, 16, 16)), 3);
+    /// assert_eq!(tree.get_value(IVec3::new(5, 5, 5)), Some(3));
+    /// assert_eq!(tree.get_value(IVec3::new(-4, 15, 0)), Some(3));
+    /// assert_eq!(tree.get_value(IVec3::new(19, 15, 0)), Some(3));
+    /// assert_eq!(tree.get_value(IVec3::new(20, 0, 0)), None);
+    /// assert_eq!(tree.get_value(IVec3::new(-5, 0, 0)), None);
+    ///
+    /// // Writing into a tile densifies it on demand.
+    /// tree.set_value(IVec3::new(5, 5, 5), Some(4));
+    /// assert_eq!(tree.get_value(IVec3::new(5, 5, 5)), Some(4));
+    /// assert_eq!(tree.get_value(IVec3::new(5, 5, 6)), Some(3));
+    /// ```
+    pub fn fill(&mut self, aabb: Aabb, value: ROOT::Voxel) {
+        let aabb = aabb.intersect(&Self::extent());
+        if aabb.is_empty() {
+            return;
+        }
+        self.track_region(aabb, |tree| {
+            Arc::make_mut(&mut tree.root).fill(&mut tree.pool, aabb, Some(value));
+        });
+    }
+
+    /// Free all nodes that no longer contain any occupied voxels, and collapse nodes
+    /// filled with a single value into tiles.
+    /// Clearing voxels with [`Tree::set_value`] already frees nodes along the path,
+    /// so this is only needed after editing leaf nodes directly, for example with
+    /// [`Tree::iter_leaf_mut`].
+    pub fn prune(&mut self) {
+        self.track_freed(|tree| {
+            Arc::make_mut(&mut tree.root).prune(&mut tree.pool);
+        });
     }
 
+    /// Returns the coordinates of all occupied voxels, including the voxels covered by
+    /// tiles, for example after [`Tree::fill`]. Use [`Tree::iter_leaf`] to only visit the
+    /// voxels stored in leaf nodes.
     /// ```
     /// #![feature(generic_const_exprs)]
-    /// use dust_vdb::{Tree, hierarchy};
-    /// use glam::UVec3;
+    /// use dust_vdb::{Tree, hierarchy, Aabb};
+    /// use glam::IVec3;
     /// let mut tree = Tree::<hierarchy!(4, 2)>::new();
-    /// tree.set_value(UVec3::new(0, 1, 2), Some(true));
-    /// tree.set_value(UVec3::new(63, 1, 3), Some(true));
-    /// tree.set_value(UVec3::new(63, 63, 63), Some(true));
+    /// tree.set_value(IVec3::new(0, 1, 2), Some(true));
+    /// tree.set_value(IVec3::new(63, 1, 3), Some(true));
+    /// tree.set_value(IVec3::new(63, 63, 63), Some(true));
     /// let mut iter = tree.iter();
-    /// assert_eq!(iter.next().unwrap(), UVec3::new(0, 1, 2));
-    /// assert_eq!(iter.next().unwrap(), UVec3::new(63, 1, 3));
-    /// assert_eq!(iter.next().unwrap(), UVec3::new(63, 63, 63));
+    /// assert_eq!(iter.next().unwrap(), IVec3::new(0, 1, 2));
+    /// assert_eq!(iter.next().unwrap(), IVec3::new(63, 1, 3));
+    /// assert_eq!(iter.next().unwrap(), IVec3::new(63, 63, 63));
     /// assert!(iter.next().is_none());
     ///
+    /// tree.fill(Aabb::new(IVec3::new(16, 16, 16), IVec3::new(32, 32, 32)), true);
+    /// assert_eq!(tree.iter().count(), 3 + 16 * 16 * 16);
+    /// assert_eq!(tree.iter_in(Tree::<hierarchy!(4, 2)>::extent()).count(), 3 + 16 * 16 * 16);
     /// ```
     pub fn iter<'a>(&'a self) -> ROOT::Iterator<'a> {
-        self.root.iter(&self.pool, UVec3 { x: 0, y: 0, z: 0 })
+        self.root.iter(&self.pool, IVec3 { x: 0, y: 0, z: 0 })
     }
 
-    pub fn iter_leaf<'a>(&'a self) -> impl Iterator<Item = (UVec3, &'a ROOT::LeafType)> {
+    /// Returns all leaf nodes and their origins.
+    ///
+    /// Tiles are not leaf nodes, so regions stored as tiles are skipped.
+    /// Use [`Tree::iter_in`] to also visit the voxels of tiles.
+    pub fn iter_leaf<'a>(&'a self) -> impl Iterator<Item = (IVec3, &'a ROOT::LeafType)> {
         self.root
-            .iter_leaf(&self.pool, UVec3 { x: 0, y: 0, z: 0 })
+            .iter_leaf(&self.pool, IVec3 { x: 0, y: 0, z: 0 })
             .map(|(position, leaf)| unsafe {
                 let leaf: &'a ROOT::LeafType = &*leaf.get();
                 (position, leaf)
             })
     }
 
+    /// Returns the coordinates of all occupied voxels intersecting with `shape`,
+    /// including the voxels covered by tiles.
+    /// Subtrees outside of the shape are skipped without being visited.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Aabb, Sphere, Tree};
+    /// use glam::{IVec3, Vec3};
+    /// let mut tree = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+    /// tree.set_value(IVec3::new(1, 2, 3), Some(1));
+    /// tree.set_value(IVec3::new(100, -100, 0), Some(1));
+    /// tree.fill(Aabb::new(IVec3::new(-4, 0, 0), IVec3::new(0, 4, 4)), 2);
+    /// let aabb = Aabb::new(IVec3::new(-1, 0, 0), IVec3::new(2, 3, 4));
+    /// let mut voxels: Vec<IVec3> = tree.iter_in(aabb).collect();
+    /// assert_eq!(voxels.len(), 12 + 1);
+    /// assert!(voxels.contains(&IVec3::new(1, 2, 3)));
+    ///
+    /// let sphere = Sphere::new(Vec3::new(100.5, -99.5, 0.5), 2.0);
+    /// assert_eq!(tree.iter_in(sphere).collect::<Vec<_>>(), vec![IVec3::new(100, -100, 0)]);
+    /// ```
+    pub fn iter_in<'a, S: Shape + 'a>(&'a self, shape: S) -> impl Iterator<Item = IVec3> + 'a {
+        let mut nodes = Vec::new();
+        self.root
+            .visit(&self.pool, IVec3::ZERO, &shape, &mut |node| {
+                nodes.push(node)
+            });
+        let bounding_box = shape.bounding_box();
+        nodes
+            .into_iter()
+            .flat_map(move |node| {
+                let (leaf_voxels, tile) = match node {
+                    VisitedNode::Leaf(origin, leaf) => (
+                        Some(leaf.iter(&[], origin)),
+                        Aabb::new(IVec3::ZERO, IVec3::ZERO),
+                    ),
+                    VisitedNode::Tile(tile, _) => (None, tile.intersect(&bounding_box)),
+                };
+                leaf_voxels.into_iter().flatten().chain(tile.iter())
+            })
+            .filter(move |coords| shape.intersects(&Aabb::new(*coords, *coords + IVec3::ONE)))
+    }
+
+    /// Returns the leaf nodes intersecting with `shape`.
+    /// Subtrees outside of the shape are skipped without being visited.
+    pub fn iter_leaf_in<S: Shape>(
+        &self,
+        shape: S,
+    ) -> impl Iterator<Item = (IVec3, &ROOT::LeafType)> {
+        let mut leaves = Vec::new();
+        self.root
+            .visit(&self.pool, IVec3::ZERO, &shape, &mut |node| {
+                if let VisitedNode::Leaf(origin, leaf) = node {
+                    leaves.push((origin, leaf));
+                }
+            });
+        leaves.into_iter()
+    }
+
+    /// Returns all leaf nodes for writing.
+    ///
+    /// Any of the leaf nodes may be written, so all chunks of leaf nodes shared with a
+    /// [`Snapshot`](crate::Snapshot) are copied up front. To edit a few voxels of a tree
+    /// with snapshots, use [`Tree::accessor_mut`] instead.
     pub fn iter_leaf_mut<'a>(
         &'a mut self,
-    ) -> impl Iterator<Item = (UVec3, &'a mut ROOT::LeafType)> {
+    ) -> impl Iterator<Item = (IVec3, &'a mut ROOT::LeafType)> {
+        // Leaf nodes are written through shared references below.
+        if let Some(pool) = self.pool.first_mut() {
+            pool.make_unique();
+        }
+        let mut journal = self.journal.as_mut();
         self.root
-            .iter_leaf(&mut self.pool, UVec3 { x: 0, y: 0, z: 0 })
-            .map(|(position, leaf)| unsafe {
+            .iter_leaf(&mut self.pool, IVec3 { x: 0, y: 0, z: 0 })
+            .map(move |(position, leaf)| unsafe {
+                if let Some(journal) = journal.as_mut() {
+                    journal.mark_existing_leaf(position);
+                }
                 let leaf: &'a mut ROOT::LeafType = &mut *leaf.get();
                 (position, leaf)
             })
@@ -151,11 +315,25 @@ where
 
         metas
     };
+    /// Each level contributes the highest bit of its extent on every axis, so the number
+    /// of levels containing two coordinates can be counted per axis.
+    /// This only works when each level is strictly larger than its child on all axes.
     const META_MASK: UVec3 = {
         let mut mask: UVec3 = UVec3::ZERO;
         let mut i = 0;
         while i < Self::METAS.len() {
             let meta = &Self::METAS[i];
+            let child_extent_log2 = if i == 0 {
+                UVec3::ZERO
+            } else {
+                Self::METAS[i - 1].extent_log2
+            };
+            if meta.extent_log2.x <= child_extent_log2.x
+                || meta.extent_log2.y <= child_extent_log2.y
+                || meta.extent_log2.z <= child_extent_log2.z
+            {
+                panic!("Each level must have a fanout of at least 2 on every axis");
+            }
             mask = UVec3 {
                 x: mask.x | (1 << (meta.extent_log2.x - 1)),
                 y: mask.y | (1 << (meta.extent_log2.y - 1)),
@@ -178,3 +356,214 @@ where
         id
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, UVec3, Vec3};
+    use rand::prelude::*;
+
+    use crate::{hierarchy, Aabb, Shape, Sphere, Tree};
+
+    #[test]
+    fn test_anisotropic_id() {
+        use crate::tree::TreeMeta;
+        type Flat = Tree<hierarchy!(#, (3, 1, 3), (4, 2, 4))>;
+        type Tall = Tree<hierarchy!(#, (1, 3, 1), (2, 4, 2))>;
+        type Cube = Tree<hierarchy!(#, 3, 4)>;
+        assert_ne!(<Flat as TreeMeta<_>>::ID, <Tall as TreeMeta<_>>::ID);
+        assert_ne!(<Flat as TreeMeta<_>>::ID, <Cube as TreeMeta<_>>::ID);
+        assert_eq!(
+            <Cube as TreeMeta<_>>::ID,
+            <Tree<hierarchy!(#, (3, 3, 3), (4, 4, 4))> as TreeMeta<_>>::ID
+        );
+    }
+
+    #[test]
+    fn test_clear_frees_nodes() {
+        let mut tree = Tree::<hierarchy!(#, 2, 2)>::new();
+        let locations = [
+            IVec3::new(0, 0, 0),
+            IVec3::new(3, 3, 3),
+            IVec3::new(4, 0, 0),
+            IVec3::new(100, 20, 7),
+        ];
+        for location in locations {
+            tree.set_value(location, Some(true));
+        }
+        assert_eq!(tree.pool[0].count(), 3);
+        assert_eq!(tree.pool[1].count(), 2);
+        for location in locations {
+            tree.set_value(location, None);
+        }
+        assert_eq!(tree.get_value(IVec3::new(0, 0, 0)), None);
+        assert_eq!(tree.pool[0].count(), 0);
+        assert_eq!(tree.pool[1].count(), 0);
+        assert!(tree.iter().next().is_none());
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut tree = Tree::<hierarchy!(2, 2)>::new();
+        tree.set_value(IVec3::new(0, 0, 0), Some(true));
+        tree.set_value(IVec3::new(8, 0, 0), Some(true));
+        for (_, leaf) in tree.iter_leaf_mut() {
+            leaf.occupancy.set(0, false);
+        }
+        assert_eq!(tree.pool[0].count(), 2);
+        tree.prune();
+        assert_eq!(tree.pool[0].count(), 0);
+    }
+
+    #[test]
+    fn test_fill_tiles() {
+        let mut tree = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+        tree.fill(Aabb::new(IVec3::ZERO, IVec3::new(20, 16, 16)), 1);
+        // The first 16x16x16 region becomes a root tile, and the 4x16x16 slab becomes
+        // tiles within a single internal node.
+        assert_eq!(tree.pool[0].count(), 0);
+        assert_eq!(tree.pool[1].count(), 1);
+        assert_eq!(tree.iter().count(), 20 * 16 * 16);
+
+        tree.set_value(IVec3::new(1, 2, 3), Some(2));
+        assert_eq!(tree.pool[0].count(), 1);
+        assert_eq!(tree.pool[1].count(), 2);
+        assert_eq!(tree.get_value(IVec3::new(1, 2, 3)), Some(2));
+        assert_eq!(tree.get_value(IVec3::new(15, 15, 15)), Some(1));
+
+        tree.set_value(IVec3::new(1, 2, 3), Some(1));
+        tree.prune();
+        assert_eq!(tree.pool[0].count(), 0);
+        assert_eq!(tree.pool[1].count(), 1);
+        assert_eq!(tree.get_value(IVec3::new(1, 2, 3)), Some(1));
+
+        let mut accessor = tree.accessor();
+        assert_eq!(accessor.get(IVec3::new(17, 0, 0)), Some(1));
+        assert_eq!(accessor.get(IVec3::new(19, 15, 15)), Some(1));
+        assert_eq!(accessor.get(IVec3::new(20, 15, 15)), None);
+        assert_eq!(accessor.get(IVec3::new(21, 15, 15)), None);
+    }
+
+    #[test]
+    fn test_clear_resets_active() {
+        use crate::IsLeaf;
+        let mut tree = Tree::<hierarchy!(#, 2, 2)>::new();
+        tree.fill(Aabb::new(IVec3::ZERO, IVec3::new(4, 4, 2)), true);
+        tree.set_value(IVec3::new(3, 3, 3), Some(true));
+        tree.update_active();
+        tree.set_value(IVec3::new(3, 3, 3), None);
+        tree.clear(Aabb::new(IVec3::ZERO, IVec3::new(1, 1, 1)));
+        let (_, leaf) = tree.iter_leaf().next().unwrap();
+        assert!(leaf.is_active(UVec3::new(1, 1, 1)));
+        assert!(!leaf.is_active(UVec3::new(3, 3, 3)));
+        assert!(!leaf.is_active(UVec3::new(0, 0, 0)));
+    }
+
+    #[test]
+    fn test_outside_extent() {
+        let mut tree = Tree::<hierarchy!(2, 2; u8)>::new();
+        tree.set_value(IVec3::new(-1, 0, 0), Some(1));
+        tree.set_value(IVec3::new(16, 0, 0), Some(1));
+        tree.set_value(IVec3::new(0, 0, 15), Some(2));
+        assert_eq!(tree.get_value(IVec3::new(-1, 0, 0)), None);
+        assert_eq!(tree.get_value(IVec3::new(16, 0, 0)), None);
+        assert_eq!(tree.get_value(IVec3::new(0, 0, -1)), None);
+        assert_eq!(tree.iter().collect::<Vec<_>>(), vec![IVec3::new(0, 0, 15)]);
+
+        let mut accessor = tree.accessor();
+        assert_eq!(accessor.get(IVec3::new(-3, 0, 0)), None);
+        assert_eq!(accessor.get(IVec3::new(0, 0, 15)), Some(2));
+        assert_eq!(accessor.get(IVec3::new(0, 0, 16)), None);
+
+        let mut accessor = tree.accessor_mut();
+        accessor.set(IVec3::new(-3, 0, 0), Some(3));
+        accessor.set(IVec3::new(0, 16, 0), Some(3));
+        assert_eq!(accessor.get(IVec3::new(-3, 0, 0)), None);
+        assert_eq!(accessor.get(IVec3::new(0, 0, 15)), Some(2));
+        assert_eq!(tree.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_negative_coords() {
+        let mut tree = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+        let locations = [
+            IVec3::new(-1, -1, -1),
+            IVec3::new(-16, 0, 15),
+            IVec3::new(-17, 3, -100),
+            IVec3::new(i32::MIN, i32::MAX, 0),
+        ];
+        for (i, location) in locations.iter().enumerate() {
+            tree.set_value(*location, Some(i as u8));
+        }
+        for (i, location) in locations.iter().enumerate() {
+            assert_eq!(tree.get_value(*location), Some(i as u8));
+        }
+        assert_eq!(tree.get_value(IVec3::new(0, 0, 0)), None);
+        assert_eq!(tree.get_value(IVec3::new(15, 15, 15)), None);
+        assert_eq!(tree.pool[0].count(), 4);
+
+        let mut found: Vec<IVec3> = tree.iter().collect();
+        found.sort_by_key(|v| v.to_array());
+        let mut expected = locations.to_vec();
+        expected.sort_by_key(|v| v.to_array());
+        assert_eq!(found, expected);
+
+        for (origin, _) in tree.iter_leaf() {
+            assert!(locations
+                .iter()
+                .any(|l| (*l - origin).cmpge(IVec3::ZERO).all()
+                    && (*l - origin).cmplt(IVec3::splat(4)).all()));
+        }
+
+        tree.fill(Aabb::new(IVec3::splat(-20), IVec3::splat(-2)), 9);
+        assert_eq!(tree.get_value(IVec3::splat(-20)), Some(9));
+        assert_eq!(tree.get_value(IVec3::splat(-3)), Some(9));
+        assert_eq!(tree.get_value(IVec3::splat(-2)), None);
+        assert_eq!(tree.get_value(IVec3::splat(-1)), Some(0));
+    }
+
+    #[test]
+    fn test_iter_in() {
+        let mut rng = rand::thread_rng();
+        let mut tree = Tree::<hierarchy!(#, 2, 2; u8)>::new();
+        let mut locations = Vec::new();
+        for _ in 0..500 {
+            let location = IVec3::new(
+                rng.gen_range(-50..50),
+                rng.gen_range(-50..50),
+                rng.gen_range(-50..50),
+            );
+            tree.set_value(location, Some(1));
+            locations.push(location);
+        }
+        locations.sort_by_key(|v| v.to_array());
+        locations.dedup();
+
+        let aabb = Aabb::new(IVec3::new(-13, -40, 5), IVec3::new(30, 2, 17));
+        let mut found: Vec<IVec3> = tree.iter_in(aabb).collect();
+        found.sort_by_key(|v| v.to_array());
+        let expected: Vec<IVec3> = locations
+            .iter()
+            .copied()
+            .filter(|l| aabb.contains(*l))
+            .collect();
+        assert_eq!(found, expected);
+        for (origin, _) in tree.iter_leaf_in(aabb) {
+            assert!(aabb.intersects(&Aabb::from_extent(origin, UVec3::splat(4))));
+        }
+        let num_leaves = tree.iter_leaf_in(aabb).count();
+        assert!(num_leaves < tree.iter_leaf().count());
+        assert!(expected.iter().all(|l| tree
+            .iter_leaf_in(aabb)
+            .any(|(origin, _)| (*l >> 2) == (origin >> 2))));
+
+        let sphere = Sphere::new(Vec3::new(3.0, -7.5, 10.0), 20.0);
+        let mut found: Vec<IVec3> = tree.iter_in(sphere).collect();
+        found.sort_by_key(|v| v.to_array());
+        let expected: Vec<IVec3> = locations
+            .iter()
+            .copied()
+            .filter(|l| sphere.intersects(&Aabb::new(*l, *l + IVec3::ONE)))
+            .collect();
+        assert_eq!(found, expected);
+    }
+}
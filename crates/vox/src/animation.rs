@@ -0,0 +1,96 @@
+use bevy_ecs::{
+    prelude::Component,
+    reflect::ReflectComponent,
+    system::{Query, Res, Resource},
+};
+use bevy_reflect::Reflect;
+use bevy_time::Time;
+use bevy_transform::prelude::Transform;
+use dust_render::Visibility;
+
+/// Playback speed of animations imported from MagicaVoxel files.
+#[derive(Resource)]
+pub struct VoxAnimationSettings {
+    pub frames_per_second: f32,
+}
+impl Default for VoxAnimationSettings {
+    fn default() -> Self {
+        Self {
+            frames_per_second: 10.0,
+        }
+    }
+}
+
+/// Per-frame transforms of an animated MagicaVoxel node.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct VoxAnimationClip {
+    /// Keyframes sorted by frame index. Each keyframe holds until the next one.
+    pub keyframes: Vec<(u32, Transform)>,
+    /// Length of the animation loop in frames, shared by all nodes in the file.
+    pub num_frames: u32,
+}
+
+/// Per-frame visibility of one model of a MagicaVoxel shape node with multiple models.
+///
+/// Each model of the shape node is spawned as an entity of its own, so that its BLAS
+/// is built once on load. At most one of them is visible on each frame, and none on
+/// frames showing an empty model.
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+pub struct VoxFlipbook {
+    /// Keyframes sorted by frame index, telling whether the model is visible.
+    /// Each keyframe holds until the next one.
+    pub keyframes: Vec<(u32, bool)>,
+    /// Length of the animation loop in frames, shared by all nodes in the file.
+    pub num_frames: u32,
+}
+
+/// Returns the index of the keyframe active at `frame`.
+/// Frames before the first keyframe use the first keyframe.
+pub(crate) fn keyframe_index(frames: impl Iterator<Item = u32>, frame: u32) -> usize {
+    frames
+        .take_while(|keyframe| *keyframe <= frame)
+        .count()
+        .saturating_sub(1)
+}
+
+fn current_frame(time: &Time, settings: &VoxAnimationSettings, num_frames: u32) -> u32 {
+    let frame = time.elapsed_seconds_f64() * settings.frames_per_second as f64;
+    (frame as u64 % num_frames.max(1) as u64) as u32
+}
+
+pub(crate) fn vox_animation_clip_system(
+    time: Res<Time>,
+    settings: Res<VoxAnimationSettings>,
+    mut query: Query<(&VoxAnimationClip, &mut Transform)>,
+) {
+    for (clip, mut transform) in query.iter_mut() {
+        let frame = current_frame(&time, &settings, clip.num_frames);
+        let index = keyframe_index(clip.keyframes.iter().map(|(f, _)| *f), frame);
+        let keyframe = &clip.keyframes[index].1;
+        // Only write on keyframe changes so that static frames don't trigger change detection.
+        if *transform != *keyframe {
+            *transform = *keyframe;
+        }
+    }
+}
+
+pub(crate) fn vox_flipbook_system(
+    time: Res<Time>,
+    settings: Res<VoxAnimationSettings>,
+    mut query: Query<(&VoxFlipbook, &mut Visibility)>,
+) {
+    for (flipbook, mut visibility) in query.iter_mut() {
+        let frame = current_frame(&time, &settings, flipbook.num_frames);
+        let index = keyframe_index(flipbook.keyframes.iter().map(|(f, _)| *f), frame);
+        let keyframe = if flipbook.keyframes[index].1 {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != keyframe {
+            *visibility = keyframe;
+        }
+    }
+}
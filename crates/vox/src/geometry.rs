@@ -5,7 +5,7 @@ use crate::{Tree, TreeRoot, VoxPalette};
 use bevy_asset::Asset;
 use dust_render::Geometry;
 use dust_vdb::{IsLeaf, Node};
-use glam::{UVec3, Vec3A, Vec4};
+use glam::{IVec3, Vec3A, Vec4};
 use rhyolite::ash::vk;
 use rhyolite::debug::DebugObject;
 use rhyolite::future::{GPUCommandFuture, GPUCommandFutureExt, UnitCommandFuture};
@@ -58,13 +58,14 @@ impl VoxGeometry {
         unit_size: f32,
         allocator: &Allocator,
         ring_buffer: &StagingRingBuffer,
-        palette_indexes: &[u8],
         palette: &VoxPalette,
     ) -> impl GPUCommandFuture<Output = Self> {
         let leaf_extent_int = <<TreeRoot as Node>::LeafType as Node>::EXTENT;
         let leaf_extent: Vec3A = leaf_extent_int.as_vec3a();
         let leaf_extent: Vec3A = unit_size * leaf_extent;
 
+        // Material values are laid out in the same order as `Self::material_data`.
+        let mut material_ptr: u32 = 0;
         let (aabbs, nodes): (Vec<vk::AabbPositionsKHR>, Vec<GPUVoxNode>) = tree
             .iter_leaf()
             .map(|(position, d)| {
@@ -85,8 +86,7 @@ impl VoxGeometry {
 
                 let mut color = glam::UVec4::ZERO;
                 let num_voxels = mask[0].count_ones();
-                for i in 0..num_voxels {
-                    let palette_index = palette_indexes[d.material_ptr as usize + i as usize];
+                for palette_index in d.iter_values() {
                     let albedo = palette.colors[palette_index as usize];
                     color += glam::UVec4::new(
                         albedo.r as u32,
@@ -119,10 +119,11 @@ impl VoxGeometry {
                         z: position.z as u16,
                         w: 0,
                         mask: mask[0],
-                        material_ptr: d.material_ptr,
+                        material_ptr,
                         avg_albedo: packed,
                     }
                 };
+                material_ptr += num_voxels;
                 (aabb, node)
             })
             .unzip();
@@ -177,10 +178,17 @@ impl VoxGeometry {
                 });
         future
     }
-    pub fn set(&mut self, coords: UVec3, value: Option<bool>) {
+    /// Palette indexes of all voxels in the tree, in the order expected by the
+    /// `material_ptr` of each `GPUVoxNode`.
+    pub fn material_data(tree: &Tree) -> Vec<u8> {
+        tree.iter_leaf()
+            .flat_map(|(_, leaf)| leaf.iter_values())
+            .collect()
+    }
+    pub fn set(&mut self, coords: IVec3, value: Option<u8>) {
         self.tree.set_value(coords, value)
     }
-    pub fn get(&mut self, coords: UVec3) -> Option<bool> {
+    pub fn get(&mut self, coords: IVec3) -> Option<u8> {
         self.tree.get_value(coords)
     }
 }
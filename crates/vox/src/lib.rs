@@ -1,7 +1,7 @@
 #![feature(generic_const_exprs)]
 #![feature(generators)]
 
-mod collector;
+mod animation;
 mod loader;
 mod palette;
 
@@ -9,14 +9,16 @@ use bevy_asset::{AssetApp, Handle};
 mod geometry;
 mod material;
 
+pub use animation::{VoxAnimationClip, VoxAnimationSettings, VoxFlipbook};
 use dust_render::{GeometryPlugin, MaterialPlugin, Renderable};
 use dust_vdb::hierarchy;
 pub use geometry::VoxGeometry;
 pub use loader::*;
 pub use material::PaletteMaterial;
-pub use palette::VoxPalette;
+pub use palette::{VoxMaterial, VoxPalette};
 
-pub type TreeRoot = hierarchy!(4, 2, 2);
+/// Each voxel stores its index into the palette.
+pub type TreeRoot = hierarchy!(4, 2, 2; u8);
 pub type Tree = dust_vdb::Tree<TreeRoot>;
 
 #[derive(Default)]
@@ -28,6 +30,16 @@ impl bevy_app::Plugin for VoxPlugin {
             .init_asset::<VoxGeometry>()
             .init_asset::<PaletteMaterial>()
             .init_asset::<PaletteMaterial>()
+            .register_type::<VoxAnimationClip>()
+            .register_type::<VoxFlipbook>()
+            .init_resource::<VoxAnimationSettings>()
+            .add_systems(
+                bevy_app::Update,
+                (
+                    animation::vox_animation_clip_system,
+                    animation::vox_flipbook_system,
+                ),
+            )
             .add_plugins(GeometryPlugin::<VoxGeometry>::default())
             .add_plugins(MaterialPlugin::<PaletteMaterial>::default());
     }
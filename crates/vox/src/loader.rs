@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use crate::{palette::VoxPalette, VoxGeometry};
 /// MagicaVoxel trees are 256x256x256 max, so the numbers in the
@@ -27,7 +27,11 @@ use rhyolite::{
 use rhyolite::{fill_buffer, HasDevice};
 use rhyolite_bevy::{AsyncQueues, QueuesRouter, StagingRingBuffer};
 
+use crate::animation::keyframe_index;
 use crate::material::PaletteMaterial;
+use crate::palette::VoxMaterial;
+use crate::{VoxAnimationClip, VoxFlipbook};
+use dust_render::Visibility;
 
 pub struct VoxLoader {
     allocator: rhyolite_bevy::Allocator,
@@ -49,14 +53,70 @@ impl FromWorld for VoxLoader {
     }
 }
 
+/// Translation and rotation of a node, accumulated along the path from the scene root.
+type Pose = (IVec3, Rotation);
+
 struct SceneGraphTraverser<'a> {
     unit_size: f32,
     scene: &'a DotVoxData,
     models: HashSet<u32>,
     instances: Vec<(u32, Entity)>,
+    /// Length of the animation loop in frames.
+    num_frames: u32,
+}
+
+/// Returns the animation frame index of a transform frame or shape model.
+fn frame_index(attributes: &HashMap<String, String>) -> u32 {
+    attributes
+        .get("_f")
+        .and_then(|frame| frame.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Returns the value of `keyframes` at `frame`. `keyframes` must be sorted by frame index.
+fn sample<T>(keyframes: &[(u32, T)], frame: u32) -> &T {
+    &keyframes[keyframe_index(keyframes.iter().map(|(f, _)| *f), frame)].1
+}
+
+/// Returns the sorted union of the frame indices in `a` and `b`.
+fn merge_frames<A, B>(a: &[(u32, A)], b: &[(u32, B)]) -> Vec<u32> {
+    let mut frames: Vec<u32> = a
+        .iter()
+        .map(|(f, _)| *f)
+        .chain(b.iter().map(|(f, _)| *f))
+        .collect();
+    frames.sort_unstable();
+    frames.dedup();
+    frames
 }
 
 impl<'a> SceneGraphTraverser<'a> {
+    fn new(scene: &'a DotVoxData) -> Self {
+        let num_frames = scene
+            .scenes
+            .iter()
+            .flat_map(|node| match node {
+                SceneNode::Transform { frames, .. } => frames
+                    .iter()
+                    .map(|frame| frame_index(&frame.attributes))
+                    .max(),
+                SceneNode::Shape { models, .. } => models
+                    .iter()
+                    .map(|model| frame_index(&model.attributes))
+                    .max(),
+                SceneNode::Group { .. } => None,
+            })
+            .max()
+            .unwrap_or(0)
+            + 1;
+        Self {
+            unit_size: 1.0,
+            scene,
+            models: HashSet::new(),
+            instances: Vec::new(),
+            num_frames,
+        }
+    }
     fn traverse(
         &mut self,
         node: u32,
@@ -82,14 +142,14 @@ impl<'a> SceneGraphTraverser<'a> {
             self.models.insert(0);
             return;
         }
-        self.traverse_recursive(node, parent, translation, rotation, name);
+        self.traverse_recursive(node, parent, &[(0, (translation, rotation))], name);
     }
+    /// `poses` are the keyframes of the accumulated pose, sorted by frame index.
     fn traverse_recursive(
         &mut self,
         node: u32,
         parent: WorldOrParent<'_, '_>,
-        translation: glam::IVec3,
-        rotation: Rotation,
+        poses: &[(u32, Pose)],
         _name: Option<&str>,
     ) {
         let node = &self.scene.scenes[node as usize];
@@ -100,81 +160,176 @@ impl<'a> SceneGraphTraverser<'a> {
                 child,
                 layer_id: _,
             } => {
-                if frames.len() != 1 {
-                    unimplemented!("Multiple frame in transform node");
-                }
                 let name = attributes.get("_name").map(String::as_str);
-                let frame = &frames[0];
-                let this_translation = frame
-                    .position()
-                    .map(|position| IVec3 {
-                        x: position.x,
-                        y: position.y,
-                        z: position.z,
+                let mut this_poses: Vec<(u32, Pose)> = frames
+                    .iter()
+                    .map(|frame| {
+                        let this_translation = frame
+                            .position()
+                            .map(|position| IVec3 {
+                                x: position.x,
+                                y: position.y,
+                                z: position.z,
+                            })
+                            .unwrap_or(IVec3::ZERO);
+                        let this_rotation = frame.orientation().unwrap_or(Rotation::IDENTITY);
+                        (
+                            frame_index(&frame.attributes),
+                            (this_translation, this_rotation),
+                        )
                     })
-                    .unwrap_or(IVec3::ZERO);
+                    .collect();
+                if this_poses.is_empty() {
+                    this_poses.push((0, (IVec3::ZERO, Rotation::IDENTITY)));
+                }
+                this_poses.sort_by_key(|(f, _)| *f);
 
-                let this_rotation = frame.orientation().unwrap_or(Rotation::IDENTITY);
-                //let rotation = rotation * this_rotation; // reverse?
-                let translation = translation + this_translation;
+                let poses: Vec<(u32, Pose)> = merge_frames(poses, &this_poses)
+                    .into_iter()
+                    .map(|frame| {
+                        let (translation, _) = sample(poses, frame);
+                        let (this_translation, this_rotation) = sample(&this_poses, frame);
+                        //let rotation = rotation * this_rotation; // reverse?
+                        (frame, (*translation + *this_translation, *this_rotation))
+                    })
+                    .collect();
 
-                self.traverse_recursive(*child, parent, translation, this_rotation, name);
+                self.traverse_recursive(*child, parent, &poses, name);
             }
             SceneNode::Group {
                 attributes: _,
                 children,
             } => {
-                parent
-                    .spawn((
-                        self.to_transform(translation, rotation, UVec3::ZERO),
-                        GlobalTransform::default(),
-                    ))
-                    .with_children(|builder| {
-                        for &i in children {
-                            self.traverse_recursive(
-                                i,
-                                WorldOrParent::Parent(builder),
-                                glam::IVec3::ZERO,
-                                Rotation::IDENTITY,
-                                None,
-                            );
-                        }
+                let mut transforms: Vec<(u32, Transform)> = poses
+                    .iter()
+                    .map(|(frame, (translation, rotation))| {
+                        (
+                            *frame,
+                            self.to_transform(*translation, *rotation, UVec3::ZERO),
+                        )
+                    })
+                    .collect();
+                transforms.dedup_by(|b, a| a.1 == b.1);
+                let mut entity = parent.spawn((transforms[0].1, GlobalTransform::default()));
+                if transforms.len() > 1 {
+                    entity.insert(VoxAnimationClip {
+                        keyframes: transforms,
+                        num_frames: self.num_frames,
                     });
+                }
+                entity.with_children(|builder| {
+                    for &i in children {
+                        self.traverse_recursive(
+                            i,
+                            WorldOrParent::Parent(builder),
+                            &[(0, (glam::IVec3::ZERO, Rotation::IDENTITY))],
+                            None,
+                        );
+                    }
+                });
             }
             SceneNode::Shape {
                 attributes: _,
                 models,
             } => {
-                // Shape nodes are leafs and correspond to models
-                if models.len() != 1 {
-                    unimplemented!("Multiple shape models in Shape node");
+                // Shape nodes are leafs and correspond to models.
+                // Empty models hide the shape on their frames.
+                let mut shape_models: Vec<(u32, Option<u32>)> = models
+                    .iter()
+                    .map(|shape_model| {
+                        let model_id = shape_model.model_id;
+                        let is_empty = self.scene.models[model_id as usize].voxels.is_empty();
+                        (
+                            frame_index(&shape_model.attributes),
+                            (!is_empty).then_some(model_id),
+                        )
+                    })
+                    .collect();
+                shape_models.sort_by_key(|(f, _)| *f);
+                shape_models.dedup_by(|b, a| a.1 == b.1);
+                let mut model_ids: Vec<u32> = shape_models
+                    .iter()
+                    .filter_map(|(_, model_id)| *model_id)
+                    .collect();
+                model_ids.sort_unstable();
+                model_ids.dedup();
+                if model_ids.is_empty() {
+                    return;
                 }
-                let shape_model = &models[0];
-                let model = &self.scene.models[shape_model.model_id as usize];
-                if model.voxels.len() == 0 {
+                if shape_models.len() == 1 {
+                    self.spawn_model(parent, poses, model_ids[0]);
                     return;
                 }
-                let size = self.scene.models[shape_model.model_id as usize].size;
-                let entity = parent
-                    .spawn(VoxBundle {
-                        transform: self.to_transform(
-                            translation,
-                            rotation,
-                            UVec3 {
-                                x: size.x,
-                                y: size.y,
-                                z: size.z,
-                            },
-                        ),
-                        ..VoxBundle::from_geometry_material(Handle::default(), Handle::default())
-                    })
-                    .id();
-                self.instances.push((shape_model.model_id, entity));
-                self.models.insert(shape_model.model_id);
+
+                // Each model gets an entity of its own, shown only on its frames.
+                // Swapping the geometry of a single entity instead would rebuild its BLAS
+                // on every frame.
+                parent
+                    .spawn((Transform::default(), GlobalTransform::default()))
+                    .with_children(|builder| {
+                        for model_id in model_ids {
+                            let mut keyframes: Vec<(u32, bool)> = shape_models
+                                .iter()
+                                .map(|(frame, id)| (*frame, *id == Some(model_id)))
+                                .collect();
+                            keyframes.dedup_by(|b, a| a.1 == b.1);
+                            let visibility = if keyframes[0].1 {
+                                Visibility::Visible
+                            } else {
+                                Visibility::Hidden
+                            };
+                            let num_frames = self.num_frames;
+                            self.spawn_model(WorldOrParent::Parent(builder), poses, model_id)
+                                .insert((
+                                    VoxFlipbook {
+                                        keyframes,
+                                        num_frames,
+                                    },
+                                    visibility,
+                                ));
+                        }
+                    });
             }
         }
     }
 
+    /// Spawn an entity showing the model `model_id` at `poses`.
+    /// The geometry and material handles are filled in once the model is loaded.
+    fn spawn_model<'w>(
+        &mut self,
+        parent: WorldOrParent<'w, '_>,
+        poses: &[(u32, Pose)],
+        model_id: u32,
+    ) -> EntityWorldMut<'w> {
+        let size = self.scene.models[model_id as usize].size;
+        let size = UVec3 {
+            x: size.x,
+            y: size.y,
+            z: size.z,
+        };
+        let mut transforms: Vec<(u32, Transform)> = poses
+            .iter()
+            .map(|(frame, (translation, rotation))| {
+                (*frame, self.to_transform(*translation, *rotation, size))
+            })
+            .collect();
+        transforms.dedup_by(|b, a| a.1 == b.1);
+
+        let mut entity = parent.spawn(VoxBundle {
+            transform: transforms[0].1,
+            ..VoxBundle::from_geometry_material(Handle::default(), Handle::default())
+        });
+        if transforms.len() > 1 {
+            entity.insert(VoxAnimationClip {
+                keyframes: transforms,
+                num_frames: self.num_frames,
+            });
+        }
+        self.instances.push((model_id, entity.id()));
+        self.models.insert(model_id);
+        entity
+    }
+
     fn to_transform(
         &self,
         translation: glam::IVec3,
@@ -208,8 +363,32 @@ impl VoxLoader {
     fn load_palette(
         &self,
         palette: &[dot_vox::Color],
+        materials: &[dot_vox::Material],
         ring_buffer: &StagingRingBuffer,
     ) -> impl GPUCommandFuture<Output = RenderRes<VoxPalette>> {
+        let mut vox_materials = Box::new([VoxMaterial::default(); 255]);
+        for material in materials.iter() {
+            // MATL ids count from 1 like the palette indexes in the file.
+            let Some(index) = (material.id as usize).checked_sub(1) else {
+                continue;
+            };
+            if let Some(vox_material) = vox_materials.get_mut(index) {
+                *vox_material = VoxMaterial::from_dot_vox(material);
+            }
+        }
+        let material_buffer = unsafe {
+            self.allocator
+                .create_static_device_buffer_with_data(
+                    std::slice::from_raw_parts(
+                        vox_materials.as_ptr() as *const u8,
+                        std::mem::size_of_val(vox_materials.as_slice()),
+                    ),
+                    vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+                    4,
+                    &ring_buffer,
+                )
+                .unwrap()
+        };
         unsafe {
             const LEN: usize = 255;
             let mem =
@@ -226,12 +405,16 @@ impl VoxLoader {
                     &ring_buffer,
                 )
                 .unwrap();
-            resident_buffer.map(|buffer| {
-                buffer.map(|buffer| VoxPalette {
-                    colors: mem,
-                    buffer,
+            resident_buffer
+                .join(material_buffer)
+                .map(|(buffer, material_buffer)| {
+                    buffer.map(|buffer| VoxPalette {
+                        colors: mem,
+                        buffer,
+                        materials: vox_materials,
+                        material_buffer: material_buffer.into_inner(),
+                    })
                 })
-            })
         }
     }
 
@@ -241,8 +424,6 @@ impl VoxLoader {
         palette: &VoxPalette,
         ring_buffer: &StagingRingBuffer,
     ) -> impl GPUCommandFuture<Output = (VoxGeometry, PaletteMaterial)> + Send {
-        let mut palette_index_collector = crate::collector::ModelIndexCollector::new();
-
         let mut tree = Tree::new();
         for voxel in model.voxels.iter() {
             let voxel = dot_vox::Voxel {
@@ -251,27 +432,15 @@ impl VoxLoader {
                 z: (model.size.y - voxel.y as u32 - 1) as u8,
                 i: voxel.i,
             };
-            let coords: UVec3 = UVec3 {
-                x: voxel.x as u32,
-                y: voxel.y as u32,
-                z: voxel.z as u32,
+            let coords: IVec3 = IVec3 {
+                x: voxel.x as i32,
+                y: voxel.y as i32,
+                z: voxel.z as i32,
             };
-            tree.set_value(coords, Some(true));
-            palette_index_collector.set(voxel);
-        }
-
-        let palette_indexes = palette_index_collector.into_iter();
-        // TODO: use iter_leaf_mut here, and insert indices
-        for (location, leaf) in tree.iter_leaf_mut() {
-            let block_index = (location.x >> 2, location.y >> 2, location.z >> 2);
-            let block_index = block_index.0 as usize
-                + block_index.1 as usize * 64
-                + block_index.2 as usize * 64 * 64;
-
-            leaf.material_ptr = palette_indexes.running_sum()[block_index];
+            tree.set_value(coords, Some(voxel.i));
         }
 
-        let palette_indexes: Vec<u8> = palette_indexes.collect();
+        let palette_indexes: Vec<u8> = VoxGeometry::material_data(&tree);
         let material_buffer = self
             .allocator
             .create_static_device_buffer_with_data(
@@ -293,7 +462,6 @@ impl VoxLoader {
             1.0,
             &self.allocator,
             ring_buffer,
-            &palette_indexes,
             palette,
         );
 
@@ -333,7 +501,7 @@ impl AssetLoader for VoxLoader {
 
             let staging_ring_buffer = StagingRingBuffer::new(self.allocator.device()).unwrap();
             let palette = self
-                .load_palette(&file.palette, &staging_ring_buffer)
+                .load_palette(&file.palette, &file.materials, &staging_ring_buffer)
                 .schedule_on_queue(self.transfer_queue);
 
             let palette = self
@@ -343,12 +511,7 @@ impl AssetLoader for VoxLoader {
                 .into_inner();
 
             let mut world = World::default();
-            let mut traverser = SceneGraphTraverser {
-                unit_size: 1.0,
-                scene: &file,
-                models: HashSet::new(),
-                instances: Vec::new(),
-            };
+            let mut traverser = SceneGraphTraverser::new(&file);
             traverser.traverse(
                 0,
                 WorldOrParent::World(&mut world),
@@ -38,6 +38,9 @@ pub struct PaletteMaterialShaderParams {
 
     /// Pointer to a list of 256 u8 colors
     palette_ptr: u64,
+
+    /// Pointer to a list of `VoxMaterial`, indexed by palette index.
+    palette_material_ptr: u64,
 }
 
 impl dust_render::Material for PaletteMaterial {
@@ -115,6 +118,7 @@ impl dust_render::Material for PaletteMaterial {
             geometry_ptr: geometry.geometry_buffer().device_address(),
             material_ptr: self.data.device_address(),
             palette_ptr: palette.buffer.device_address(),
+            palette_material_ptr: palette.material_buffer.device_address(),
         }
     }
 }
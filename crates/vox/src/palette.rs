@@ -5,5 +5,80 @@ use rhyolite::{future::RenderData, ResidentBuffer};
 pub struct VoxPalette {
     pub colors: Box<[dot_vox::Color; 255]>,
     pub buffer: ResidentBuffer,
+    /// Material properties of each palette entry, imported from MATL chunks.
+    pub materials: Box<[VoxMaterial; 255]>,
+    /// Array of `VoxMaterial`, indexed by palette index.
+    pub material_buffer: ResidentBuffer,
 }
 impl RenderData for VoxPalette {}
+
+/// Material properties of a palette entry. Matches `PaletteMaterialProperties` in the shaders.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VoxMaterial {
+    /// Emitted radiance relative to the albedo.
+    pub emission: f32,
+    pub roughness: f32,
+    pub metalness: f32,
+    /// Fraction of light passing through glass.
+    pub transmission: f32,
+    /// Index of refraction.
+    pub ior: f32,
+}
+
+impl Default for VoxMaterial {
+    fn default() -> Self {
+        Self {
+            emission: 0.0,
+            roughness: 1.0,
+            metalness: 0.0,
+            transmission: 0.0,
+            ior: 1.0,
+        }
+    }
+}
+
+impl VoxMaterial {
+    /// Convert the properties of a MATL chunk. Properties that don't apply to the
+    /// material type are ignored, because MagicaVoxel keeps them around
+    /// when switching material types in the editor.
+    pub fn from_dot_vox(material: &dot_vox::Material) -> Self {
+        let get = |key: &str| -> Option<f32> {
+            material
+                .properties
+                .get(key)
+                .and_then(|value| value.parse().ok())
+        };
+        let material_type = material
+            .properties
+            .get("_type")
+            .map(String::as_str)
+            .unwrap_or("_diffuse");
+        let (metal, glass, emit) = match material_type {
+            "_metal" => (true, false, false),
+            "_glass" => (false, true, false),
+            "_emit" => (false, false, true),
+            // `_blend` mixes the other types by weights we don't model, so it shades as diffuse.
+            _ => (false, false, false),
+        };
+
+        let mut result = Self::default();
+        if metal || glass {
+            result.roughness = get("_rough").unwrap_or(result.roughness);
+        }
+        if metal {
+            result.metalness = get("_metal").unwrap_or(0.0);
+        }
+        if glass {
+            result.transmission = get("_trans").unwrap_or(1.0);
+            // MagicaVoxel stores the index of refraction minus one.
+            result.ior = 1.0 + get("_ior").unwrap_or(0.3);
+        }
+        if emit {
+            // `_flux` is the power setting in the editor, scaling the emission exponentially.
+            result.emission =
+                get("_emit").unwrap_or(0.0) * 2.0_f32.powf(get("_flux").unwrap_or(0.0));
+        }
+        result
+    }
+}